@@ -0,0 +1,88 @@
+//! Benchmark of patterns led by a literal, such as "ERROR:" or "#include", over a made-up C++
+//! source tree, with and without the literal prefilter.
+//!
+//! Run with `cargo bench --bench literal`.
+use std::time::{Duration, Instant};
+
+use rare::{RareBuilder, RARE};
+
+/// The number of lines of the made-up source tree.
+const LINE_COUNT: usize = 200_000;
+
+/// Builds lines that look like C++ sources and build logs, with a few of them holding what the
+/// patterns look for.
+fn source_lines() -> Vec<String> {
+    (0..LINE_COUNT)
+        .map(|idx| match idx % 1000 {
+            0 => format!("ERROR: undefined reference to symbol_{idx}"),
+            1 => format!("#include <clang/ast/decl_{}.h>", idx % 37),
+            2..=4 => format!("compiling lib/sema/sema_{idx}.cpp"),
+            _ => format!(
+                "    auto value_{idx} = context.lookup(decl_{}, /*recurse=*/true); // see note {}",
+                idx % 97,
+                idx % 13
+            ),
+        })
+        .collect()
+}
+
+/// Runs the function passed in a few times, and returns the fastest run.
+///
+/// * `f`:
+fn time(mut f: impl FnMut()) -> Duration {
+    (0..3)
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+/// Matches every line the way the CLI does: `is_match` first, then `write_match_all` on the lines
+/// that match.
+///
+/// * `rare`:
+/// * `lines`:
+/// * Return: the number of matches.
+fn grep(rare: &RARE, lines: &[String]) -> usize {
+    let mut cache = rare.create_cache();
+    let mut spans = Vec::new();
+    for line in lines {
+        if rare.is_match_with(&mut cache, line) {
+            rare.write_match_all_with(&mut cache, line, &mut spans);
+        }
+    }
+    spans.len()
+}
+
+fn main() {
+    let lines = source_lines();
+    let bytes: usize = lines.iter().map(|line| line.len() + 1).sum();
+    println!("{LINE_COUNT} lines, {bytes} bytes");
+
+    // a keyword list, as built from a file of symbols.
+    let keywords = (0..3000)
+        .map(|idx| format!("symbol_{}", idx * 500))
+        .collect::<Vec<_>>()
+        .join("|");
+    for pattern in [
+        "ERROR: [a-z ]+",
+        "#include <[a-z0-9_/]+\\.h>",
+        "[a-z0-9_]+\\.cpp",
+        "decl_[0-9]+, ",
+        &keywords,
+    ] {
+        let filtered = RARE::new(pattern).unwrap();
+        let unfiltered = RareBuilder::new(pattern).prefilter(false).build().unwrap();
+        let mut count = 0;
+        let with = time(|| count = grep(&filtered, &lines));
+        let without = time(|| assert_eq!(grep(&unfiltered, &lines), count));
+        let name = match pattern.len() > 28 {
+            true => "symbol_0|...|symbol_1499500",
+            false => pattern,
+        };
+        println!("{name:<28} prefilter {with:>10.2?}, without {without:>10.2?} ({count} matches)");
+    }
+}
@@ -0,0 +1,56 @@
+//! Benchmark of patterns with lots of empty transitions, like "a?a?...a?aa...a" from
+//! `matcher_stress_test`, where every step walks many empty edges, and of `match_all` with matches
+//! that could go on until the end of the string.
+//!
+//! Run with `cargo bench --bench pathological`.
+use std::time::{Duration, Instant};
+
+use rare::RARE;
+
+/// Runs the function passed in a few times, and returns the fastest run.
+///
+/// * `f`:
+fn time(mut f: impl FnMut()) -> Duration {
+    (0..5)
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    for n in [10, 20, 30] {
+        let pattern = format!("{}{}", "a?".repeat(n), "a".repeat(n));
+        let regex = RARE::new(&pattern).unwrap();
+        // "a" n times matches, by taking none of the optional ones.
+        let line = "a".repeat(n);
+        // the pattern almost matches at every position, then fails.
+        let miss = format!("{}b", "a".repeat(n - 1)).repeat(1000);
+
+        let is_match = time(|| assert!(regex.is_match(&line)));
+        let is_miss = time(|| assert!(!regex.is_match(&miss)));
+        let mut count = 0;
+        let match_all = time(|| count = regex.match_all(&line).map_or(0, |spans| spans.len()));
+        println!(
+            "(a?)x{n}(a)x{n}: is_match {:>10.2?}, miss over {} bytes {:>10.2?}, \
+             match_all {:>10.2?}",
+            is_match,
+            miss.len(),
+            is_miss,
+            match_all
+        );
+    }
+
+    // every a matches, but could be the start of a longer match, up to a b that never comes.
+    let regex = RARE::new("a|a[^x]*b").unwrap();
+    for n in [10_000, 100_000, 1_000_000] {
+        let line = "a".repeat(n);
+        let mut count = 0;
+        let match_all = time(|| count = regex.match_all(&line).map_or(0, |spans| spans.len()));
+        assert_eq!(count, n);
+        println!("a|a[^x]*b over {n:>7} a: match_all {match_all:>10.2?}");
+    }
+}
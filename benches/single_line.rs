@@ -0,0 +1,82 @@
+//! Regression benchmark: matching one very long line, such as a multi-megabyte JSON log, should
+//! take time linear in its length.
+//!
+//! Run with `cargo bench --bench single_line`.
+use std::time::{Duration, Instant};
+
+use rare::{RareBuilder, RARE};
+
+/// The size of the largest line, in bytes.
+const MAX_LEN: usize = 10 * 1024 * 1024;
+
+/// Builds a single line of JSON records, at least `len` bytes long, with no line breaks.
+///
+/// * `len`:
+fn json_line(len: usize) -> String {
+    let mut line = String::with_capacity(len + 64);
+    line.push('[');
+    let mut id = 0;
+    while line.len() < len {
+        line.push_str(&format!(
+            "{{\"id\":{id},\"user\":\"üser{}\",\"ok\":true}},",
+            id % 97
+        ));
+        id += 1;
+    }
+    line.push(']');
+    line
+}
+
+/// Runs the function passed in a few times, and returns the fastest run.
+///
+/// * `f`:
+fn time(mut f: impl FnMut()) -> Duration {
+    (0..3)
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    let line = json_line(MAX_LEN);
+    // never matches, so the whole line is read.
+    let missing = RARE::new("\"id\":-[0-9]+").unwrap();
+    let ids = RARE::new("\"id\":[0-9]+").unwrap();
+    let missing_dfa = RareBuilder::new("\"id\":-[0-9]+")
+        .full_dfa(true)
+        .build()
+        .unwrap();
+
+    let mut ns_per_byte = Vec::new();
+    for len in [MAX_LEN / 8, MAX_LEN / 4, MAX_LEN / 2, MAX_LEN] {
+        // cut on a record boundary, so the line stays valid UTF-8.
+        let end = line[..len].rfind(',').unwrap();
+        let line = &line[..end];
+
+        let is_match = time(|| assert!(!missing.is_match(line)));
+        let full_dfa = time(|| assert!(!missing_dfa.is_match(line)));
+        let mut count = 0;
+        let match_all = time(|| count = ids.match_all(line).map_or(0, |spans| spans.len()));
+        println!(
+            "{:>9} bytes: is_match {:>10.2?}, full DFA {:>10.2?}, match_all {:>10.2?} \
+             ({count} matches)",
+            line.len(),
+            is_match,
+            full_dfa,
+            match_all
+        );
+        ns_per_byte.push((is_match + match_all).as_nanos() as f64 / line.len() as f64);
+    }
+
+    // linear means the time per byte stays about the same as the line grows.
+    let (first, last) = (ns_per_byte[0], *ns_per_byte.last().unwrap());
+    println!("time per byte: {first:.2} ns at 1/8 of the size, {last:.2} ns at full size");
+    assert!(
+        last < first * 3.0,
+        "matching a single line doesn't scale linearly anymore"
+    );
+}
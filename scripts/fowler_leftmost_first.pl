@@ -0,0 +1,64 @@
+#!/usr/bin/env perl
+# Generates the leftmost-first expectations of the AT&T test corpus under testdata/fowler, by
+# running each test with Perl, whose regular expressions are leftmost-first: for each foo.dat,
+# writes foo.leftmost_first.dat, with the same tests and the matches Perl finds.
+#
+# Usage: perl scripts/fowler_leftmost_first.pl
+use strict;
+use warnings;
+use File::Basename qw(dirname);
+
+my $dir = dirname(__FILE__) . "/../testdata/fowler";
+
+# Expands the escapes of a field whose test has the $ flag, as the corpus specifies.
+sub unescape {
+    my ($field) = @_;
+    my %simple = (n => "\n", t => "\t", r => "\r", f => "\f", v => "\x0B", a => "\x07", e => "\e", '\\' => '\\');
+    $field =~ s/\\x([0-9a-fA-F]{2})|\\(.)/defined $1 ? chr(hex $1) : ($simple{$2} \/\/ "\\$2")/ge;
+    return $field;
+}
+
+# Returns the match of the test as the corpus writes it: the start and end of each group, with
+# the unset groups after the last set one left out.
+sub run {
+    my ($flags, $pattern, $string) = @_;
+    # some tests repeat empty matches on purpose.
+    no warnings "regexp";
+    my $re = eval { $flags =~ /i/ ? qr/$pattern/i : qr/$pattern/ };
+    return undef unless defined $re;
+    return "NOMATCH" unless $string =~ $re;
+    my @groups = map { defined $-[$_] ? "($-[$_],$+[$_])" : "(?,?)" } 0 .. $#-;
+    push @groups, "(?,?)" while @groups < @+;
+    pop @groups while @groups > 1 && $groups[-1] eq "(?,?)";
+    return join "", @groups;
+}
+
+opendir(my $dh, $dir) or die "can't read $dir: $!";
+for my $name (sort grep { /\.dat$/ && !/\.leftmost_first\.dat$/ } readdir $dh) {
+    (my $out_name = $name) =~ s/\.dat$/.leftmost_first.dat/;
+    open(my $in, "<", "$dir/$name") or die "can't read $name: $!";
+    open(my $out, ">", "$dir/$out_name") or die "can't write $out_name: $!";
+    print $out "NOTE\tDO NOT EDIT: generated by scripts/fowler_leftmost_first.pl from $name with Perl $^V.\n";
+    # a pattern written SAME is the one of the test before.
+    my $last_pattern;
+    while (my $line = <$in>) {
+        chomp $line;
+        my ($flags, $pattern, $string, $expected) = grep { length } split /\t/, $line;
+        # notes, blank lines, tests commented out and tests of other syntaxes are copied as they
+        # are.
+        if (!defined $expected || $flags =~ /^#/ || $flags !~ /E/) {
+            print $out "$line\n";
+            next;
+        }
+        my ($re_pattern, $re_string) = ($pattern, $string eq "NULL" ? "" : $string);
+        if ($flags =~ /\$/) {
+            $re_pattern = unescape($re_pattern);
+            $re_string = unescape($re_string);
+        }
+        $re_pattern = $last_pattern if $pattern eq "SAME";
+        $last_pattern = $re_pattern;
+        # a pattern Perl rejects keeps the error the corpus expects.
+        my $found = run($flags, $re_pattern, $re_string) // $expected;
+        print $out "$flags\t$pattern\t$string\t$found\n";
+    }
+}
@@ -0,0 +1,186 @@
+#!/usr/bin/env perl
+# Generates the Unicode tables under src/lexer/unicode_tables from the Unicode Character Database
+# that ships with Perl.
+#
+# Usage: perl scripts/generate_unicode_tables.pl
+use strict;
+use warnings;
+use File::Basename qw(dirname);
+use Unicode::UCD qw(all_casefolds prop_aliases prop_invlist prop_value_aliases prop_values);
+
+my $out_dir = dirname(__FILE__) . "/../src/lexer/unicode_tables";
+my $version = Unicode::UCD::UnicodeVersion();
+
+sub rust_char {
+    my ($cp) = @_;
+    return sprintf("'\\u{%X}'", $cp);
+}
+
+sub header {
+    my ($fh) = @_;
+    print $fh "// DO NOT EDIT: generated by scripts/generate_unicode_tables.pl from Unicode $version.\n\n";
+}
+
+# Simple case folding (statuses C and S of CaseFolding.txt). Characters that fold to the same
+# character are case-insensitively equal, so each one is listed with all the others of its set.
+sub case_folding {
+    my $folds = all_casefolds();
+    my %sets;
+    for my $cp (keys %$folds) {
+        my $fold = $folds->{$cp};
+        next unless $fold->{status} eq 'C' || $fold->{status} eq 'S';
+        my $target = hex($fold->{simple});
+        $sets{$target}{$target} = 1;
+        $sets{$target}{$cp} = 1;
+    }
+    my %equivalents;
+    for my $set (values %sets) {
+        my @members = sort { $a <=> $b } keys %$set;
+        for my $cp (@members) {
+            $equivalents{$cp} = [grep { $_ != $cp } @members];
+        }
+    }
+
+    open(my $fh, '>', "$out_dir/case_folding.rs") or die "can't write case_folding.rs: $!";
+    header($fh);
+    print $fh "/// Each character that has other cases, with those other cases, sorted by character.\n";
+    print $fh "pub(crate) const CASE_FOLDING_SIMPLE: &[(char, &[char])] = &[\n";
+    for my $cp (sort { $a <=> $b } keys %equivalents) {
+        my $others = join(", ", map { rust_char($_) } @{ $equivalents{$cp} });
+        print $fh "    (" . rust_char($cp) . ", &[$others]),\n";
+    }
+    print $fh "];\n";
+    close($fh);
+}
+
+# Returns the inclusive ranges of the characters with the property passed in, sorted. The
+# surrogates aren't characters in Rust, so they're left out of the ranges.
+sub ranges {
+    my ($property) = @_;
+    my @invlist = prop_invlist($property);
+    die "unknown property $property" unless @invlist;
+    my @ranges;
+    for (my $i = 0; $i < @invlist; $i += 2) {
+        my ($lo, $hi) = ($invlist[$i], $i + 1 < @invlist ? $invlist[$i + 1] - 1 : 0x10FFFF);
+        $lo = 0xE000 if $lo >= 0xD800 && $lo <= 0xDFFF;
+        $hi = 0xD7FF if $hi >= 0xD800 && $hi <= 0xDFFF;
+        push @ranges, [$lo, $hi] if $lo <= $hi;
+    }
+    return @ranges;
+}
+
+# Writes a table of ranges, as a Rust constant.
+sub print_ranges {
+    my ($fh, $doc, $name, @ranges) = @_;
+    print $fh "/// $doc\n";
+    print $fh "pub(crate) const $name: &[(char, char)] = &[\n";
+    for my $range (@ranges) {
+        print $fh "    (" . rust_char($range->[0]) . ", " . rust_char($range->[1]) . "),\n";
+    }
+    print $fh "];\n";
+}
+
+# The classes of the \d, \s and \w escapes, with the same definitions as Perl and UTS #18:
+# decimal numbers, white space, and alphabetic characters, marks, decimal numbers, connector
+# punctuation and joiners.
+sub perl_classes {
+    open(my $fh, '>', "$out_dir/perl_classes.rs") or die "can't write perl_classes.rs: $!";
+    header($fh);
+    print_ranges($fh, "The characters \\d matches: the decimal numbers (Nd).", "PERL_DIGIT", ranges("Nd"));
+    print $fh "\n";
+    print_ranges($fh, "The characters \\s matches: White_Space.", "PERL_SPACE", ranges("White_Space"));
+    print $fh "\n";
+    print_ranges($fh, "The characters \\w matches, the word characters.", "PERL_WORD", ranges("Word"));
+    close($fh);
+}
+
+# The name a property or value is looked up with: lowercase, without spaces, dashes or
+# underscores, as the loose matching of UAX #44 allows.
+sub loose {
+    my ($name) = @_;
+    (my $ret = lc $name) =~ s/[ _-]//g;
+    return $ret;
+}
+
+# Writes the tables of a set of properties, or of the values of a property, and the table that
+# finds them by name.
+#
+# * $file: the module to write.
+# * $what: what the tables are, for the doc comments.
+# * @properties: for each table, its aliases, the long name first, and the property its
+#   characters are read from.
+sub property_tables {
+    my ($file, $what, @properties) = @_;
+    open(my $fh, '>', "$out_dir/$file") or die "can't write $file: $!";
+    header($fh);
+    my %by_name;
+    for my $property (@properties) {
+        my ($aliases, $query) = @$property;
+        (my $const = uc $aliases->[0]) =~ s/[^A-Z0-9]/_/g;
+        $by_name{loose($_)} = $const for @$aliases;
+        my @names = do { my %seen; grep { !$seen{$_}++ } @$aliases };
+        print_ranges($fh, join(", ", @names) . ".", $const, ranges($query));
+        print $fh "
+";
+    }
+    print $fh "/// The $what, by each of their names, in lowercase and without spaces, dashes or
+";
+    print $fh "/// underscores. Sorted by name.
+";
+    print $fh "pub(crate) const BY_NAME: &[(&str, &[(char, char)])] = &[
+";
+    for my $name (sort keys %by_name) {
+        print $fh "    (\"$name\", $by_name{$name}),
+";
+    }
+    print $fh "];
+";
+    close($fh);
+}
+
+# The general categories, both the values (Lu) and their groups (L).
+sub general_categories {
+    my @properties = map {
+        my ($short, @long) = prop_value_aliases("gc", $_);
+        [[@long, $short], "gc=$short"]
+    } prop_values("gc");
+    property_tables("general_category.rs", "general categories", @properties);
+}
+
+# The scripts, by the Script property: Common and Inherited hold the characters shared between
+# scripts.
+sub scripts {
+    # Katakana_Or_Hiragana has no characters of its own.
+    my @properties = map {
+        my ($short, @long) = prop_value_aliases("sc", $_);
+        defined $short ? [[@long, $short], "Script=$short"] : ()
+    } prop_values("sc");
+    property_tables("script.rs", "scripts", @properties);
+}
+
+# The binary properties most often matched against, and Any, ASCII and Assigned.
+sub binary_properties {
+    my @names = qw(
+        Alphabetic ASCII_Hex_Digit Bidi_Control Cased Case_Ignorable Dash
+        Default_Ignorable_Code_Point Deprecated Diacritic Emoji Emoji_Component Emoji_Modifier
+        Emoji_Modifier_Base Emoji_Presentation Extended_Pictographic Extender Hex_Digit
+        ID_Continue ID_Start Ideographic Join_Control Lowercase Math Noncharacter_Code_Point
+        Pattern_Syntax Pattern_White_Space Quotation_Mark Regional_Indicator Sentence_Terminal
+        Terminal_Punctuation Unified_Ideograph Uppercase Variation_Selector White_Space
+        XID_Continue XID_Start Any ASCII Assigned
+    );
+    my @properties = map {
+        my ($short, @long) = prop_aliases($_);
+        [[@long, $short], $_]
+    } @names;
+    property_tables("property_bool.rs", "binary properties", @properties);
+}
+
+case_folding();
+perl_classes();
+general_categories();
+scripts();
+binary_properties();
+
+# the short tables go on one line, as rustfmt lays them out, so that the tree stays formatted.
+system("rustfmt", "--edition", "2021", glob("$out_dir/*.rs")) == 0 or die "rustfmt failed: $?";
@@ -1,3 +1,6 @@
 // For now only accepts ASCII characters
-pub mod token_type;
+pub mod char_class;
+pub mod flags;
 pub mod scanner;
+pub mod token_type;
+pub mod unicode_tables;
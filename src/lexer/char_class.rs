@@ -0,0 +1,189 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use super::unicode_tables::case_folding::CASE_FOLDING_SIMPLE;
+
+/// A set of characters, written as a bracket expression (`[a-z]`, `[^\n"]`, ...) in the regular
+/// expression.
+///
+/// The whole class is a single token, and hence a single NFA state. Checking whether a character
+/// belongs to the class is a binary search over the ranges, so large classes stay cheap.
+///
+/// * `ranges`: inclusive character ranges. After `CharClass::canonicalize`, these are sorted and
+///   don't overlap or touch each other.
+/// * `negated`: whether the class matches every character *not* inside `ranges`.
+#[derive(PartialEq, Eq, PartialOrd, Clone, Debug, Default)]
+pub struct CharClass {
+    ranges: Vec<(char, char)>,
+    negated: bool,
+}
+
+impl CharClass {
+    /// Constructs an empty, non-negated class. An empty class matches nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Constructs a class that matches every character.
+    pub fn any() -> Self {
+        Self {
+            ranges: Vec::new(),
+            negated: true,
+        }
+    }
+
+    /// Constructs a non-negated class from a table of ranges, such as the generated Unicode ones.
+    ///
+    /// * `ranges`: sorted ranges, which don't overlap or touch each other.
+    pub fn from_ranges(ranges: &[(char, char)]) -> Self {
+        Self {
+            ranges: ranges.to_vec(),
+            negated: false,
+        }
+    }
+
+    /// Adds a single character to the class.
+    ///
+    /// * `c`:
+    pub fn push_char(&mut self, c: char) {
+        self.ranges.push((c, c));
+    }
+
+    /// Adds an inclusive range of characters to the class. The caller makes sure `lo <= hi`.
+    ///
+    /// * `lo`:
+    /// * `hi`:
+    pub fn push_range(&mut self, lo: char, hi: char) {
+        debug_assert!(lo <= hi);
+        self.ranges.push((lo, hi));
+    }
+
+    /// Adds every character the other class matches to this class, as `[\d\s]` does. The other
+    /// class must be canonicalized. If it's negated, the characters outside its ranges are added.
+    ///
+    /// * `other`:
+    pub fn push_class(&mut self, other: &CharClass) {
+        match other.negated {
+            false => self.ranges.extend_from_slice(&other.ranges),
+            true => self.ranges.extend(complement(&other.ranges)),
+        }
+    }
+
+    /// Flips the negation flag of this class.
+    pub fn negate(&mut self) {
+        self.negated = !self.negated;
+    }
+
+    /// Returns the (canonicalized) ranges of this class, ignoring the negation flag.
+    pub fn ranges(&self) -> &[(char, char)] {
+        &self.ranges
+    }
+
+    /// Sorts the ranges and merges the ones that overlap or are next to each other.
+    ///
+    /// Must be called once all characters are pushed, before `CharClass::matches` is used.
+    pub fn canonicalize(&mut self) {
+        self.ranges.sort_unstable();
+        let mut merged: Vec<(char, char)> = Vec::with_capacity(self.ranges.len());
+        for &(lo, hi) in self.ranges.iter() {
+            match merged.last_mut() {
+                // `char::from_u32` fails on the surrogate gap, in which case the ranges can't
+                // touch anyways.
+                Some(last) if last.1 >= lo || char::from_u32(last.1 as u32 + 1) == Some(lo) => {
+                    last.1 = last.1.max(hi);
+                }
+                _ => merged.push((lo, hi)),
+            }
+        }
+        self.ranges = merged;
+    }
+
+    /// Adds the other cases of every character in the class, so that the class matches regardless
+    /// of case. Uses the simple case folding of Unicode, so "k" also matches the Kelvin sign, but
+    /// "ß" doesn't match "ss".
+    ///
+    /// Negation is left untouched, so a negated class excludes all cases.
+    pub fn case_fold(&mut self) {
+        let mut folded: Vec<(char, char)> = Vec::new();
+        for &(lo, hi) in self.ranges.iter() {
+            let start = CASE_FOLDING_SIMPLE.partition_point(|&(c, _)| c < lo);
+            for &(_, others) in CASE_FOLDING_SIMPLE[start..]
+                .iter()
+                .take_while(|&&(c, _)| c <= hi)
+            {
+                folded.extend(others.iter().map(|&other| (other, other)));
+            }
+        }
+        self.ranges.extend(folded);
+        self.canonicalize();
+    }
+
+    #[inline]
+    /// Returns whether the character passed in belongs to this class.
+    ///
+    /// * `c`:
+    pub fn matches(&self, c: char) -> bool {
+        let found = self
+            .ranges
+            .binary_search_by(|&(lo, hi)| {
+                if hi < c {
+                    Ordering::Less
+                } else if lo > c {
+                    Ordering::Greater
+                } else {
+                    Ordering::Equal
+                }
+            })
+            .is_ok();
+        found != self.negated
+    }
+}
+
+/// Returns the ranges of the characters outside the ranges passed in.
+///
+/// * `ranges`: sorted ranges, which don't overlap or touch each other.
+fn complement(ranges: &[(char, char)]) -> Vec<(char, char)> {
+    // the characters right before and after one, skipping the surrogate gap.
+    let before = |c: char| match c {
+        '\u{E000}' => Some('\u{D7FF}'),
+        _ => char::from_u32((c as u32).checked_sub(1)?),
+    };
+    let after = |c: char| match c {
+        '\u{D7FF}' => Some('\u{E000}'),
+        _ => char::from_u32(c as u32 + 1),
+    };
+
+    let mut ret = Vec::with_capacity(ranges.len() + 1);
+    let mut lo = Some('\0');
+    for &(start, end) in ranges {
+        if let (Some(lo), Some(hi)) = (lo, before(start)) {
+            if lo <= hi {
+                ret.push((lo, hi));
+            }
+        }
+        lo = after(end);
+    }
+    if let Some(lo) = lo {
+        ret.push((lo, char::MAX));
+    }
+    ret
+}
+
+impl fmt::Display for CharClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[")?;
+        if self.negated {
+            write!(f, "^")?;
+        }
+        for &(lo, hi) in self.ranges.iter() {
+            if lo == hi {
+                write!(f, "{}", lo.escape_debug())?;
+            } else {
+                write!(f, "{}-{}", lo.escape_debug(), hi.escape_debug())?;
+            }
+        }
+        write!(f, "]")
+    }
+}
+
+mod test;
@@ -0,0 +1,63 @@
+#[cfg(test)]
+use super::*;
+
+#[test]
+fn canonicalize_test() {
+    let mut class = CharClass::new();
+    class.push_range('x', 'z');
+    class.push_range('a', 'f');
+    class.push_char('g');
+    class.push_range('c', 'e');
+    class.canonicalize();
+    assert_eq!(class.ranges(), &[('a', 'g'), ('x', 'z')]);
+
+    assert!(class.matches('a'));
+    assert!(class.matches('g'));
+    assert!(class.matches('y'));
+    assert!(!class.matches('h'));
+    assert!(!class.matches('A'));
+
+    class.negate();
+    assert!(!class.matches('a'));
+    assert!(class.matches('h'));
+    assert!(class.matches('\n'));
+}
+
+#[test]
+fn case_fold_test() {
+    let mut class = CharClass::new();
+    class.push_range('a', 'c');
+    class.push_char('k');
+    class.push_char('σ');
+    class.push_char('1');
+    class.case_fold();
+    for c in ['a', 'B', 'c', 'K', '\u{212A}', 'Σ', 'ς', '1'] {
+        assert!(class.matches(c), "{c} should match");
+    }
+    assert!(!class.matches('D'));
+
+    // a negated class excludes every case.
+    let mut class = CharClass::new();
+    class.push_char('é');
+    class.negate();
+    class.case_fold();
+    assert!(!class.matches('É'));
+    assert!(class.matches('e'));
+}
+
+#[test]
+fn push_class_test() {
+    let mut class = CharClass::new();
+    class.push_char('_');
+    class.push_class(&CharClass::from_ranges(&[('0', '9')]));
+    class.canonicalize();
+    assert_eq!(class.ranges(), &[('0', '9'), ('_', '_')]);
+
+    // a negated class adds what it doesn't contain.
+    let mut not_digit = CharClass::from_ranges(&[('0', '9')]);
+    not_digit.negate();
+    let mut class = CharClass::new();
+    class.push_class(&not_digit);
+    class.canonicalize();
+    assert_eq!(class.ranges(), &[('\0', '/'), (':', char::MAX)]);
+}
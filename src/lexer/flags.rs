@@ -0,0 +1,42 @@
+/// The flags that change how the rest of the pattern is read. Set inline with "(?aimRsx)" for the
+/// rest of the enclosing group, or "(?aimRsx:...)" for the group itself. A dash turns the flags
+/// after it off, as in "(?i-s)".
+///
+/// * `ascii` (a): \d, \s, \w and \b only know the characters of ASCII, as in [0-9] for \d, rather
+///   than those of Unicode.
+/// * `case_insensitive` (i): characters match regardless of their case.
+/// * `multi_line` (m): ^ and $ match at the start and end of each line, not only those of the whole
+///   string.
+/// * `crlf` (R): under the m flag, lines may end with \r\n, or a lone \r, as well as \n.
+/// * `dot_matches_new_line` (s): . matches \n too.
+/// * `ignore_whitespace` (x): whitespace in the pattern is ignored, and # starts a comment that
+///   runs to the end of the line.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub(crate) struct Flags {
+    pub ascii: bool,
+    pub case_insensitive: bool,
+    pub multi_line: bool,
+    pub crlf: bool,
+    pub dot_matches_new_line: bool,
+    pub ignore_whitespace: bool,
+}
+
+impl Flags {
+    /// Sets or clears the flag with the letter passed in.
+    ///
+    /// * `letter`: one of a, i, m, R, s and x.
+    /// * `value`:
+    /// * Return: `false` if the letter isn't a flag.
+    pub fn set(&mut self, letter: char, value: bool) -> bool {
+        match letter {
+            'a' => self.ascii = value,
+            'i' => self.case_insensitive = value,
+            'm' => self.multi_line = value,
+            'R' => self.crlf = value,
+            's' => self.dot_matches_new_line = value,
+            'x' => self.ignore_whitespace = value,
+            _ => return false,
+        }
+        true
+    }
+}
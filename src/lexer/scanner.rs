@@ -1,31 +1,140 @@
-use super::token_type::{Token, TokenType};
-/// A scanner converts a raw string into an infix list of tokens. At the moment, the scanner, and
-/// hence all other components of this regular expression engine, only works on ASCII characters.
+use super::{
+    char_class::CharClass,
+    flags::Flags,
+    token_type::{LookKind, Token, TokenType},
+    unicode_tables::{
+        self,
+        perl_classes::{PERL_DIGIT, PERL_SPACE, PERL_WORD},
+    },
+};
+/// A scanner converts a raw string into an infix list of tokens. It reads the pattern one Unicode
+/// character at a time, so any character may appear in it literally. Besides the operators, it
+/// scans bracket expressions with their ranges, POSIX classes, equivalence classes and collating
+/// symbols, escapes such as \d, \b, \n or \x{1F600}, the Unicode properties \p{..} and \P{..},
+/// bounded repetitions, and the prefixes of groups: names, flags and lookarounds.
 ///
 /// * `token_list`: the resultant infix token list. Only valid after `Scanner::scan` is called.
 /// * `input`: a reference to the input string. Since this is only a reference, the lifetime of the
-///            input string must be at least that of the Scanner.
-/// * `curr_scan_fn`: the function called by the scanner for each character it reads.
+///   input string must be at least that of the Scanner.
+/// * `curr_scan_fn`: the function called by the scanner for each character it reads. It returns
+///   `None` if the character is only part of a longer token.
 /// * `concat_next`: whether a concatenation notation may be inserted when scanning the next
-///                  character.
+///   character.
+/// * `bracket`: the bracket expression being scanned, if any.
+/// * `escape`: the escape sequence being scanned, if any.
+/// * `repeat`: the string index of the opening brace, and the text read so far, of the bounded
+///   repetition being scanned, if any.
+/// * `group`: the string index of the opening parentheses, and the text read so far after "(?", of
+///   the group prefix being scanned, if any.
+/// * `capture_names`: the name of each capture group opened so far, `None` if unnamed. Element 0 is
+///   group 0, the whole match, which never has a name.
+/// * `flags`: the flags in effect at the character being scanned.
+/// * `flag_stack`: the flags to restore when each open group is closed, innermost last, along with
+///   the kind of the group if it's a lookaround.
+/// * `next_char`: the character after the one being scanned, if any.
 pub(crate) struct Scanner<'a> {
     token_list: Vec<Token>,
     input: &'a str,
-    curr_scan_fn: fn(&mut Scanner<'a>, char) -> TokenType,
+    curr_scan_fn: ScanFn<'a>,
     concat_next: bool,
+    bracket: Option<BracketData>,
+    escape: Option<EscapeData>,
+    repeat: Option<(usize, String)>,
+    group: Option<(usize, String)>,
+    capture_names: Vec<Option<String>>,
+    flags: Flags,
+    flag_stack: Vec<(Flags, Option<LookKind>)>,
+    next_char: Option<char>,
+}
+
+type ScanFn<'a> = fn(&mut Scanner<'a>, usize, char) -> Result<Option<TokenType>, String>;
+
+/// Bookkeeping for the bracket expression currently being scanned.
+///
+/// * `class`: the characters collected so far.
+/// * `pos`: the string index of the opening bracket.
+/// * `prev`: the last character read, which may still turn out to be the start of a range.
+/// * `dash`: whether a dash (-) follows `prev`, which makes the next character the end of a range.
+/// * `name`: the string index of the inner opening bracket, and the text read so far after it, of
+///   the POSIX class, equivalence class or collating symbol being scanned, if any.
+struct BracketData {
+    class: CharClass,
+    pos: usize,
+    prev: Option<char>,
+    dash: bool,
+    name: Option<(usize, String)>,
+}
+
+/// Bookkeeping for the escape sequence currently being scanned, from the backslash on.
+///
+/// * `pos`: the string index of the backslash.
+/// * `text`: the characters read after the backslash so far.
+/// * `in_bracket`: whether the escape is inside a bracket expression, where the scanner goes back
+///   to once the escape is finished.
+struct EscapeData {
+    pos: usize,
+    text: String,
+    in_bracket: bool,
+}
+
+/// The ASCII versions of \d, \s and \w, under the a flag.
+const ASCII_DIGIT: &[(char, char)] = &[('0', '9')];
+const ASCII_SPACE: &[(char, char)] = &[('\t', '\r'), (' ', ' ')];
+const ASCII_WORD: &[(char, char)] = &[('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')];
+
+/// The POSIX classes of bracket expressions, such as `[[:alpha:]]`, as defined for the C locale.
+const POSIX_CLASSES: &[(&str, &[(char, char)])] = &[
+    ("alnum", &[('0', '9'), ('A', 'Z'), ('a', 'z')]),
+    ("alpha", &[('A', 'Z'), ('a', 'z')]),
+    ("blank", &[('\t', '\t'), (' ', ' ')]),
+    ("cntrl", &[('\0', '\x1F'), ('\x7F', '\x7F')]),
+    ("digit", &[('0', '9')]),
+    ("graph", &[('!', '~')]),
+    ("lower", &[('a', 'z')]),
+    ("print", &[(' ', '~')]),
+    ("punct", &[('!', '/'), (':', '@'), ('[', '`'), ('{', '~')]),
+    ("space", &[('\t', '\r'), (' ', ' ')]),
+    ("upper", &[('A', 'Z')]),
+    ("xdigit", &[('0', '9'), ('A', 'F'), ('a', 'f')]),
+];
+
+/// What an escape sequence stands for.
+enum Escaped {
+    /// A single character: `\n`, `\x41`, `\.`, ...
+    Char(char),
+    /// A class of characters: `\d`, `\W`, ...
+    Class(CharClass),
 }
 
 impl<'a> Scanner<'a> {
+    #[allow(dead_code)]
     /// Creates a new Scanner.
     ///
     /// The variable containing this scanner should be mutable.
     /// * `input_str`: The string to be scanned.
     pub fn new(input_str: &'a str) -> Scanner<'a> {
+        Scanner::with_flags(input_str, Flags::default())
+    }
+
+    /// Creates a new Scanner, with the flags passed in set from the start of the string, as if it
+    /// began with "(?flags)".
+    ///
+    /// * `input_str`: The string to be scanned.
+    /// * `flags`: the initial flags.
+    pub fn with_flags(input_str: &'a str, flags: Flags) -> Scanner<'a> {
         Scanner {
             token_list: Vec::new(),
             input: input_str,
             curr_scan_fn: Scanner::scan_char,
             concat_next: false,
+            bracket: None,
+            escape: None,
+            repeat: None,
+            group: None,
+            capture_names: vec![None],
+            flags,
+            flag_stack: Vec::new(),
+            next_char: None,
         }
     }
 
@@ -33,8 +142,15 @@ impl<'a> Scanner<'a> {
     ///
     /// The scanner is expected to be consumed by the postfix converter after this step.
     pub fn scan(&mut self) -> Result<(), String> {
-        for (idx, input_char) in self.input.chars().enumerate() {
-            let ret_token = (self.curr_scan_fn)(self, input_char);
+        let mut chars = self.input.chars().enumerate().peekable();
+        while let Some((idx, input_char)) = chars.next() {
+            self.next_char = chars.peek().map(|&(_, c)| c);
+            let ret_token = match (self.curr_scan_fn)(self, idx, input_char)? {
+                Some(tok) => tok,
+                // the character belongs to a token that isn't finished yet.
+                None => continue,
+            };
+            let ret_token = self.apply_flags(ret_token);
             let mut need_concat = ret_token.need_concat_next();
             match ret_token {
                 TokenType::Escape => {
@@ -42,56 +158,841 @@ impl<'a> Scanner<'a> {
                     // that of the last character.
                     need_concat = self.concat_next;
                 }
-                // TokenType::Dollar will be error-checked by the postfix converter.
+                // the anchors may go anywhere, as in "a(^|,)b": they only never match where
+                // they can't hold.
                 TokenType::Character(_)
                 | TokenType::Dot
-                | TokenType::LParen
-                | TokenType::Dollar => self.push_need_concat(idx, ret_token),
-                TokenType::Hat => self.handle_push_hat(idx)?,
-                TokenType::QuestionMark | TokenType::Star | TokenType::Plus => {
-                    self.handle_push_single_quantifier(idx, ret_token)?
+                | TokenType::CharClass(_)
+                | TokenType::LParen(_)
+                | TokenType::Hat
+                | TokenType::Dollar
+                | TokenType::LineStart { .. }
+                | TokenType::LineEnd { .. }
+                | TokenType::EndNewLine
+                | TokenType::WordBoundary { .. }
+                | TokenType::NotWordBoundary { .. } => self.push_need_concat(idx, ret_token),
+                TokenType::Beam | TokenType::RParen => {
+                    self.push_missing_operand(idx);
+                    self.token_list.push(Token::new(idx, ret_token));
+                }
+                // the lookaround applies to the group just closed, like a quantifier.
+                TokenType::Lookaround(_) => {
+                    self.push_missing_operand(idx);
+                    self.token_list.push(Token::new(idx, TokenType::RParen));
+                    self.token_list.push(Token::new(idx, ret_token));
                 }
+                TokenType::QuestionMark { .. } if self.make_last_lazy() => (),
+                TokenType::QuestionMark { .. }
+                | TokenType::Star { .. }
+                | TokenType::Plus { .. }
+                | TokenType::Repeat { .. } => self.handle_push_quantifier(idx, ret_token)?,
                 _ => self.token_list.push(Token::new(idx, ret_token)),
             }
             self.concat_next = need_concat;
         }
 
+        if let Some(data) = &self.escape {
+            return Err(format!("Character \\ at {0}: unfinished escape", data.pos));
+        }
+        if let Some(data) = &self.bracket {
+            return Err(format!("Character [ at {0}: missing ]", data.pos));
+        }
+        if let Some((pos, _)) = &self.repeat {
+            return Err(format!("Character {{ at {0}: missing }}", pos));
+        }
+        if let Some((pos, _)) = &self.group {
+            return Err(format!("Character ( at {0}: unfinished group prefix", pos));
+        }
+        if let Some(Token {
+            token_type: TokenType::Beam,
+            ..
+        }) = self.token_list.last()
+        {
+            self.push_missing_operand(self.input.chars().count());
+        }
+
         Ok(())
     }
 
     /// Scans the input character.
     /// If the input character is escape ('\\'), the next time the scanner calls its scan function,
-    /// it calls `scan_escape`.
+    /// it calls `scan_escape`. If it is an opening bracket ('['), the scanner goes into bracket
+    /// mode until the matching closing bracket.
     ///
+    /// * `idx`: the string index.
     /// * `input_char`: the input character.
     /// * Return: the token type detected.
-    fn scan_char(&mut self, input_char: char) -> TokenType {
-        match input_char {
+    fn scan_char(&mut self, idx: usize, input_char: char) -> Result<Option<TokenType>, String> {
+        if self.flags.ignore_whitespace {
+            if input_char.is_whitespace() {
+                return Ok(None);
+            }
+            if input_char == '#' {
+                self.curr_scan_fn = Scanner::scan_comment;
+                return Ok(None);
+            }
+        }
+        let ret = match input_char {
             '.' => TokenType::Dot,
-            '*' => TokenType::Star,
+            '*' => TokenType::Star { greedy: true },
             '|' => TokenType::Beam,
-            '+' => TokenType::Plus,
+            '+' => TokenType::Plus { greedy: true },
+            '^' if self.flags.multi_line => TokenType::LineStart {
+                crlf: self.flags.crlf,
+            },
             '^' => TokenType::Hat,
+            '$' if self.flags.multi_line => TokenType::LineEnd {
+                crlf: self.flags.crlf,
+            },
             '$' => TokenType::Dollar,
-            '?' => TokenType::QuestionMark,
-            '(' => TokenType::LParen,
-            ')' => TokenType::RParen,
+            '?' => TokenType::QuestionMark { greedy: true },
+            '(' if self.next_char == Some('?') => {
+                self.curr_scan_fn = Scanner::scan_group_question_mark;
+                self.group = Some((idx, String::new()));
+                return Ok(None);
+            }
+            '(' => {
+                let token = self.new_capture_group(idx, None)?;
+                self.open_group(token, self.flags, None)
+            }
+            ')' => match self.flag_stack.pop() {
+                Some((flags, look)) => {
+                    self.flags = flags;
+                    look.map_or(TokenType::RParen, TokenType::Lookaround)
+                }
+                None => TokenType::RParen,
+            },
+            '[' => {
+                self.curr_scan_fn = Scanner::scan_bracket_start;
+                self.bracket = Some(BracketData {
+                    class: CharClass::new(),
+                    pos: idx,
+                    prev: None,
+                    dash: false,
+                    name: None,
+                });
+                return Ok(None);
+            }
+            '{' => {
+                self.curr_scan_fn = Scanner::scan_repeat;
+                self.repeat = Some((idx, String::new()));
+                return Ok(None);
+            }
             '\\' => {
-                self.curr_scan_fn = Scanner::scan_escape;
+                self.start_escape(idx, false);
                 TokenType::Escape
             }
             _ => TokenType::Character(input_char),
             // the user cannot input the concat token.
+        };
+        Ok(Some(ret))
+    }
+
+    /// The scan function called inside a comment, which starts with # under the x flag and runs to
+    /// the end of the line.
+    /// After the end of the line, the scanner's next scan function is `scan_char`.
+    ///
+    /// * `input_char`:
+    fn scan_comment(&mut self, _idx: usize, input_char: char) -> Result<Option<TokenType>, String> {
+        if input_char == '\n' {
+            self.curr_scan_fn = Scanner::scan_char;
         }
+        Ok(None)
     }
 
-    /// The scan function called if the last character scanned is an escape.
-    /// After this function is called, the scanner's next scan function is `scan_char`.
+    /// Starts scanning an escape sequence, for the backslash just read.
+    ///
+    /// * `idx`: the string index of the backslash.
+    /// * `in_bracket`: whether the backslash is inside a bracket expression.
+    fn start_escape(&mut self, idx: usize, in_bracket: bool) {
+        self.curr_scan_fn = Scanner::scan_escape;
+        self.escape = Some(EscapeData {
+            pos: idx,
+            text: String::new(),
+            in_bracket,
+        });
+    }
+
+    /// The scan function called inside an escape sequence, that is, after a backslash.
+    ///
+    /// The escapes are:
+    /// * `\d`, `\s` and `\w`, the Unicode digits, white space and word characters, and `\D`,
+    ///   `\S` and `\W`, the characters outside them. Only those of ASCII under the a flag.
+    /// * `\b` and `\B`, the word boundary anchors, and `\A`, `\z` and `\Z`, the anchors at the
+    ///   start and end of the whole string whatever the flags, outside brackets.
+    /// * `\n`, `\t`, `\r`, `\f`, `\v` and `\0`, the control characters.
+    /// * `\xHH`, `\x{H...}` and `\u{H...}`, the character with the hexadecimal code point written.
+    /// * `\pL` or `\p{...}`, the characters with a Unicode property, and `\PL` or `\P{...}`, the
+    ///   characters without it.
+    /// * A backslash before any other character that isn't a letter or a digit, that character.
+    ///
+    /// Any other letter or digit is an error, so that it can be given a meaning later.
+    /// After the escape, the scanner's next scan function is `scan_char`, or `scan_bracket` inside
+    /// a bracket expression.
     ///
+    /// * `idx`: the string index.
+    /// * `input_char`:
+    fn scan_escape(&mut self, idx: usize, input_char: char) -> Result<Option<TokenType>, String> {
+        let data = self.escape.as_mut().unwrap();
+        match data.text.chars().next() {
+            Some('x' | 'u') => return self.scan_hex_escape(idx, input_char),
+            Some(_) => return self.scan_property_escape(idx, input_char),
+            None => (),
+        }
+        let escaped = match input_char {
+            'd' | 'D' | 's' | 'S' | 'w' | 'W' => {
+                let table = match (input_char.to_ascii_lowercase(), self.flags.ascii) {
+                    ('d', false) => PERL_DIGIT,
+                    ('s', false) => PERL_SPACE,
+                    (_, false) => PERL_WORD,
+                    ('d', true) => ASCII_DIGIT,
+                    ('s', true) => ASCII_SPACE,
+                    (_, true) => ASCII_WORD,
+                };
+                let mut class = CharClass::from_ranges(table);
+                if input_char.is_ascii_uppercase() {
+                    class.negate();
+                }
+                Escaped::Class(class)
+            }
+            'n' => Escaped::Char('\n'),
+            't' => Escaped::Char('\t'),
+            'r' => Escaped::Char('\r'),
+            'f' => Escaped::Char('\x0C'),
+            'v' => Escaped::Char('\x0B'),
+            '0' => Escaped::Char('\0'),
+            'x' | 'u' | 'p' | 'P' => {
+                data.text.push(input_char);
+                return Ok(None);
+            }
+            'b' | 'B' | 'A' | 'z' | 'Z' if data.in_bracket => {
+                return Err(format!(
+                    "Character {0} at {1}: \\{0} isn't allowed inside brackets",
+                    input_char, idx
+                ))
+            }
+            'b' | 'B' => {
+                let ascii = self.flags.ascii;
+                self.escape = None;
+                self.curr_scan_fn = Scanner::scan_char;
+                return Ok(Some(match input_char {
+                    'b' => TokenType::WordBoundary { ascii },
+                    _ => TokenType::NotWordBoundary { ascii },
+                }));
+            }
+            'A' | 'z' | 'Z' => {
+                self.escape = None;
+                self.curr_scan_fn = Scanner::scan_char;
+                return Ok(Some(match input_char {
+                    'A' => TokenType::Hat,
+                    'z' => TokenType::Dollar,
+                    _ => TokenType::EndNewLine,
+                }));
+            }
+            '1'..='9' => {
+                return Err(format!(
+                    "Character {0} at {1}: backreferences such as \\{0} aren't supported",
+                    input_char, idx
+                ))
+            }
+            _ if input_char.is_alphanumeric() => {
+                return Err(format!(
+                    "Character {0} at {1}: unknown escape \\{0}",
+                    input_char, idx
+                ))
+            }
+            _ => Escaped::Char(input_char),
+        };
+        self.finish_escape(idx, escaped)
+    }
+
+    /// The scan function called inside a hexadecimal escape, after "\x" or "\u": two digits, or
+    /// any number of digits between braces.
+    ///
+    /// * `idx`: the string index.
+    /// * `input_char`:
+    fn scan_hex_escape(
+        &mut self,
+        idx: usize,
+        input_char: char,
+    ) -> Result<Option<TokenType>, String> {
+        let data = self.escape.as_mut().unwrap();
+        let pos = data.pos;
+        let braced = data.text[1..].starts_with('{');
+        // only \x can do without braces.
+        let takes_digit = braced || data.text.starts_with('x');
+        match input_char {
+            '{' if data.text.len() == 1 => data.text.push(input_char),
+            '}' if braced && data.text.len() > 2 => (),
+            _ if input_char.is_ascii_hexdigit() && takes_digit => data.text.push(input_char),
+            _ => {
+                return Err(format!(
+                    "Character {0} at {1}: unexpected in the escape \\{2} at {3}",
+                    input_char, idx, data.text, pos
+                ))
+            }
+        }
+        let digits = match (braced, input_char) {
+            (true, '}') => &data.text[2..],
+            (false, _) if data.text.len() == 3 => &data.text[1..],
+            _ => return Ok(None),
+        };
+        let code_point = u32::from_str_radix(digits, 16)
+            .ok()
+            .and_then(char::from_u32);
+        match code_point {
+            Some(c) => self.finish_escape(idx, Escaped::Char(c)),
+            None => Err(format!(
+                "Character \\ at {0}: {1} isn't the code point of a character",
+                pos, digits
+            )),
+        }
+    }
+
+    /// The scan function called inside a Unicode property escape, after "\p" or "\P": a one-letter
+    /// general category, or any name between braces. See `unicode_tables::property` for the names.
+    ///
+    /// * `idx`: the string index.
+    /// * `input_char`:
+    fn scan_property_escape(
+        &mut self,
+        idx: usize,
+        input_char: char,
+    ) -> Result<Option<TokenType>, String> {
+        let data = self.escape.as_mut().unwrap();
+        let name = match (data.text.len(), input_char) {
+            (1, '{') => {
+                data.text.push(input_char);
+                return Ok(None);
+            }
+            (1, _) if input_char.is_ascii_alphabetic() => input_char.to_string(),
+            (1, _) => {
+                return Err(format!(
+                    "Character {0} at {1}: expected a property name or {{ after \\{2}",
+                    input_char, idx, data.text
+                ))
+            }
+            (_, '}') => data.text[2..].to_string(),
+            _ => {
+                data.text.push(input_char);
+                return Ok(None);
+            }
+        };
+        let Some(table) = unicode_tables::property(&name) else {
+            return Err(format!(
+                "Character \\ at {0}: unknown Unicode property {1}",
+                data.pos, name
+            ));
+        };
+        let mut class = CharClass::from_ranges(table);
+        if data.text.starts_with('P') {
+            class.negate();
+        }
+        self.finish_escape(idx, Escaped::Class(class))
+    }
+
+    /// Ends the escape sequence being scanned, and goes back to where the backslash was read.
+    ///
+    /// * `idx`: the string index of the last character of the escape.
+    /// * `escaped`: what the escape stands for.
+    /// * Return: the token of the escape, or `None` inside a bracket expression, where it's added
+    ///   to the class instead.
+    fn finish_escape(&mut self, idx: usize, escaped: Escaped) -> Result<Option<TokenType>, String> {
+        let data = self.escape.take().unwrap();
+        if !data.in_bracket {
+            self.curr_scan_fn = Scanner::scan_char;
+            return Ok(Some(match escaped {
+                Escaped::Char(c) => TokenType::Character(c),
+                Escaped::Class(class) => TokenType::CharClass(class),
+            }));
+        }
+        self.curr_scan_fn = Scanner::scan_bracket;
+        match escaped {
+            Escaped::Char(c) => self.push_bracket_member(idx, c)?,
+            Escaped::Class(class) => self.push_bracket_class(data.pos, &class)?,
+        }
+        Ok(None)
+    }
+
+    /// The scan function called for the question mark right after a left parentheses.
+    /// After this function is called, the scanner's next scan function is `scan_group_prefix`.
+    fn scan_group_question_mark(
+        &mut self,
+        _idx: usize,
+        _input_char: char,
+    ) -> Result<Option<TokenType>, String> {
+        self.curr_scan_fn = Scanner::scan_group_prefix;
+        Ok(None)
+    }
+
+    /// The scan function called after "(?".
+    ///
+    /// The prefix is either that of a named group, "(?P<name>" or "(?<name>", that of a
+    /// lookaround, "(?=", "(?!", "(?<=" or "(?<!", or a list of flags: "(?flags)" sets them for
+    /// the rest of the enclosing group, and "(?flags:" opens a non-capturing group with them set.
+    /// The list may be empty before ":".
+    ///
+    /// * `idx`: the string index.
+    /// * `input_char`:
+    fn scan_group_prefix(
+        &mut self,
+        idx: usize,
+        input_char: char,
+    ) -> Result<Option<TokenType>, String> {
+        let (pos, text) = self.group.as_mut().unwrap();
+        let pos = *pos;
+        match (text.as_str(), input_char) {
+            ("", 'P') => text.push(input_char),
+            ("", '<') if matches!(self.next_char, Some('=' | '!')) => text.push(input_char),
+            ("" | "<", '=' | '!') => {
+                let kind = match (text.as_str(), input_char) {
+                    ("", '=') => LookKind::Ahead,
+                    ("", _) => LookKind::NotAhead,
+                    (_, '=') => LookKind::Behind,
+                    _ => LookKind::NotBehind,
+                };
+                self.curr_scan_fn = Scanner::scan_char;
+                self.group = None;
+                return self.open_lookaround(pos, kind).map(Some);
+            }
+            ("" | "P", '<') => {
+                text.clear();
+                self.curr_scan_fn = Scanner::scan_group_name;
+            }
+            (_, ':' | ')') if !text.starts_with('P') => {
+                if input_char == ')' && text.is_empty() {
+                    return Err(format!("Character ( at {0}: empty flag group", pos));
+                }
+                let flags = Self::parse_flags(self.flags, pos, text)?;
+                self.curr_scan_fn = Scanner::scan_char;
+                self.group = None;
+                if input_char == ':' {
+                    return Ok(Some(self.open_group(TokenType::LParen(None), flags, None)));
+                }
+                self.flags = flags;
+            }
+            (_, 'a' | 'i' | 'm' | 'R' | 's' | 'x' | '-') if !text.starts_with('P') => {
+                text.push(input_char)
+            }
+            _ => {
+                return Err(format!(
+                    "Character {0} at {1}: unknown group prefix (?{2}{0} at {3}",
+                    input_char, idx, text, pos
+                ))
+            }
+        }
+        Ok(None)
+    }
+
+    /// Applies a list of flags, such as "i-s", on top of the flags passed in.
+    ///
+    /// * `flags`: the flags in effect before the list.
+    /// * `pos`: the string index of the opening parentheses.
+    /// * `text`: the list of flags. Only contains flag letters and dashes.
+    /// * Return: the flags in effect after the list.
+    fn parse_flags(mut flags: Flags, pos: usize, text: &str) -> Result<Flags, String> {
+        let (on, off) = text.split_once('-').unwrap_or((text, ""));
+        if text.ends_with('-') || off.contains('-') {
+            return Err(format!(
+                "Character ( at {0}: dash must be followed by flags, and appear only once",
+                pos
+            ));
+        }
+        for letter in on.chars() {
+            flags.set(letter, true);
+        }
+        for letter in off.chars() {
+            flags.set(letter, false);
+        }
+        Ok(flags)
+    }
+
+    /// Saves the flags in effect so that they are restored when the group closes, then sets the
+    /// flags of the group.
+    ///
+    /// * `token`: the `TokenType::LParen` opening the group.
+    /// * `flags`: the flags in effect inside the group.
+    /// * `look`: the kind of the group, if it's a lookaround.
+    /// * Return: `token`.
+    fn open_group(&mut self, token: TokenType, flags: Flags, look: Option<LookKind>) -> TokenType {
+        self.flag_stack.push((self.flags, look));
+        self.flags = flags;
+        token
+    }
+
+    /// Returns whether the character being scanned is inside a lookaround group.
+    fn in_lookaround(&self) -> bool {
+        self.flag_stack.iter().any(|(_, look)| look.is_some())
+    }
+
+    /// Opens a lookaround group, after "(?=", "(?!", "(?<=" or "(?<!". The group doesn't capture,
+    /// and is closed by `TokenType::Lookaround` rather than `TokenType::RParen`.
+    ///
+    /// Lookarounds can't be nested, see `Scanner::new_capture_group` for the capture groups.
+    ///
+    /// * `pos`: the string index of the opening parentheses.
+    /// * `kind`:
+    fn open_lookaround(&mut self, pos: usize, kind: LookKind) -> Result<TokenType, String> {
+        if self.in_lookaround() {
+            return Err(format!(
+                "Character ( at {0}: lookarounds can't be nested, {1}...) is inside another one",
+                pos, kind
+            ));
+        }
+        Ok(self.open_group(TokenType::LParen(None), self.flags, Some(kind)))
+    }
+
+    /// The scan function called inside the name of a named group, that is, after "(?<" or
+    /// "(?P<". The name is collected until ">" is read.
+    ///
+    /// * `idx`: the string index.
     /// * `input_char`:
-    fn scan_escape(&mut self, input_char: char) -> TokenType {
+    /// * Return: the `TokenType::LParen` of the group when ">" is read.
+    fn scan_group_name(
+        &mut self,
+        idx: usize,
+        input_char: char,
+    ) -> Result<Option<TokenType>, String> {
+        let (pos, text) = self.group.as_mut().unwrap();
+        if input_char != '>' {
+            let valid = if text.is_empty() {
+                input_char.is_alphabetic() || input_char == '_'
+            } else {
+                input_char.is_alphanumeric() || input_char == '_'
+            };
+            if !valid {
+                return Err(format!(
+                    "Character {0} at {1}: not allowed in the name of the group at {2}",
+                    input_char, idx, pos
+                ));
+            }
+            text.push(input_char);
+            return Ok(None);
+        }
+
         self.curr_scan_fn = Scanner::scan_char;
-        TokenType::Character(input_char)
+        let (pos, name) = self.group.take().unwrap();
+        if name.is_empty() {
+            return Err(format!("Character ( at {0}: empty group name", pos));
+        }
+        if self
+            .capture_names
+            .iter()
+            .flatten()
+            .any(|other| *other == name)
+        {
+            return Err(format!(
+                "Character ( at {0}: duplicate group name {1}",
+                pos, name
+            ));
+        }
+        let token = self.new_capture_group(pos, Some(name))?;
+        Ok(Some(self.open_group(token, self.flags, None)))
+    }
+
+    /// Numbers a new capture group, and returns the token opening it.
+    ///
+    /// A lookaround only tells whether its group matches, so a group inside it would never
+    /// capture anything: those are errors, rather than groups that are always unset.
+    ///
+    /// * `pos`: the string index of the opening parentheses.
+    /// * `name`: the name of the group, if any.
+    fn new_capture_group(&mut self, pos: usize, name: Option<String>) -> Result<TokenType, String> {
+        if self.in_lookaround() {
+            return Err(format!(
+                "Character ( at {0}: capture groups aren't allowed inside lookarounds, use (?:...)",
+                pos
+            ));
+        }
+        self.capture_names.push(name);
+        Ok(TokenType::LParen(Some(self.capture_names.len() - 1)))
+    }
+
+    /// The scan function called inside a bounded repetition, that is, after an opening brace.
+    ///
+    /// The text between the braces is collected, then parsed once the closing brace is read.
+    ///
+    /// * `idx`: the string index.
+    /// * `input_char`:
+    /// * Return: the finished `TokenType::Repeat` when the closing brace is read.
+    fn scan_repeat(&mut self, idx: usize, input_char: char) -> Result<Option<TokenType>, String> {
+        let (pos, text) = self.repeat.as_mut().unwrap();
+        match input_char {
+            '0'..='9' | ',' => {
+                text.push(input_char);
+                Ok(None)
+            }
+            '}' => {
+                self.curr_scan_fn = Scanner::scan_char;
+                let (pos, text) = self.repeat.take().unwrap();
+                Ok(Some(Scanner::parse_repeat(pos, &text)?))
+            }
+            _ => Err(format!(
+                "Character {0} at {1}: unexpected inside the repetition at {2}",
+                input_char, idx, pos
+            )),
+        }
+    }
+
+    /// Parses the text between the braces of a bounded repetition: `m`, `m,`, `,n` or `m,n`.
+    ///
+    /// * `pos`: the string index of the opening brace.
+    /// * `text`: the text between the braces. Only contains digits and commas.
+    fn parse_repeat(pos: usize, text: &str) -> Result<TokenType, String> {
+        let malformed = || {
+            format!(
+                "Character {{ at {0}: malformed repetition {{{1}}}",
+                pos, text
+            )
+        };
+        let parse_bound = |bound: &str| -> Result<Option<usize>, String> {
+            if bound.is_empty() {
+                return Ok(None);
+            }
+            bound.parse().map(Some).map_err(|_| malformed())
+        };
+
+        let (min, max) = match text.split_once(',') {
+            None => {
+                let count = parse_bound(text)?.ok_or_else(malformed)?;
+                (count, Some(count))
+            }
+            Some((min, max)) => {
+                let (min, max) = (parse_bound(min)?, parse_bound(max)?);
+                if min.is_none() && max.is_none() {
+                    return Err(malformed());
+                }
+                (min.unwrap_or(0), max)
+            }
+        };
+        if max.is_some_and(|max| max < min) {
+            return Err(format!(
+                "Character {{ at {0}: repetition {{{1}}} has its minimum above its maximum",
+                pos, text
+            ));
+        }
+
+        Ok(TokenType::Repeat {
+            min,
+            max,
+            greedy: true,
+        })
+    }
+
+    /// The scan function called right after the opening bracket.
+    ///
+    /// A hat (^) here negates the class. Otherwise, this is the same as `scan_bracket_first`.
+    ///
+    /// * `idx`: the string index.
+    /// * `input_char`:
+    fn scan_bracket_start(
+        &mut self,
+        idx: usize,
+        input_char: char,
+    ) -> Result<Option<TokenType>, String> {
+        if input_char == '^' {
+            self.bracket_data().class.negate();
+            self.curr_scan_fn = Scanner::scan_bracket_first;
+            return Ok(None);
+        }
+        self.scan_bracket_first(idx, input_char)
+    }
+
+    /// The scan function called for the first member of a bracket expression.
+    ///
+    /// A closing bracket (]) here is a literal character rather than the end of the class, as in
+    /// POSIX. Otherwise, this is the same as `scan_bracket`.
+    ///
+    /// * `idx`: the string index.
+    /// * `input_char`:
+    fn scan_bracket_first(
+        &mut self,
+        idx: usize,
+        input_char: char,
+    ) -> Result<Option<TokenType>, String> {
+        self.curr_scan_fn = Scanner::scan_bracket;
+        if input_char == ']' {
+            self.push_bracket_member(idx, ']')?;
+            return Ok(None);
+        }
+        self.scan_bracket(idx, input_char)
+    }
+
+    /// The scan function called inside a bracket expression.
+    ///
+    /// A dash (-) between two characters makes a range. A dash at the start or the end of the
+    /// class is a literal dash. Escapes mean the same as outside, and escaped punctuation is always
+    /// literal, so `\]` and `\-` can be used anywhere inside the class.
+    ///
+    /// * `idx`: the string index.
+    /// * `input_char`:
+    /// * Return: the finished `TokenType::CharClass` when the closing bracket is read.
+    fn scan_bracket(&mut self, idx: usize, input_char: char) -> Result<Option<TokenType>, String> {
+        match input_char {
+            ']' => {
+                self.curr_scan_fn = Scanner::scan_char;
+                let mut data = self.bracket.take().unwrap();
+                if let Some(prev) = data.prev {
+                    data.class.push_char(prev);
+                }
+                if data.dash {
+                    data.class.push_char('-');
+                }
+                data.class.canonicalize();
+                return Ok(Some(TokenType::CharClass(data.class)));
+            }
+            '\\' => self.start_escape(idx, true),
+            '[' if matches!(self.next_char, Some(':' | '=' | '.')) => {
+                self.bracket_data().name = Some((idx, String::new()));
+                self.curr_scan_fn = Scanner::scan_bracket_name;
+            }
+            '-' => {
+                let data = self.bracket_data();
+                if data.prev.is_some() && !data.dash {
+                    data.dash = true;
+                } else {
+                    self.push_bracket_member(idx, '-')?;
+                }
+            }
+            _ => self.push_bracket_member(idx, input_char)?,
+        }
+        Ok(None)
+    }
+
+    /// The scan function called inside a bracket expression after "[:", "[=" or "[.", up to the
+    /// matching ":]", "=]" or ".]".
+    ///
+    /// "[:name:]" is one of the POSIX classes. Only the C locale is supported, where the
+    /// equivalence class "[=c=]" and the collating symbol "[.c.]" are the single character c, so
+    /// those with a longer name, such as "[.hyphen.]", are errors. The collating symbol may start
+    /// or end a range, as in "[[.a.]-z]", but the equivalence class may not.
+    ///
+    /// * `idx`: the string index.
+    /// * `input_char`:
+    fn scan_bracket_name(
+        &mut self,
+        idx: usize,
+        input_char: char,
+    ) -> Result<Option<TokenType>, String> {
+        let (pos, text) = self.bracket_data().name.as_mut().unwrap();
+        let pos = *pos;
+        let kind = text.chars().next();
+        // the text starts with the delimiter, so it's closed once it ends with it again.
+        let closed = text.len() > 1 && text.ends_with(kind.unwrap());
+        if !(input_char == ']' && closed) {
+            // only letters may make the name of a POSIX class, after a hat (^) negating it.
+            let valid = kind != Some(':')
+                || !closed
+                    && (input_char.is_ascii_alphabetic()
+                        || input_char == ':'
+                        || input_char == '^' && text == ":");
+            if !valid {
+                return Err(format!(
+                    "Character {0} at {1}: unexpected in the POSIX class at {2}",
+                    input_char, idx, pos
+                ));
+            }
+            text.push(input_char);
+            return Ok(None);
+        }
+
+        let (_, text) = self.bracket_data().name.take().unwrap();
+        self.curr_scan_fn = Scanner::scan_bracket;
+        let name = &text[1..text.len() - 1];
+        let mut chars = name.chars();
+        match (kind, chars.next(), chars.next()) {
+            (Some(':'), _, _) => {
+                let (negated, name) = match name.strip_prefix('^') {
+                    Some(name) => (true, name),
+                    None => (false, name),
+                };
+                let Some(&(_, table)) = POSIX_CLASSES.iter().find(|&&(class, _)| class == name)
+                else {
+                    return Err(format!(
+                        "Character [ at {0}: unknown POSIX class [:{1}:]",
+                        pos, name
+                    ));
+                };
+                let mut class = CharClass::from_ranges(table);
+                if negated {
+                    class.negate();
+                }
+                self.push_bracket_class(pos, &class)?;
+            }
+            (Some('='), Some(c), None) => {
+                self.push_bracket_class(pos, &CharClass::from_ranges(&[(c, c)]))?
+            }
+            (_, Some(c), None) => self.push_bracket_member(idx, c)?,
+            (Some('='), _, _) => {
+                return Err(format!(
+                    "Character [ at {0}: the equivalence class [{1}] isn't a single character, \
+                     only the C locale is supported",
+                    pos, text
+                ))
+            }
+            _ => {
+                return Err(format!(
+                    "Character [ at {0}: the collating symbol [{1}] isn't a single character, \
+                     only the C locale is supported",
+                    pos, text
+                ))
+            }
+        }
+        Ok(None)
+    }
+
+    /// Adds a whole class, such as `\d`, to the bracket expression being scanned.
+    ///
+    /// * `idx`: the string index of the class.
+    /// * `class`: a canonicalized class.
+    fn push_bracket_class(&mut self, idx: usize, class: &CharClass) -> Result<(), String> {
+        let data = self.bracket_data();
+        if data.dash {
+            return Err(format!(
+                "Character {0} at {1}: a range can't end with the class {2}",
+                data.prev.unwrap(),
+                idx,
+                class
+            ));
+        }
+        if let Some(prev) = data.prev.take() {
+            data.class.push_char(prev);
+        }
+        data.class.push_class(class);
+        Ok(())
+    }
+
+    /// Adds a literal character to the bracket expression being scanned. If a dash precedes it,
+    /// the character closes a range.
+    ///
+    /// * `idx`: the string index.
+    /// * `input_char`:
+    fn push_bracket_member(&mut self, idx: usize, input_char: char) -> Result<(), String> {
+        let data = self.bracket_data();
+        match data.prev.take() {
+            Some(lo) if data.dash => {
+                data.dash = false;
+                if lo > input_char {
+                    return Err(format!(
+                        "Character {0} at {1}: range {2}-{0} is out of order",
+                        input_char, idx, lo
+                    ));
+                }
+                data.class.push_range(lo, input_char);
+            }
+            Some(prev) => {
+                data.class.push_char(prev);
+                data.prev = Some(input_char);
+            }
+            None => data.prev = Some(input_char),
+        }
+        Ok(())
+    }
+
+    #[inline]
+    /// Returns the bracket expression being scanned. Only valid inside bracket mode.
+    fn bracket_data(&mut self) -> &mut BracketData {
+        self.bracket.as_mut().unwrap()
     }
 
     /// Reverses the token list, or in other words, convert the token list held by this scanner
@@ -100,6 +1001,12 @@ impl<'a> Scanner<'a> {
         self.token_list.reverse();
     }
 
+    /// Takes the name of each capture group out of this scanner. Element 0 is group 0, the whole
+    /// match.
+    pub fn take_capture_names(&mut self) -> Vec<Option<String>> {
+        std::mem::take(&mut self.capture_names)
+    }
+
     /// Takes the token list from this scanner and incinerate the scanner.
     pub fn move_vec(self) -> Vec<Token> {
         self.token_list
@@ -124,42 +1031,80 @@ impl<'a> Scanner<'a> {
         self.token_list.push(Token::new(idx, token));
     }
 
-    /// Handles pushing hat (^) token onto the stack.
-    ///
-    /// This is the easiest place to catch any error related to this anchor. For the dollar anchor,
-    /// it's easier to catch errors during the postfix conversion phase.
+    /// Pushes an empty operand if the beam (|) or right parentheses about to be pushed has nothing
+    /// on its left, as in "()", "(|a)" or "a|". Without it, the parser would take whatever NFA
+    /// happens to be before the parentheses as the operand.
     ///
-    /// * `idx`:
-    fn handle_push_hat(&mut self, idx: usize) -> Result<(), String> {
+    /// * `idx`: the string index.
+    fn push_missing_operand(&mut self, idx: usize) {
         match self.token_list.last() {
-            None => self.push_need_concat(idx, TokenType::Hat),
-            Some(tok) => {
-                if !(tok.token_type == TokenType::LParen || tok.token_type == TokenType::Beam) {
-                    return Err(format!(
-                        "Character {0} at {1}: {0} not at beginning of statement",
-                        TokenType::Hat,
-                        idx
-                    ));
+            None
+            | Some(Token {
+                token_type: TokenType::LParen(_) | TokenType::Beam,
+                ..
+            }) => self.token_list.push(Token::new(idx, TokenType::Empty)),
+            Some(_) => (),
+        }
+    }
+
+    /// Rewrites a token according to the flags in effect: characters and classes match both
+    /// cases under the i flag, and the dot matches \n under the s flag. The flags of ^ and $ are
+    /// read by `Scanner::scan_char`, since \A and \z scan to the same tokens but ignore them.
+    ///
+    /// * `token`: the token scanned.
+    /// * Return: the token to push.
+    fn apply_flags(&self, token: TokenType) -> TokenType {
+        match token {
+            TokenType::Character(c) if self.flags.case_insensitive => {
+                let mut class = CharClass::new();
+                class.push_char(c);
+                class.case_fold();
+                if class.ranges() == [(c, c)] {
+                    TokenType::Character(c)
+                } else {
+                    TokenType::CharClass(class)
                 }
-                self.push_need_concat(idx, TokenType::Hat);
             }
+            TokenType::CharClass(mut class) if self.flags.case_insensitive => {
+                class.case_fold();
+                TokenType::CharClass(class)
+            }
+            TokenType::Dot if self.flags.dot_matches_new_line => {
+                TokenType::CharClass(CharClass::any())
+            }
+            _ => token,
+        }
+    }
+
+    /// Makes the quantifier just pushed lazy, for the ? read right after it, as in "*?". A
+    /// quantifier already lazy stays as it is, so that "a*??" is the lazy star made optional.
+    ///
+    /// * Return: whether there was a greedy quantifier to make lazy. If not, the ? is a quantifier
+    ///   itself.
+    fn make_last_lazy(&mut self) -> bool {
+        match self.token_list.last_mut().map(|tok| &mut tok.token_type) {
+            Some(
+                TokenType::QuestionMark { greedy }
+                | TokenType::Star { greedy }
+                | TokenType::Plus { greedy }
+                | TokenType::Repeat { greedy, .. },
+            ) if *greedy => {
+                *greedy = false;
+                true
+            }
+            _ => false,
         }
-        Ok(())
     }
 
-    /// Handles pushing star (*), plus (+) and question mark (?) onto the stack.
-    /// These are single-character symbols, hence the name.
+    /// Handles pushing star (*), plus (+), question mark (?) and bounded repetition ({m,n}) onto
+    /// the stack.
     ///
     /// This function checks whether these tokens are placed at the start of a statement, if yes,
     /// complain, and if not, push them to the list.
     ///
     /// * `idx`: the string index
     /// * `token`: the token type
-    fn handle_push_single_quantifier(
-        &mut self,
-        idx: usize,
-        token: TokenType,
-    ) -> Result<(), String> {
+    fn handle_push_quantifier(&mut self, idx: usize, token: TokenType) -> Result<(), String> {
         match self.token_list.last() {
             None => {
                 return Err(format!(
@@ -169,7 +1114,7 @@ impl<'a> Scanner<'a> {
             }
 
             Some(tok) => {
-                if tok.token_type == TokenType::LParen || tok.token_type == TokenType::Beam {
+                if matches!(tok.token_type, TokenType::LParen(_) | TokenType::Beam) {
                     return Err(format!(
                         "Character {0} at {1}: {0} at beginning of statement",
                         token, idx
@@ -8,7 +8,7 @@ fn simple_scan_test() {
     // I can test private methods?
     let res_vec: &Vec<Token> = &test_scanner.token_list;
     let outputs: [TokenType; 12] = [
-        TokenType::LParen,
+        TokenType::LParen(Some(1)),
         TokenType::Character('a'),
         TokenType::Concat,
         TokenType::Character('b'),
@@ -19,7 +19,7 @@ fn simple_scan_test() {
         TokenType::Character('.'),
         TokenType::Concat,
         TokenType::Dot,
-        TokenType::Star,
+        TokenType::Star { greedy: true },
     ];
 
     let mut idx = 0;
@@ -33,3 +33,381 @@ fn simple_scan_test() {
         println!("{}", out);
     }
 }
+
+#[test]
+fn bracket_scan_test() {
+    let mut test_scanner = Scanner::new("[a-z][^\\n\"]x[]\\-a-]");
+    test_scanner.scan().unwrap();
+    let res_vec: &Vec<Token> = &test_scanner.token_list;
+    assert_eq!(res_vec.len(), 7);
+
+    let expected_ranges: [&[(char, char)]; 3] = [
+        &[('a', 'z')],
+        &[('\n', '\n'), ('"', '"')],
+        &[('-', '-'), (']', ']'), ('a', 'a')],
+    ];
+    let classes = res_vec.iter().filter_map(|tok| match &tok.token_type {
+        TokenType::CharClass(class) => Some(class),
+        _ => None,
+    });
+    for (class, ranges) in classes.zip(expected_ranges.iter()) {
+        assert_eq!(class.ranges(), *ranges);
+    }
+    assert_eq!(res_vec[3].token_type, TokenType::Concat);
+    assert_eq!(res_vec[4].token_type, TokenType::Character('x'));
+
+    assert!(Scanner::new("[a-z").scan().is_err());
+    assert!(Scanner::new("[z-a]").scan().is_err());
+}
+
+#[test]
+fn escape_scan_test() {
+    let scanned = |pattern: &str| {
+        let mut test_scanner = Scanner::new(pattern);
+        test_scanner.scan().unwrap();
+        test_scanner
+            .token_list
+            .into_iter()
+            .map(|tok| tok.token_type)
+            .filter(|token_type| *token_type != TokenType::Concat)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        scanned("\\n\\t\\r\\f\\v\\0\\.\\x41\\x{e9}\\u{1F600}"),
+        ['\n', '\t', '\r', '\x0C', '\x0B', '\0', '.', 'A', 'é', '😀'].map(TokenType::Character)
+    );
+
+    let [TokenType::CharClass(digit), TokenType::CharClass(not_word)] = &scanned("\\d\\W")[..]
+    else {
+        panic!("\\d and \\W should scan to classes");
+    };
+    assert!(digit.matches('7') && digit.matches('٣') && !digit.matches('a'));
+    assert!(not_word.matches(' ') && !not_word.matches('é') && !not_word.matches('_'));
+
+    // inside brackets, next to other members.
+    let [TokenType::CharClass(class)] = &scanned("[\\s\\x2Da-c\\d]")[..] else {
+        panic!("[\\s\\x2Da-c\\d] should scan to a class");
+    };
+    for c in [' ', '\u{2003}', '-', 'b', '9'] {
+        assert!(class.matches(c), "{c:?} should match");
+    }
+    assert!(!class.matches('d'));
+    let [TokenType::CharClass(class)] = &scanned("[^\\S\\n]")[..] else {
+        panic!("[^\\S\\n] should scan to a class");
+    };
+    assert!(class.matches(' ') && !class.matches('\n') && !class.matches('a'));
+
+    let tokens = scanned("\\b(?a)\\B\\d");
+    assert_eq!(
+        tokens[..2],
+        [
+            TokenType::WordBoundary { ascii: false },
+            TokenType::NotWordBoundary { ascii: true },
+        ]
+    );
+    let [.., TokenType::CharClass(digit)] = &tokens[..] else {
+        panic!("\\d should scan to a class");
+    };
+    assert_eq!(digit.ranges(), [('0', '9')]);
+    let [TokenType::CharClass(greek), TokenType::CharClass(not_letter)] =
+        &scanned("[\\p{Greek}\\d]\\PL")[..]
+    else {
+        panic!("\\p and \\P should scan to classes");
+    };
+    assert!(greek.matches('λ') && greek.matches('9') && !greek.matches('a'));
+    assert!(not_letter.matches('9') && !not_letter.matches('λ'));
+    // \A and \z are ^ and $ without the multi-line flag, whatever the flags.
+    assert_eq!(
+        scanned("(?mR)\\A^$\\z\\Z"),
+        [
+            TokenType::Hat,
+            TokenType::LineStart { crlf: true },
+            TokenType::LineEnd { crlf: true },
+            TokenType::Dollar,
+            TokenType::EndNewLine,
+        ]
+    );
+
+    for bad in [
+        "\\q",
+        "\\1",
+        "[\\b]",
+        "[\\Z]",
+        "[\\k]",
+        "\\",
+        "a\\x",
+        "\\x4",
+        "\\xG1",
+        "\\x{}",
+        "\\x{110000}",
+        "\\x{D800}",
+        "\\u41",
+        "\\u{41",
+        "[a-\\d]",
+        "\\p",
+        "\\p{Greek",
+        "\\p{}",
+        "\\p{Klingon}",
+        "\\p1",
+        "\\p{Foo=Greek}",
+        "[a-\\pL]",
+    ] {
+        assert!(Scanner::new(bad).scan().is_err(), "{bad} should not scan");
+    }
+    let err = Scanner::new("ab\\q").scan().unwrap_err();
+    assert!(err.contains("at 3"), "{err}");
+}
+
+#[test]
+fn repeat_scan_test() {
+    let mut test_scanner = Scanner::new("a{3}b{2,}c{,4}d{1,5}");
+    test_scanner.scan().unwrap();
+    let repeats: Vec<&TokenType> = test_scanner
+        .token_list
+        .iter()
+        .map(|tok| &tok.token_type)
+        .filter(|tok| matches!(tok, TokenType::Repeat { .. }))
+        .collect();
+    assert_eq!(
+        repeats,
+        [
+            &TokenType::Repeat {
+                min: 3,
+                max: Some(3),
+                greedy: true
+            },
+            &TokenType::Repeat {
+                min: 2,
+                max: None,
+                greedy: true
+            },
+            &TokenType::Repeat {
+                min: 0,
+                max: Some(4),
+                greedy: true
+            },
+            &TokenType::Repeat {
+                min: 1,
+                max: Some(5),
+                greedy: true
+            },
+        ]
+    );
+
+    for bad in ["a{", "a{,}", "a{}", "a{3,2}", "a{1,2,3}", "a{x}", "{2}"] {
+        assert!(Scanner::new(bad).scan().is_err(), "{bad} should not scan");
+    }
+}
+
+#[test]
+fn lazy_quantifier_scan_test() {
+    let mut test_scanner = Scanner::new("a*?b+?c??d{2,3}?e*??f?+");
+    test_scanner.scan().unwrap();
+    let quantifiers: Vec<TokenType> = test_scanner
+        .token_list
+        .into_iter()
+        .map(|tok| tok.token_type)
+        .filter(|tok| tok.is_symbol() && *tok != TokenType::Concat)
+        .collect();
+    assert_eq!(
+        quantifiers,
+        [
+            TokenType::Star { greedy: false },
+            TokenType::Plus { greedy: false },
+            TokenType::QuestionMark { greedy: false },
+            TokenType::Repeat {
+                min: 2,
+                max: Some(3),
+                greedy: false
+            },
+            // a ? after a lazy quantifier is another quantifier.
+            TokenType::Star { greedy: false },
+            TokenType::QuestionMark { greedy: true },
+            TokenType::QuestionMark { greedy: true },
+            TokenType::Plus { greedy: true },
+        ]
+    );
+
+    // an escaped ? is a character.
+    let mut test_scanner = Scanner::new("a*\\?");
+    test_scanner.scan().unwrap();
+    assert_eq!(
+        test_scanner.token_list.last().unwrap().token_type,
+        TokenType::Character('?')
+    );
+    assert!(Scanner::new("*?").scan().is_err());
+    assert!(Scanner::new("(??)").scan().is_err());
+}
+
+#[test]
+fn named_group_scan_test() {
+    let mut test_scanner = Scanner::new("(?P<year>[0-9]{4})-(?<month>..)(x)");
+    test_scanner.scan().unwrap();
+    assert_eq!(
+        test_scanner.token_list[0].token_type,
+        TokenType::LParen(Some(1))
+    );
+    assert_eq!(
+        test_scanner.take_capture_names(),
+        [
+            None,
+            Some("year".to_string()),
+            Some("month".to_string()),
+            None
+        ]
+    );
+
+    for bad in [
+        "(?P<a>x)(?P<a>y)",
+        "(?<>x)",
+        "(?<1a>x)",
+        "(?<a-b>x)",
+        "(?P<a",
+        "(?Pa>x)",
+        "(?",
+    ] {
+        assert!(Scanner::new(bad).scan().is_err(), "{bad} should not scan");
+    }
+    let err = Scanner::new("ab(?<x>.)(?<x>.)").scan().unwrap_err();
+    assert!(err.contains("at 9"), "{err}");
+}
+
+#[test]
+fn flag_group_scan_test() {
+    let mut test_scanner = Scanner::new("(?:a)(?i)b(?-i:c)(?s).(?m)$");
+    test_scanner.scan().unwrap();
+    let token_types: Vec<&TokenType> = test_scanner
+        .token_list
+        .iter()
+        .map(|tok| &tok.token_type)
+        .filter(|tok| **tok != TokenType::Concat)
+        .collect();
+    let mut folded_b = CharClass::new();
+    folded_b.push_char('b');
+    folded_b.case_fold();
+    assert_eq!(
+        token_types,
+        [
+            &TokenType::LParen(None),
+            &TokenType::Character('a'),
+            &TokenType::RParen,
+            &TokenType::CharClass(folded_b),
+            &TokenType::LParen(None),
+            &TokenType::Character('c'),
+            &TokenType::RParen,
+            &TokenType::CharClass(CharClass::any()),
+            &TokenType::LineEnd { crlf: false },
+        ]
+    );
+    // (?:...) doesn't take a group number.
+    assert_eq!(test_scanner.take_capture_names(), [None]);
+
+    let mut test_scanner = Scanner::new("(?x) a b # comment\n c\\ ");
+    test_scanner.scan().unwrap();
+    assert_eq!(test_scanner.token_list.len(), 7);
+
+    for bad in ["(?)", "(?i", "(?y)", "(?i-)", "(?-)", "(?i-s-m)", "(?P:a)"] {
+        assert!(Scanner::new(bad).scan().is_err(), "{bad} should not scan");
+    }
+}
+
+#[test]
+fn lookaround_scan_test() {
+    let mut test_scanner = Scanner::new("(?<!a)b(?=c|)(?i:(?!d))");
+    test_scanner.scan().unwrap();
+    let token_types: Vec<&TokenType> = test_scanner
+        .token_list
+        .iter()
+        .map(|tok| &tok.token_type)
+        .filter(|tok| **tok != TokenType::Concat)
+        .collect();
+    let mut folded_d = CharClass::new();
+    folded_d.push_char('d');
+    folded_d.case_fold();
+    // the closing parentheses is followed by the kind of the lookaround.
+    assert_eq!(
+        token_types,
+        [
+            &TokenType::LParen(None),
+            &TokenType::Character('a'),
+            &TokenType::RParen,
+            &TokenType::Lookaround(LookKind::NotBehind),
+            &TokenType::Character('b'),
+            &TokenType::LParen(None),
+            &TokenType::Character('c'),
+            &TokenType::Beam,
+            &TokenType::Empty,
+            &TokenType::RParen,
+            &TokenType::Lookaround(LookKind::Ahead),
+            &TokenType::LParen(None),
+            &TokenType::LParen(None),
+            &TokenType::CharClass(folded_d),
+            &TokenType::RParen,
+            &TokenType::Lookaround(LookKind::NotAhead),
+            &TokenType::RParen,
+        ]
+    );
+    assert_eq!(test_scanner.take_capture_names(), [None]);
+
+    // a lookaround may hold a non-capturing group, but no capture group or other lookaround.
+    assert!(Scanner::new("(?<=(?:ab)+)c").scan().is_ok());
+    let err = Scanner::new("a(?=b(c))").scan().unwrap_err();
+    assert!(err.contains("at 5") && err.contains("capture"), "{err}");
+    let err = Scanner::new("(?=a)(?!b(?<=c))").scan().unwrap_err();
+    assert!(err.contains("at 9") && err.contains("nested"), "{err}");
+    for bad in ["(?=(?P<x>a))", "(?P<=a)", "(?<", "(?<>a)"] {
+        assert!(Scanner::new(bad).scan().is_err(), "{bad} should not scan");
+    }
+}
+
+#[test]
+fn posix_class_scan_test() {
+    let class_of = |pattern: &str| {
+        let mut test_scanner = Scanner::new(pattern);
+        test_scanner.scan().unwrap();
+        match test_scanner.token_list.pop().unwrap().token_type {
+            TokenType::CharClass(class) => class,
+            token_type => panic!("{pattern} should scan to a class, not {token_type}"),
+        }
+    };
+    assert_eq!(
+        class_of("[[:alpha:][:digit:]_]").ranges(),
+        [('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')]
+    );
+    assert_eq!(
+        class_of("[[:punct:]]").ranges(),
+        [('!', '/'), (':', '@'), ('[', '`'), ('{', '~')]
+    );
+    let not_space = class_of("[[:^space:]]");
+    assert!(not_space.matches('a') && !not_space.matches('\t') && !not_space.matches(' '));
+    let not_upper = class_of("[^[:upper:]]");
+    assert!(not_upper.matches('a') && !not_upper.matches('Q'));
+
+    // single characters, as in the C locale, which may also start or end a range.
+    assert_eq!(class_of("[[=a=]b]").ranges(), [('a', 'b')]);
+    assert_eq!(class_of("[[.-.]]").ranges(), [('-', '-')]);
+    assert_eq!(class_of("[[.].]x]").ranges(), [(']', ']'), ('x', 'x')]);
+    assert_eq!(class_of("[[.a.]-[.c.]]").ranges(), [('a', 'c')]);
+    // a [ not followed by :, = or . is a literal.
+    assert_eq!(class_of("[[a]").ranges(), [('[', '['), ('a', 'a')]);
+
+    for bad in [
+        "[[:alpha:]",
+        "[[:alpha]]",
+        "[[:word:]]",
+        "[[:Alpha:]]",
+        "[[:al pha:]]",
+        "[[:alpha:x]]",
+        "[[:alpha^:]]",
+        "[a-[:digit:]]",
+        "[[=aleph=]]",
+        "[[.hyphen.]]",
+        "[[.NIL.]]",
+        "[a-[=b=]]",
+        "[[==]]",
+    ] {
+        assert!(Scanner::new(bad).scan().is_err(), "{bad} should not scan");
+    }
+    let err = Scanner::new("ab[[.hyphen.]]").scan().unwrap_err();
+    assert!(err.contains("at 3") && err.contains("[.hyphen.]"), "{err}");
+}
@@ -1,5 +1,7 @@
 use std::fmt;
 
+use super::char_class::CharClass;
+
 /// The types of token. The integer representation makrs the precedence for symbols,
 /// which is needed when parsing. The characters (Character and Dot) don't need precedence,
 /// but they still have their integer number to help distinguishing.
@@ -7,24 +9,119 @@ use std::fmt;
 #[repr(u8)]
 pub enum TokenType {
     Empty = 0,
-    LParen,
+    /// Opens a group. Holds the capture group index, counting from 1 in the order of the opening
+    /// parentheses, or `None` if the group doesn't capture.
+    LParen(Option<usize>),
     RParen,
     Beam,
     /// Simply concatenates the 2 tokens it stands between
     Concat,
-    QuestionMark,
-    Plus,
-    Star,
+    /// The quantifiers. Each is greedy, taking as many repetitions as it can first, unless a ?
+    /// right after it makes it lazy, as in `*?`, taking as few as it can first. Which one is taken
+    /// first only decides the match under `MatchKind::LeftmostFirst`, and the capture groups.
+    QuestionMark {
+        greedy: bool,
+    },
+    Plus {
+        greedy: bool,
+    },
+    Star {
+        greedy: bool,
+    },
+    /// Bounded repetition: `{m}`, `{m,}`, `{,n}` or `{m,n}`. `max` is `None` if unbounded.
+    Repeat {
+        min: usize,
+        max: Option<usize>,
+        greedy: bool,
+    },
+    /// Only produced by the postfix converter, in place of a pair of parentheses. Wraps the NFA
+    /// before it in a capture group, with the group index held.
+    Group(usize),
+    /// A NFA state that records the current string position into the capture slot held. Matches
+    /// like `Empty`. Capture group `i` uses slots `2i` (start) and `2i + 1` (end).
+    Save(usize),
+    /// ^, or \A: matches at the start of the string.
     Hat,
+    /// $, or \z: matches at the end of the string.
     Dollar,
+    /// ^ under the multi-line flag: matches at the start of the string, or after a \n. `crlf` is
+    /// set under the CRLF flag, where a \r alone ends a line too, and \r\n is a single line
+    /// ending: ^ matches after it, not between the two.
+    LineStart {
+        crlf: bool,
+    },
+    /// $ under the multi-line flag: matches at the end of the string, or before a \n. `crlf` is
+    /// set under the CRLF flag, where $ also matches before a \r, but not between \r and \n.
+    LineEnd {
+        crlf: bool,
+    },
+    /// \Z: matches at the end of the string, or right before a \n ending it.
+    EndNewLine,
+    /// \b: matches between a word character and something else, a non-word character or either
+    /// end of the string. `ascii` is set under the ASCII flag, where only [0-9A-Za-z_] are word
+    /// characters rather than everything \w matches.
+    WordBoundary {
+        ascii: bool,
+    },
+    /// \B: matches wherever `WordBoundary` doesn't.
+    NotWordBoundary {
+        ascii: bool,
+    },
+    /// Only produced by the scanner, right after the parentheses closing a lookaround group, such
+    /// as `(?=...)`. Turns the NFA before it into a lookaround assertion, the same way `Group`
+    /// turns it into a capture group.
+    Lookaround(LookKind),
+    /// A NFA state that matches like `Empty`, but only where the lookaround assertion held holds.
+    /// Holds its index among the lookarounds of the expression.
+    Assert(usize),
     /// Characters don't have precedence
     Character(char),
     Dot,
+    /// A bracket expression such as `[a-z]` or `[^\n"]`. Matches one character, like `Dot`.
+    CharClass(CharClass),
     /// The actual token is expected to be the next token.
     /// This token is not pushed to the token list when scanning the input string.
     Escape,
 }
 
+/// The four lookaround assertions. They look at the text after or before the position, without
+/// consuming it.
+#[derive(PartialEq, Eq, PartialOrd, Clone, Copy, Debug)]
+pub enum LookKind {
+    /// `(?=...)`: the text after the position starts with a match.
+    Ahead,
+    /// `(?!...)`: the text after the position doesn't start with a match.
+    NotAhead,
+    /// `(?<=...)`: the text before the position ends with a match.
+    Behind,
+    /// `(?<!...)`: the text before the position doesn't end with a match.
+    NotBehind,
+}
+
+impl LookKind {
+    /// Returns whether the assertion looks at the text after the position.
+    pub(crate) fn ahead(self) -> bool {
+        matches!(self, LookKind::Ahead | LookKind::NotAhead)
+    }
+
+    /// Returns whether the assertion holds where there's no match, rather than where there's one.
+    pub(crate) fn negated(self) -> bool {
+        matches!(self, LookKind::NotAhead | LookKind::NotBehind)
+    }
+}
+
+impl fmt::Display for LookKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LookKind::Ahead => write!(f, "(?="),
+            LookKind::NotAhead => write!(f, "(?!"),
+            LookKind::Behind => write!(f, "(?<="),
+            LookKind::NotBehind => write!(f, "(?<!"),
+        }
+    }
+}
+
+#[derive(Clone)]
 pub(crate) struct Token {
     pub pos: usize,
     pub token_type: TokenType,
@@ -32,7 +129,10 @@ pub(crate) struct Token {
 
 impl Token {
     pub fn new(pos: usize, token: TokenType) -> Self {
-        Self { pos, token_type: token }
+        Self {
+            pos,
+            token_type: token,
+        }
     }
 }
 
@@ -53,16 +153,30 @@ impl TokenType {
     /// Parentheses,
     /// Beam,
     /// Concatenation operation,
-    /// Quantifiers (for now, star, question mark and plus).
+    /// Quantifiers (star, question mark, plus and bounded repetition).
     /// Characters always go straight into the postfix stack, so they have the highest precedence.
     pub(crate) fn precedence(&self) -> u8 {
         match self {
-            TokenType::LParen | TokenType::RParen => 0,
+            TokenType::LParen(_) | TokenType::RParen => 0,
             TokenType::Beam => 1,
             TokenType::Concat => 2,
-            TokenType::QuestionMark | TokenType::Plus | TokenType::Star => 3,
+            TokenType::QuestionMark { .. }
+            | TokenType::Plus { .. }
+            | TokenType::Star { .. }
+            | TokenType::Repeat { .. }
+            | TokenType::Group(_)
+            | TokenType::Lookaround(_) => 3,
             // non-symbols anyways.
-            TokenType::Empty | TokenType::Hat | TokenType::Dollar => 4,
+            TokenType::Empty
+            | TokenType::Save(_)
+            | TokenType::Hat
+            | TokenType::Dollar
+            | TokenType::LineStart { .. }
+            | TokenType::LineEnd { .. }
+            | TokenType::EndNewLine
+            | TokenType::WordBoundary { .. }
+            | TokenType::NotWordBoundary { .. }
+            | TokenType::Assert(_) => 4,
             _ => 5,
         }
     }
@@ -72,7 +186,7 @@ impl TokenType {
     /// Using the convention described at the `precedence` function, a symbol's precedence is less
     /// than that of characters.
     pub(crate) fn is_symbol(&self) -> bool {
-        self.precedence() <= TokenType::precedence(&TokenType::Star)
+        self.precedence() <= TokenType::precedence(&TokenType::Star { greedy: true })
     }
 
     /// Returns whether the next token needs to be preceded by concatenation, if that token is not
@@ -83,7 +197,41 @@ impl TokenType {
     /// The escape character is handled by the lexer: it simply uses the concatenation value of the
     /// token before it.
     pub(crate) fn need_concat_next(&self) -> bool {
-        !matches!(self, TokenType::LParen | TokenType::Beam)
+        !matches!(self, TokenType::LParen(_) | TokenType::Beam)
+    }
+
+    /// Returns whether this token consumes a character when its NFA state is entered. The other
+    /// tokens inside a NFA (empty, saves, anchors and lookarounds) are passed through without
+    /// consuming.
+    pub(crate) fn consumes(&self) -> bool {
+        matches!(
+            self,
+            TokenType::Character(_) | TokenType::Dot | TokenType::CharClass(_)
+        )
+    }
+
+    #[inline]
+    /// Returns whether the NFA state holding this token can be entered by consuming the character
+    /// passed in. Always `false` for the tokens that don't consume.
+    ///
+    /// * `c`:
+    pub(crate) fn accepts(&self, c: char) -> bool {
+        match self {
+            TokenType::Character(expected) => *expected == c,
+            TokenType::Dot => c != '\n',
+            TokenType::CharClass(class) => class.matches(c),
+            _ => false,
+        }
+    }
+}
+
+/// Returns the ? written after a lazy quantifier.
+///
+/// * `greedy`:
+fn lazy_suffix(greedy: bool) -> &'static str {
+    match greedy {
+        true => "",
+        false => "?",
     }
 }
 
@@ -93,13 +241,31 @@ impl fmt::Display for TokenType {
             TokenType::Empty => write!(f, "Empty"),
             TokenType::Character(c) => write!(f, "{}", c),
             TokenType::Dot => write!(f, "."),
-            TokenType::Star => write!(f, "*"),
+            TokenType::CharClass(class) => write!(f, "{}", class),
+            TokenType::Star { greedy } => write!(f, "*{}", lazy_suffix(*greedy)),
             TokenType::Beam => write!(f, "|"),
-            TokenType::QuestionMark => write!(f, "?"),
-            TokenType::Plus => write!(f, "+"),
-            TokenType::Hat => write!(f, "^"),
-            TokenType::Dollar => write!(f, "$"),
-            TokenType::LParen => write!(f, "("),
+            TokenType::QuestionMark { greedy } => write!(f, "?{}", lazy_suffix(*greedy)),
+            TokenType::Plus { greedy } => write!(f, "+{}", lazy_suffix(*greedy)),
+            TokenType::Repeat {
+                min,
+                max: None,
+                greedy,
+            } => write!(f, "{{{min},}}{}", lazy_suffix(*greedy)),
+            TokenType::Repeat {
+                min,
+                max: Some(max),
+                greedy,
+            } => write!(f, "{{{min},{max}}}{}", lazy_suffix(*greedy)),
+            TokenType::Hat | TokenType::LineStart { .. } => write!(f, "^"),
+            TokenType::Dollar | TokenType::LineEnd { .. } => write!(f, "$"),
+            TokenType::EndNewLine => write!(f, "\\Z"),
+            TokenType::WordBoundary { .. } => write!(f, "\\b"),
+            TokenType::NotWordBoundary { .. } => write!(f, "\\B"),
+            TokenType::LParen(_) => write!(f, "("),
+            TokenType::Group(idx) => write!(f, "Group {idx}"),
+            TokenType::Save(slot) => write!(f, "Save {slot}"),
+            TokenType::Lookaround(kind) => write!(f, "{kind}...)"),
+            TokenType::Assert(idx) => write!(f, "Lookaround {idx}"),
             TokenType::RParen => write!(f, ")"),
             TokenType::Escape => write!(f, "\\"),
             TokenType::Concat => write!(f, "Concatenation"),
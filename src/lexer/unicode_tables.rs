@@ -0,0 +1,50 @@
+// Unicode data tables, generated by `scripts/generate_unicode_tables.pl`. Run the script again to
+// update them to a newer Unicode version, rather than editing them by hand.
+pub mod case_folding;
+pub mod general_category;
+pub mod perl_classes;
+pub mod property_bool;
+pub mod script;
+
+/// Returns the characters with the Unicode property passed in, as \p{...} names it.
+///
+/// The name is either a general category (L, Letter, Lu, Uppercase_Letter, ...), a script (Greek,
+/// Grek, ...), or a binary property (Alphabetic, White_Space, Emoji, ...), looked up in that
+/// order. Or it's a general category or a script given with its property, as in gc=L or
+/// Script=Greek. As UTS #18 suggests, case, spaces, dashes and underscores don't matter, and an
+/// "is" prefix may be added, as in IsGreek.
+///
+/// * `name`:
+/// * Return: the sorted ranges of the characters, or `None` if there's no such property.
+pub(crate) fn property(name: &str) -> Option<&'static [(char, char)]> {
+    fn loose(name: &str) -> String {
+        name.chars()
+            .filter(|c| !matches!(c, ' ' | '_' | '-'))
+            .flat_map(char::to_lowercase)
+            .collect()
+    }
+    type Table = &'static [(&'static str, &'static [(char, char)])];
+
+    let (tables, value): (&[Table], String) = match name.split_once('=') {
+        None => (
+            &[
+                general_category::BY_NAME,
+                script::BY_NAME,
+                property_bool::BY_NAME,
+            ],
+            loose(name),
+        ),
+        Some((key, value)) => match loose(key).as_str() {
+            "gc" | "generalcategory" => (&[general_category::BY_NAME], loose(value)),
+            "sc" | "script" => (&[script::BY_NAME], loose(value)),
+            _ => return None,
+        },
+    };
+    let find = |value: &str| {
+        tables.iter().find_map(|table| {
+            let idx = table.binary_search_by_key(&value, |&(name, _)| name).ok()?;
+            Some(table[idx].1)
+        })
+    };
+    find(&value).or_else(|| find(value.strip_prefix("is")?))
+}
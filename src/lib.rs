@@ -1,7 +1,7 @@
 #![warn(clippy::all)]
 pub mod rare;
-pub use rare::RARE;
+pub use rare::{Cache, Captures, CapturesIter, DfaSize, MatchKind, RareBuilder, RARE};
 
-mod parser;
 mod lexer;
+mod parser;
 mod postfix_converter;
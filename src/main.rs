@@ -1,17 +1,23 @@
 use std::{
-    io::{self, BufRead, Write},
+    io::{self, BufRead},
     process::exit,
 };
 
-use rare::RARE;
+use rare::RareBuilder;
 
 fn main() -> io::Result<()> {
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() != 2 {
-        panic!("Expected 1 argument")
-    }
+    // usage: rare [-i] PATTERN
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (case_insensitive, pattern) = match args.as_slice() {
+        [pattern] => (false, pattern),
+        [flag, pattern] if flag == "-i" => (true, pattern),
+        _ => panic!("Expected 1 argument, optionally preceded by -i"),
+    };
 
-    let rare = match RARE::new(&args[1]) {
+    let rare = match RareBuilder::new(pattern)
+        .case_insensitive(case_insensitive)
+        .build()
+    {
         Ok(r) => r,
         Err(msg) => {
             println!("{msg}");
@@ -88,13 +94,15 @@ fn main() -> io::Result<()> {
     // is match or not only. No highlight.
     // scales way better. Although still smoked by grep.
     let in_handle = io::stdin().lock();
+    // reused for every line, so matching doesn't allocate once per line.
+    let mut cache = rare.create_cache();
     for buf_result in in_handle.lines() {
         let buf = if let Ok(ret) = buf_result {
             ret
         } else {
             continue;
         };
-        if rare.is_match(&buf) {
+        if rare.is_match_with(&mut cache, &buf) {
             println!("{buf}");
         }
     }
@@ -1,13 +1,22 @@
-use crate::{lexer::token_type::Token, rare::RARE};
+use crate::{
+    lexer::token_type::{LookKind, Token},
+    rare::{Lookaround, Lookarounds, Options, RARE},
+};
 
+pub(crate) mod byte_classes;
+pub(crate) mod closure;
+pub(crate) mod glushkov;
+pub(crate) mod literals;
 pub(crate) mod nfa;
 pub(crate) mod state;
 
+use glushkov::Glushkov;
+use literals::Literals;
 use nfa::Nfa;
 use state::State;
 
 use crate::{
-    lexer::{scanner::Scanner, token_type::TokenType},
+    lexer::{flags::Flags, scanner::Scanner, token_type::TokenType},
     postfix_converter::PostfixConverter,
 };
 
@@ -15,13 +24,24 @@ use crate::{
 /// The parser elements should not be accessed by the user manually. Instead, retrieve the regular
 /// expression using `Parser::parse`.
 ///
-/// * `postfix_stack`: the postfix stack passed in. It is assumed that this stack is provided by
-///                    the postfix converter.
+/// * `postfix_stack`: the postfix stack passed in. It is assumed that this stack is provided by the
+///   postfix converter.
 /// * `nfa_stack`: a temporary NFA stack. After `Parser::parse`, the stack should only have at most
-///                2 NFAs left inside.
+///   2 NFAs left inside.
+/// * `options`: the compile options.
+/// * `capture_names`: the name of each capture group, `None` if unnamed. Element 0 is group 0, the
+///   whole match.
+/// * `repeated_groups`: whether each capture group is inside a quantifier that may go through it
+///   more than once, indexed like `capture_names`.
+/// * `lookarounds`: the lookarounds compiled so far, which the `TokenType::Assert` states of the
+///   NFA refer to.
 pub struct Parser {
     postfix_stack: Vec<Token>,
     nfa_stack: Vec<Nfa>,
+    options: Options,
+    capture_names: Vec<Option<String>>,
+    repeated_groups: Vec<bool>,
+    lookarounds: Lookarounds,
 }
 impl Parser {
     /// Constructs a parser from the regex string passed in.
@@ -31,11 +51,26 @@ impl Parser {
     ///
     /// * `regex`: regular expression string.
     /// * Return: the newly constructed `Parser` if successful, otherwise, a string describing the
-    ///           error is returned.
+    ///   error is returned.
     pub fn new(regex: &str) -> Result<Self, String> {
+        Self::with_options(regex, Options::default())
+    }
+
+    /// Constructs a parser from the regex string passed in, using the compile options passed in.
+    ///
+    /// * `regex`: regular expression string.
+    /// * `options`: the compile options.
+    pub(crate) fn with_options(regex: &str, options: Options) -> Result<Self, String> {
+        let flags = Flags {
+            case_insensitive: options.case_insensitive,
+            multi_line: options.multi_line,
+            crlf: options.crlf,
+            ..Flags::default()
+        };
+        let mut scanner = Scanner::with_flags(regex, flags);
+        scanner.scan()?;
+        let capture_names = scanner.take_capture_names();
         let mut pfix_stack = {
-            let mut scanner = Scanner::new(regex);
-            scanner.scan()?;
             let mut conv = PostfixConverter::from_scanner(scanner);
             conv.convert()?;
             conv.move_postfix_vec()
@@ -46,6 +81,10 @@ impl Parser {
         Ok(Self {
             postfix_stack: pfix_stack,
             nfa_stack: vec![Nfa::new(Token::new(0, TokenType::Empty))],
+            options,
+            repeated_groups: vec![false; capture_names.len()],
+            capture_names,
+            lookarounds: Lookarounds::default(),
         })
     }
 
@@ -54,6 +93,9 @@ impl Parser {
     ///
     /// * Return: the parsed `Regex` object.
     pub fn parse(&mut self) -> Result<RARE, String> {
+        // the postfix stack is popped from the back.
+        let literals = Literals::new(self.postfix_stack.iter().rev());
+        let glushkov = Glushkov::new(self.postfix_stack.iter().rev());
         while let Some(tok) = self.postfix_stack.pop() {
             if tok.token_type.is_symbol() {
                 self.handle_symbol(tok)?
@@ -65,11 +107,22 @@ impl Parser {
         // note: there should always be at least 1 NFA by the time parsing finishes.
         let last_state = self.nfa_stack.pop().unwrap();
         // a case where there isn't another NFA down there: empty regular expression "".
-        if let Some(mut ret) = self.nfa_stack.pop() {
-            ret.merge(last_state);
-            return Ok(RARE::from_nfa(ret));
-        }
-        Ok(RARE::from_nfa(last_state))
+        let nfa = match self.nfa_stack.pop() {
+            Some(mut ret) => {
+                ret.merge(last_state);
+                ret
+            }
+            None => last_state,
+        };
+        Ok(RARE::from_nfa(
+            nfa,
+            std::mem::take(&mut self.capture_names),
+            std::mem::take(&mut self.repeated_groups),
+            literals,
+            glushkov,
+            std::mem::take(&mut self.lookarounds),
+            self.options.clone(),
+        ))
     }
 
     /// Handles the symbol passed in. This assumes that the input passed in is a symbol.
@@ -81,9 +134,14 @@ impl Parser {
         match input.token_type {
             TokenType::Concat => self.handle_concat(input.pos),
             TokenType::Beam => self.handle_beam(input.pos),
-            TokenType::Star => self.handle_star(input.pos),
-            TokenType::Plus => self.handle_plus(input.pos),
-            TokenType::QuestionMark => self.handle_question_mark(input.pos),
+            TokenType::Star { greedy } => self.handle_star(input.pos, greedy),
+            TokenType::Plus { greedy } => self.handle_plus(input.pos, greedy),
+            TokenType::QuestionMark { greedy } => self.handle_question_mark(input.pos, greedy),
+            TokenType::Repeat { min, max, greedy } => {
+                self.handle_repeat(input.pos, min, max, greedy)
+            }
+            TokenType::Group(idx) => self.handle_group(input.pos, idx),
+            TokenType::Lookaround(kind) => self.handle_lookaround(input.pos, kind),
             _ => Err(String::from(
                 "Program bug in symbol handling. Contact the author about this error.",
             )),
@@ -139,33 +197,48 @@ impl Parser {
             Some(ret) => ret,
         };
 
-        // first NFA's end plus 1. After merging with push_nfa, this is where that end is.
-        let first_end = first_nfa.states.len();
+        // a|b|c is parsed as (a|b)|c. Adding c as a third branch of (a|b), rather than nesting,
+        // keeps long lists of alternatives flat:
+        //
+        // (empty)────>(a)────>(empty)
+        //    ├──>──(b)──>──┤
+        //    └──>──(c)──>──┘
+        if first_nfa.alternation {
+            let mut push_nfa = first_nfa;
+            let join = push_nfa.end;
+            push_nfa.end = 0;
+            push_nfa.merge(second_nfa);
+            let second_end = push_nfa.end;
+            push_nfa.states[second_end].add_edge(join);
+            push_nfa.end = join;
+            push_nfa.alternation = true;
+
+            self.nfa_stack.push(push_nfa);
+            return Ok(());
+        }
+
         let mut push_nfa = Nfa::new(Token::new(0, TokenType::Empty));
 
-        push_nfa.merge(first_nfa);
+        // the start of push_nfa gets a second edge, towards second_nfa. Fusing it with the start
+        // of first_nfa would let a loop inside first_nfa jump into second_nfa.
+        push_nfa.merge_unfused(first_nfa);
+        let first_end = push_nfa.end;
         // maybe I should encapsulate this in a simple function. This is a bit of "magic", if you
         // don't know how `merge` works.
         push_nfa.end = 0;
         push_nfa.merge(second_nfa);
+        let second_end = push_nfa.end;
 
         {
             // last index plus 1
             let new_last_len = push_nfa.states.len();
             // point both ends of the newly merged NFAs to an empty node that is the end of the OR
             // boolean.
-            push_nfa
-                .states
-                .get_mut(first_end)
-                .unwrap()
-                .add_edge(new_last_len);
-            push_nfa
-                .states
-                .last_mut()
-                .unwrap()
-                .add_edge(new_last_len);
+            push_nfa.states[first_end].add_edge(new_last_len);
+            push_nfa.states[second_end].add_edge(new_last_len);
             push_nfa.add_state(State::new(Token::new(0, TokenType::Empty)));
         }
+        push_nfa.alternation = true;
 
         self.nfa_stack.push(push_nfa);
         Ok(())
@@ -174,78 +247,289 @@ impl Parser {
     /// Handles the Kleene star symbol.
     ///
     /// Requires at least 1 NFA in the stack.
-    fn handle_star(&mut self, pos: usize) -> Result<(), String> {
+    ///
+    /// * `pos`: the string index.
+    /// * `greedy`: whether another repetition takes priority over leaving the loop.
+    fn handle_star(&mut self, pos: usize, greedy: bool) -> Result<(), String> {
+        let last_nfa = self.pop_quantified_nfa(pos)?;
+        self.mark_repeated(&last_nfa);
+        self.nfa_stack.push(Parser::star_nfa(last_nfa, greedy));
+        Ok(())
+    }
+
+    /// Handles the plus symbol.
+    ///
+    /// Requires at least 1 NFA in the stack.
+    ///
+    /// * `pos`: the string index.
+    /// * `greedy`: whether another repetition takes priority over leaving the loop.
+    fn handle_plus(&mut self, pos: usize, greedy: bool) -> Result<(), String> {
+        let last_nfa = self.pop_quantified_nfa(pos)?;
+        self.mark_repeated(&last_nfa);
+        self.nfa_stack.push(Parser::plus_nfa(last_nfa, greedy));
+        Ok(())
+    }
+
+    /// Handles the question mark symbol.
+    ///
+    /// Requires at least 1 NFA in the stack.
+    ///
+    /// * `pos`: the string index.
+    /// * `greedy`: whether going through the NFA takes priority over skipping it.
+    fn handle_question_mark(&mut self, pos: usize, greedy: bool) -> Result<(), String> {
+        let last_nfa = self.pop_quantified_nfa(pos)?;
+        self.nfa_stack
+            .push(Parser::question_mark_nfa(last_nfa, greedy));
+        Ok(())
+    }
+
+    /// Handles the bounded repetition symbol, {min,max}.
+    ///
+    /// Requires at least 1 NFA in the stack. Fails if the copies would push the NFA over the size
+    /// limit in the compile options.
+    ///
+    /// * `pos`: the string index.
+    /// * `min`: the minimum number of repetitions.
+    /// * `max`: the maximum number of repetitions, `None` if unbounded.
+    /// * `greedy`: whether the optional repetitions take priority over stopping.
+    fn handle_repeat(
+        &mut self,
+        pos: usize,
+        min: usize,
+        max: Option<usize>,
+        greedy: bool,
+    ) -> Result<(), String> {
         // TL;DR
         //
-        //   ┌────────────>─────────────┐
-        // (empty)──>──(star_nfa)──>──(empty)
-        //   └─────<──────┘
-        let mut new_nfa = self.new_single_quantifier_nfa(pos)?;
+        // x{2,4} is compiled as xx(x(x)?)?, and x{2,} as xx+.
+        // The optional copies are nested rather than chained (xxx?x?), so that there's only one
+        // way to match a given number of x.
+        let last_nfa = self.pop_quantified_nfa(pos)?;
+        if max.is_none_or(|max| max > 1) {
+            self.mark_repeated(&last_nfa);
+        }
 
-        new_nfa.states[new_nfa.end].add_edge(0);
-        new_nfa.merge(Nfa::new(Token::new(0, TokenType::Empty)));
-        new_nfa.states[0].add_edge(new_nfa.end);
+        let copies = max.unwrap_or(min.max(1));
+        let stack_size: usize = self.nfa_stack.iter().map(|nfa| nfa.states.len()).sum();
+        let new_size = last_nfa
+            .states
+            .len()
+            .checked_mul(copies)
+            .and_then(|size| size.checked_add(stack_size));
+        if new_size.is_none_or(|size| size > self.options.size_limit) {
+            return Err(format!(
+                "Repetition at {0}: the compiled NFA would exceed the size limit of {1} states",
+                pos + 1,
+                self.options.size_limit
+            ));
+        }
+
+        let mut new_nfa = Nfa::new(Token::new(0, TokenType::Empty));
+        let required = match max {
+            None if min > 0 => min - 1,
+            _ => min,
+        };
+        for _ in 0..required {
+            new_nfa.merge(last_nfa.clone());
+        }
+        match max {
+            None if min == 0 => new_nfa.merge(Parser::star_nfa(last_nfa, greedy)),
+            None => new_nfa.merge(Parser::plus_nfa(last_nfa, greedy)),
+            Some(max) if max > min => {
+                let mut optional = Parser::question_mark_nfa(last_nfa.clone(), greedy);
+                for _ in min + 1..max {
+                    let mut copy = last_nfa.clone();
+                    copy.merge(optional);
+                    optional = Parser::question_mark_nfa(copy, greedy);
+                }
+                new_nfa.merge(optional);
+            }
+            Some(_) => (),
+        }
 
         self.nfa_stack.push(new_nfa);
         Ok(())
     }
 
-    /// Handles the plus symbol.
+    /// Handles the group symbol, which the postfix converter puts in place of a pair of
+    /// parentheses.
     ///
     /// Requires at least 1 NFA in the stack.
-    fn handle_plus(&mut self, pos: usize) -> Result<(), String> {
+    ///
+    /// * `pos`: the string index.
+    /// * `idx`: the capture group index.
+    fn handle_group(&mut self, pos: usize, idx: usize) -> Result<(), String> {
         // TL;DR
         //
-        // (empty)──>──(star_nfa)──>──(empty)
-        //   └─────<──────┘
-        // so, very similar to handle_star
-        let mut new_nfa = self.new_single_quantifier_nfa(pos)?;
+        // (save 2 * idx)──>──(group_nfa)──>──(save 2 * idx + 1)
+        let group_nfa = match self.nfa_stack.pop() {
+            Some(r) => r,
+            None => return Err(format!("Character ( at {0}: empty group", pos + 1)),
+        };
 
-        new_nfa.states[new_nfa.end].add_edge(0);
-        new_nfa.merge(Nfa::new(Token::new(0, TokenType::Empty)));
-        // difference to star: this line
-        // new_nfa.states[0].add_edge(TokenType::Empty, new_nfa.end);
+        let mut new_nfa = Nfa::new(Token::new(pos, TokenType::Save(2 * idx)));
+        new_nfa.merge(group_nfa);
+        new_nfa.merge(Nfa::new(Token::new(pos, TokenType::Save(2 * idx + 1))));
 
         self.nfa_stack.push(new_nfa);
         Ok(())
     }
 
-    /// Handles the question mark symbol.
+    /// Handles the lookaround symbol, which the scanner puts right after the parentheses closing a
+    /// lookaround group.
     ///
-    /// Requires at least 1 NFA in the stack.
-    fn handle_question_mark(&mut self, pos: usize) -> Result<(), String> {
+    /// Requires at least 1 NFA in the stack. The NFA of the group is compiled on its own, and is
+    /// replaced with a single `TokenType::Assert` state referring to it.
+    ///
+    /// * `pos`: the string index of the closing parentheses.
+    /// * `kind`:
+    fn handle_lookaround(&mut self, pos: usize, kind: LookKind) -> Result<(), String> {
+        let group_nfa = match self.nfa_stack.pop() {
+            Some(r) => r,
+            None => return Err(format!("Character ) at {0}: empty lookaround", pos + 1)),
+        };
+        let idx = self.lookarounds.push(Lookaround::new(kind, group_nfa));
+
+        self.nfa_stack
+            .push(Nfa::new(Token::new(pos, TokenType::Assert(idx))));
+        Ok(())
+    }
+
+    /// Pops the NFA a quantifier applies to.
+    ///
+    /// * `pos`: the string index.
+    fn pop_quantified_nfa(&mut self, pos: usize) -> Result<Nfa, String> {
+        match self.nfa_stack.pop() {
+            Some(r) => Ok(r),
+            None => Err(format!(
+                "Character +, position {0}: Missing preceding value",
+                pos + 1
+            )),
+        }
+    }
+
+    /// Records that the capture groups inside the NFA passed in are repeated, for a quantifier that
+    /// may go through it more than once.
+    ///
+    /// * `nfa`:
+    fn mark_repeated(&mut self, nfa: &Nfa) {
+        for state in nfa.states.iter() {
+            if let TokenType::Save(slot) = state.token.token_type {
+                self.repeated_groups[slot / 2] = true;
+            }
+        }
+    }
+
+    /// Wraps the NFA passed in with a Kleene star.
+    ///
+    /// * `star_nfa`:
+    /// * `greedy`: whether another repetition takes priority over leaving the loop.
+    fn star_nfa(star_nfa: Nfa, greedy: bool) -> Nfa {
         // TL;DR
         //
+        //   ┌──────<──────┐
+        // (empty)──>──(star_nfa)
+        //   └──────>──────(empty)
+        //
+        // the edges of a state are taken in order, so the first edge out of the start decides
+        // whether it's greedy. Each repetition goes back to the start, already visited at that
+        // position if the loop is nested in another one, before it may leave. Leaving from the
+        // end of the repetition instead, "(?:.*?)+b" over "abb" would take the lazy loop out,
+        // come back into it through the outer loop, and read the first b.
+        //
+        // But a repetition that can match nothing would then find the start visited and be
+        // dropped, along with the way out of the loop it leads to. So that an empty repetition
+        // still leaves the loop, as in "(?:a??)*" over "a", such a loop is built as a plus
+        // that may be skipped:
+        //
         //   ┌────────────>─────────────┐
         // (empty)──>──(star_nfa)──>──(empty)
-        // So, very similar to handle_star also.
-        let mut new_nfa = self.new_single_quantifier_nfa(pos)?;
+        //   └─────<──────┘
+        if star_nfa.matches_empty() {
+            let mut new_nfa = Parser::plus_nfa(star_nfa, greedy);
+            Parser::add_skip_edge(&mut new_nfa, greedy);
+            return new_nfa;
+        }
+
+        let mut new_nfa = Parser::new_single_quantifier_nfa(star_nfa);
+        let body_end = new_nfa.end;
+        new_nfa.states[body_end].add_edge(0);
+        new_nfa.add_state(State::new(Token::new(0, TokenType::Empty)));
+        Parser::add_skip_edge(&mut new_nfa, greedy);
+
+        new_nfa
+    }
 
-        // difference to star: this line
-        // new_nfa.states[new_nfa.end].add_edge(TokenType::Empty, 0);
+    /// Wraps the NFA passed in with a plus.
+    ///
+    /// * `plus_nfa`:
+    /// * `greedy`: whether another repetition takes priority over leaving the loop.
+    fn plus_nfa(plus_nfa: Nfa, greedy: bool) -> Nfa {
+        // TL;DR
+        //
+        // (empty)──>──(plus_nfa)──>──(empty)
+        //   └─────<──────┘
+        // so, very similar to star_nfa
+        let mut new_nfa = Parser::new_single_quantifier_nfa(plus_nfa);
+
+        let loop_end = new_nfa.end;
+        // the end must be a new state: the matcher stops at the end, it wouldn't take the loop.
+        new_nfa.merge_unfused(Nfa::new(Token::new(0, TokenType::Empty)));
+        let edges = &mut new_nfa.states[loop_end].edges;
+        match greedy {
+            true => edges.insert(0, 0),
+            false => edges.push(0),
+        }
+
+        new_nfa
+    }
+
+    /// Wraps the NFA passed in with a question mark.
+    ///
+    /// * `question_nfa`:
+    /// * `greedy`: whether going through the NFA takes priority over skipping it.
+    fn question_mark_nfa(question_nfa: Nfa, greedy: bool) -> Nfa {
+        // TL;DR
+        //
+        //   ┌────────────>─────────────┐
+        // (empty)──>──(question_nfa)──>──(empty)
+        // So, very similar to star_nfa also.
+        let mut new_nfa = Parser::new_single_quantifier_nfa(question_nfa);
+
+        // difference to star: no edge back to the start.
         new_nfa.merge(Nfa::new(Token::new(0, TokenType::Empty)));
-        new_nfa.states[0].add_edge(new_nfa.end);
+        Parser::add_skip_edge(&mut new_nfa, greedy);
 
-        self.nfa_stack.push(new_nfa);
-        Ok(())
+        new_nfa
+    }
+
+    /// Adds the edge from the start of a quantifier NFA straight to its end, after the edges into
+    /// the quantified NFA if greedy, or before them if lazy.
+    ///
+    /// * `nfa`:
+    /// * `greedy`:
+    fn add_skip_edge(nfa: &mut Nfa, greedy: bool) {
+        let end = nfa.end;
+        let edges = &mut nfa.states[0].edges;
+        match greedy {
+            true => edges.push(end),
+            false => edges.insert(0, end),
+        }
     }
 
     /// Constructs a NFA for single quantifiers to use.
     ///
-    /// * `pos`: the string index.
-    fn new_single_quantifier_nfa(&mut self, pos: usize) -> Result<Nfa, String> {
-        let last_nfa = match self.nfa_stack.pop() {
-            Some(r) => r,
-            None => {
-                return Err(format!(
-                    "Character +, position {0}: Missing preceding value",
-                    pos + 1
-                ))
-            }
-        };
+    /// * `last_nfa`: the NFA the quantifier applies to.
+    fn new_single_quantifier_nfa(last_nfa: Nfa) -> Nfa {
         let mut new_nfa = Nfa::new(Token::new(0, TokenType::Empty));
-        new_nfa.merge(last_nfa);
-        Ok(new_nfa)
+        // the quantifiers add edges to the start. If last_nfa loops back to its own start, these
+        // edges must not be shared with that loop.
+        if last_nfa.loops_to_start() {
+            new_nfa.merge_unfused(last_nfa);
+        } else {
+            new_nfa.merge(last_nfa);
+        }
+        new_nfa
     }
 }
 
@@ -0,0 +1,163 @@
+use std::collections::{HashMap, HashSet};
+
+use super::nfa::Nfa;
+use crate::lexer::token_type::TokenType;
+use crate::lexer::unicode_tables::perl_classes::PERL_WORD;
+
+/// The number of code points whose class is kept in a table: the bytes of ASCII and Latin-1.
+const TABLE_LEN: usize = 256;
+
+/// The alphabet of an automaton built from the NFA: the characters, split into classes that no
+/// state of the NFA tells apart. Computed once when compiling.
+///
+/// A pattern only ever looks at a few characters and ranges, so most characters behave exactly the
+/// same. Tables indexed by class rather than by character are then much smaller: "[a-z]+@x" only
+/// has 5 classes, \n, '@', 'x', the rest of [a-z], and everything else.
+///
+/// Two characters share a class if every consuming state of the NFA accepts either both or none of
+/// them. \n always gets a class of its own, since the anchors treat it specially, and so do \r if
+/// the pattern has anchors under the CRLF flag, and the word characters if it has a \b or \B. A
+/// class may be made of several ranges, such as [a-w] and [y-z] above.
+///
+/// * `table`: the class of each of the first 256 code points, so that the common case is a single
+///   lookup.
+/// * `starts`: the first character of each range the characters are cut into, in increasing order,
+///   from 0. Used for the characters past the table.
+/// * `classes`: the class of each range of `starts`.
+/// * `representatives`: one character of each class.
+#[derive(Clone, Debug)]
+pub(crate) struct ByteClasses {
+    table: [u32; TABLE_LEN],
+    starts: Vec<u32>,
+    classes: Vec<u32>,
+    representatives: Vec<char>,
+}
+
+impl ByteClasses {
+    /// Computes the classes of the NFA passed in.
+    ///
+    /// * `nfa`:
+    pub fn new(nfa: &Nfa) -> Self {
+        // the distinct sets of characters the states tell apart, as inclusive ranges. A negated
+        // class splits the characters the same way as its ranges do, and so does the dot as \n.
+        let mut sets: HashSet<Vec<(u32, u32)>> = HashSet::new();
+        sets.insert(vec![('\n' as u32, '\n' as u32)]);
+        for state in nfa.states.iter() {
+            match &state.token.token_type {
+                TokenType::Character(c) => {
+                    sets.insert(vec![(*c as u32, *c as u32)]);
+                }
+                TokenType::CharClass(class) => {
+                    sets.insert(
+                        class
+                            .ranges()
+                            .iter()
+                            .map(|&(lo, hi)| (lo as u32, hi as u32))
+                            .collect(),
+                    );
+                }
+                TokenType::LineStart { crlf: true } | TokenType::LineEnd { crlf: true } => {
+                    sets.insert(vec![('\r' as u32, '\r' as u32)]);
+                }
+                TokenType::WordBoundary { ascii: false }
+                | TokenType::NotWordBoundary { ascii: false } => {
+                    sets.insert(
+                        PERL_WORD
+                            .iter()
+                            .map(|&(lo, hi)| (lo as u32, hi as u32))
+                            .collect(),
+                    );
+                }
+                TokenType::WordBoundary { ascii: true }
+                | TokenType::NotWordBoundary { ascii: true } => {
+                    sets.insert(
+                        [('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')]
+                            .iter()
+                            .map(|&(lo, hi)| (lo as u32, hi as u32))
+                            .collect(),
+                    );
+                }
+                _ => (),
+            }
+        }
+
+        // cut the characters at every bound of every set.
+        let mut starts: Vec<u32> = vec![0];
+        for &(lo, hi) in sets.iter().flatten() {
+            starts.push(lo);
+            starts.push(hi + 1);
+        }
+        // no character lies in the surrogate gap, so a range starting there really starts after
+        // it. And nothing starts past the last character.
+        for start in starts.iter_mut() {
+            if (0xD800..0xE000).contains(start) {
+                *start = 0xE000;
+            }
+        }
+        starts.retain(|&start| start <= char::MAX as u32);
+        starts.sort_unstable();
+        starts.dedup();
+
+        // then refine: each set splits every class into the ranges inside it and those outside.
+        let mut classes: Vec<u32> = vec![0; starts.len()];
+        let mut next_class = 1;
+        let mut split: HashMap<u32, u32> = HashMap::new();
+        for set in sets.iter() {
+            split.clear();
+            for &(lo, hi) in set.iter() {
+                let first = starts.partition_point(|&start| start <= lo) - 1;
+                for idx in (first..starts.len()).take_while(|&idx| starts[idx] <= hi) {
+                    classes[idx] = *split.entry(classes[idx]).or_insert_with(|| {
+                        next_class += 1;
+                        next_class - 1
+                    });
+                }
+            }
+        }
+
+        // number the classes from 0, in the order of their first character.
+        let mut renumbered: HashMap<u32, u32> = HashMap::new();
+        let mut representatives: Vec<char> = Vec::new();
+        for (idx, class) in classes.iter_mut().enumerate() {
+            let next = renumbered.len() as u32;
+            *class = *renumbered.entry(*class).or_insert_with(|| {
+                representatives.push(char::from_u32(starts[idx]).unwrap());
+                next
+            });
+        }
+
+        let mut table = [0; TABLE_LEN];
+        for (c, class) in table.iter_mut().enumerate() {
+            *class = classes[starts.partition_point(|&start| start <= c as u32) - 1];
+        }
+        Self {
+            table,
+            starts,
+            classes,
+            representatives,
+        }
+    }
+
+    /// Returns the number of classes.
+    pub fn len(&self) -> usize {
+        self.representatives.len()
+    }
+
+    #[inline]
+    /// Returns the class of the character passed in.
+    ///
+    /// * `c`:
+    pub fn get(&self, c: char) -> usize {
+        if (c as usize) < TABLE_LEN {
+            return self.table[c as usize] as usize;
+        }
+        self.classes[self.starts.partition_point(|&start| start <= c as u32) - 1] as usize
+    }
+
+    /// Returns one character of the class passed in. The NFA treats all the others the same way.
+    ///
+    /// * `class`:
+    pub fn representative(&self, class: usize) -> char {
+        self.representatives[class]
+    }
+}
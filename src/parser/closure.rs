@@ -0,0 +1,409 @@
+use std::ops::ControlFlow;
+
+use super::nfa::Nfa;
+use crate::lexer::token_type::TokenType;
+use crate::lexer::unicode_tables::perl_classes::PERL_WORD;
+use crate::rare::SparseSet;
+
+/// The most states the walks building the epsilon closures may go through, all closures together.
+/// Past that, they aren't built, see `EpsilonClosures`.
+const MAX_WALKED: usize = 1 << 20;
+
+/// A set of conditions on the string position, one bit per anchor. An epsilon path through
+/// anchors can only be followed where all the anchors on it hold.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub(crate) struct Assertions(u16);
+
+impl Assertions {
+    /// Hat (^), or \A: the start of the string.
+    pub const START_TEXT: Self = Self(1);
+    /// Dollar ($), or \z: the end of the string.
+    pub const END_TEXT: Self = Self(1 << 1);
+    /// ^ under the multi-line flag: the start of the string, or after a \n.
+    pub const START_LINE: Self = Self(1 << 2);
+    /// $ under the multi-line flag: the end of the string, or before a \n.
+    pub const END_LINE: Self = Self(1 << 3);
+    /// \b: a word character on exactly one side, by the Unicode definition of \w.
+    pub const WORD_BOUNDARY: Self = Self(1 << 4);
+    /// \B: a word character on both sides or neither, by the Unicode definition of \w.
+    pub const NOT_WORD_BOUNDARY: Self = Self(1 << 5);
+    /// \b under the ASCII flag, where only [0-9A-Za-z_] are word characters.
+    pub const ASCII_WORD_BOUNDARY: Self = Self(1 << 6);
+    /// \B under the ASCII flag.
+    pub const NOT_ASCII_WORD_BOUNDARY: Self = Self(1 << 7);
+    /// The anchors that look at whether the characters around are Unicode word characters.
+    pub const WORD_ANCHORS: Self = Self(Self::WORD_BOUNDARY.0 | Self::NOT_WORD_BOUNDARY.0);
+    /// The anchors that look at whether the characters around are ASCII word characters.
+    pub const ASCII_WORD_ANCHORS: Self =
+        Self(Self::ASCII_WORD_BOUNDARY.0 | Self::NOT_ASCII_WORD_BOUNDARY.0);
+    /// ^ under the multi-line and CRLF flags: the start of the string, or after a \n, or after a
+    /// \r that isn't followed by a \n.
+    pub const START_LINE_CRLF: Self = Self(1 << 8);
+    /// $ under the multi-line and CRLF flags: the end of the string, or before a \r, or before a
+    /// \n that doesn't follow a \r.
+    pub const END_LINE_CRLF: Self = Self(1 << 9);
+    /// The anchors that look for \r.
+    pub const CRLF_ANCHORS: Self = Self(Self::START_LINE_CRLF.0 | Self::END_LINE_CRLF.0);
+    /// \Z: the end of the string, or before a \n that ends it. Unlike the others, this one looks
+    /// past the character after the position, see `Assertions::with_next`.
+    pub const END_NEW_LINE: Self = Self(1 << 10);
+
+    /// Returns the condition of an anchor, or `None` if the token passed in isn't one.
+    ///
+    /// * `token_type`:
+    pub fn of_anchor(token_type: &TokenType) -> Option<Self> {
+        match token_type {
+            TokenType::Hat => Some(Self::START_TEXT),
+            TokenType::Dollar => Some(Self::END_TEXT),
+            TokenType::LineStart { crlf: false } => Some(Self::START_LINE),
+            TokenType::LineEnd { crlf: false } => Some(Self::END_LINE),
+            TokenType::LineStart { crlf: true } => Some(Self::START_LINE_CRLF),
+            TokenType::LineEnd { crlf: true } => Some(Self::END_LINE_CRLF),
+            TokenType::EndNewLine => Some(Self::END_NEW_LINE),
+            TokenType::WordBoundary { ascii: false } => Some(Self::WORD_BOUNDARY),
+            TokenType::NotWordBoundary { ascii: false } => Some(Self::NOT_WORD_BOUNDARY),
+            TokenType::WordBoundary { ascii: true } => Some(Self::ASCII_WORD_BOUNDARY),
+            TokenType::NotWordBoundary { ascii: true } => Some(Self::NOT_ASCII_WORD_BOUNDARY),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    /// Returns the conditions that hold at a string position. Computed once per position, so that
+    /// checking an anchor while matching is a single bit test. \Z only holds at the end here, see
+    /// `Assertions::with_next` for the rest.
+    ///
+    /// * `prev_char`: the character right before the position, `None` at the start.
+    /// * `curr_char`: the character right after the position, `None` at the end.
+    pub fn at(prev_char: Option<char>, curr_char: Option<char>) -> Self {
+        let mut ret = Self::default();
+        if prev_char.is_none() {
+            ret = ret.union(Self::START_TEXT);
+        }
+        if curr_char.is_none() {
+            ret = ret.union(Self::END_TEXT).union(Self::END_NEW_LINE);
+        }
+        if prev_char.is_none_or(|c| c == '\n') {
+            ret = ret.union(Self::START_LINE);
+        }
+        if curr_char.is_none_or(|c| c == '\n') {
+            ret = ret.union(Self::END_LINE);
+        }
+        if prev_char.is_none_or(|c| c == '\n' || c == '\r' && curr_char != Some('\n')) {
+            ret = ret.union(Self::START_LINE_CRLF);
+        }
+        if curr_char.is_none_or(|c| c == '\r' || c == '\n' && prev_char != Some('\r')) {
+            ret = ret.union(Self::END_LINE_CRLF);
+        }
+        let word = |c: Option<char>| c.is_some_and(is_word_char);
+        ret = ret.union(match word(prev_char) != word(curr_char) {
+            true => Self::WORD_BOUNDARY,
+            false => Self::NOT_WORD_BOUNDARY,
+        });
+        let ascii_word = |c: Option<char>| c.is_some_and(is_ascii_word_char);
+        ret = ret.union(match ascii_word(prev_char) != ascii_word(curr_char) {
+            true => Self::ASCII_WORD_BOUNDARY,
+            false => Self::NOT_ASCII_WORD_BOUNDARY,
+        });
+        ret
+    }
+
+    #[inline]
+    /// Adds the condition of \Z right before a \n ending the string, which needs to know the
+    /// character after the one after the position.
+    ///
+    /// * `curr_char`: the character right after the position.
+    /// * `next_char`: the character after `curr_char`.
+    pub fn with_next(self, curr_char: Option<char>, next_char: Option<char>) -> Self {
+        match (curr_char, next_char) {
+            (Some('\n'), None) => self.union(Self::END_NEW_LINE),
+            _ => self,
+        }
+    }
+
+    /// Returns the conditions that hold at a byte offset of a string.
+    ///
+    /// * `string`:
+    /// * `pos`: a byte offset on a char boundary.
+    pub fn at_offset(string: &str, pos: usize) -> Self {
+        let mut after = string[pos..].chars();
+        let curr_char = after.next();
+        Self::at(string[..pos].chars().next_back(), curr_char).with_next(curr_char, after.next())
+    }
+
+    /// Returns the conditions in either set.
+    ///
+    /// * `other`:
+    pub fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    #[inline]
+    /// Returns whether every condition of `other` is also in this set.
+    ///
+    /// * `other`:
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    #[inline]
+    /// Returns whether some condition is in both sets.
+    ///
+    /// * `other`:
+    pub fn intersects(self, other: Self) -> bool {
+        self.0 & other.0 != 0
+    }
+}
+
+#[inline]
+/// Returns whether the character passed in is a word character, as \w matches: a letter, a mark,
+/// a decimal number, a connector punctuation such as _, or a join control.
+///
+/// * `c`:
+pub(crate) fn is_word_char(c: char) -> bool {
+    if c.is_ascii() {
+        return is_ascii_word_char(c);
+    }
+    PERL_WORD
+        .binary_search_by(|&(lo, hi)| {
+            if hi < c {
+                std::cmp::Ordering::Less
+            } else if lo > c {
+                std::cmp::Ordering::Greater
+            } else {
+                std::cmp::Ordering::Equal
+            }
+        })
+        .is_ok()
+}
+
+#[inline]
+/// Returns whether the character passed in is a word character by the ASCII definition,
+/// [0-9A-Za-z_].
+///
+/// * `c`:
+pub(crate) fn is_ascii_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Where an epsilon path ends.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Target {
+    /// A state that is entered by consuming a character.
+    Consume(usize),
+    /// The end state of the NFA, reached without consuming anything: there's a match.
+    Match,
+}
+
+/// One epsilon path: its end, and the anchors it goes through.
+///
+/// * `target`:
+/// * `needs`: the conditions the string position must satisfy to follow the path.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) struct ClosureEntry {
+    pub target: Target,
+    pub needs: Assertions,
+}
+
+/// The epsilon closure of every NFA state, computed once when compiling.
+///
+/// The closure of a state lists what can be reached from it, after it's been entered, by only
+/// passing through states that don't consume (empty, saves and anchors): the consuming states
+/// that may be entered next, and whether the end state may be reached. Anchors make these paths
+/// conditional, so each path also records those it passes through. The matcher then only checks
+/// those against the current position, instead of walking the empty edges again on every
+/// character.
+///
+/// Lookarounds can't be recorded that way: the paths through n optional ones would be listed once
+/// for each of the 2^n subsets of them. So the paths stop at `TokenType::Assert` states, and the
+/// NFAs with lookarounds walk their empty edges at every position instead, see `walk`.
+///
+/// Nested optional groups make the closures grow quadratically with the NFA, as in "(a?){1000}"
+/// where each of the a may be followed by any later one. Past `MAX_WALKED`, they aren't built, and
+/// the empty edges are walked at every position instead, as with lookarounds.
+///
+/// * `entries`: the closures of all states, one after the other.
+/// * `starts`: the closure of state `s` is `entries[starts[s]..starts[s + 1]]`.
+/// * `anchors`: every anchor some path goes through.
+pub(crate) struct EpsilonClosures {
+    entries: Vec<ClosureEntry>,
+    starts: Vec<usize>,
+    anchors: Assertions,
+}
+
+impl EpsilonClosures {
+    /// Computes the epsilon closure of every state of the NFA passed in.
+    ///
+    /// * `nfa`:
+    /// * Return: the closures, or `None` if the walks along the epsilon paths would go through more
+    ///   than `MAX_WALKED` states.
+    pub fn new(nfa: &Nfa) -> Option<Self> {
+        let mut entries = Vec::new();
+        let mut starts = Vec::with_capacity(nfa.states.len() + 1);
+        // the anchors passed so far, each time a state has been reached. A state reached again
+        // through other ones is still explored, unless it was reached before through fewer: the
+        // Pike VM would have entered it already wherever this path can be followed.
+        let mut visited: Vec<Vec<Assertions>> = vec![Vec::new(); nfa.states.len()];
+        let mut touched: Vec<usize> = Vec::new();
+        let mut stack: Vec<(usize, Assertions)> = Vec::new();
+        let mut walked = 0usize;
+
+        for state_ref in 0..nfa.states.len() {
+            starts.push(entries.len());
+            if state_ref == nfa.end {
+                // the end state has been entered, that's a match already.
+                entries.push(ClosureEntry {
+                    target: Target::Match,
+                    needs: Assertions::default(),
+                });
+                continue;
+            }
+            for visited_ref in touched.drain(..) {
+                visited[visited_ref].clear();
+            }
+            // the state itself has been entered already, so a path looping back to it without
+            // consuming adds nothing.
+            if !nfa.states[state_ref].token.token_type.consumes() {
+                visited[state_ref].push(Assertions::default());
+                touched.push(state_ref);
+            }
+            // pushed in reverse, so that the paths are listed in the order of the edges.
+            stack.extend(
+                nfa.states[state_ref]
+                    .edges
+                    .iter()
+                    .rev()
+                    .map(|&next| (next, Assertions::default())),
+            );
+            while let Some((next_ref, needs)) = stack.pop() {
+                let reached = &mut visited[next_ref];
+                if reached.iter().any(|&before| needs.contains(before)) {
+                    continue;
+                }
+                if reached.is_empty() {
+                    touched.push(next_ref);
+                }
+                reached.push(needs);
+                walked += 1;
+                if walked > MAX_WALKED {
+                    return None;
+                }
+                let token_type = &nfa.states[next_ref].token.token_type;
+                if token_type.consumes() {
+                    entries.push(ClosureEntry {
+                        target: Target::Consume(next_ref),
+                        needs,
+                    });
+                    continue;
+                }
+                // the NFAs with lookarounds walk their empty edges instead.
+                if let TokenType::Assert(_) = token_type {
+                    continue;
+                }
+                let needs = match Assertions::of_anchor(token_type) {
+                    Some(anchor) => needs.union(anchor),
+                    None => needs,
+                };
+                if next_ref == nfa.end {
+                    entries.push(ClosureEntry {
+                        target: Target::Match,
+                        needs,
+                    });
+                    continue;
+                }
+                stack.extend(
+                    nfa.states[next_ref]
+                        .edges
+                        .iter()
+                        .rev()
+                        .map(|&next| (next, needs)),
+                );
+            }
+        }
+        starts.push(entries.len());
+        let anchors = entries
+            .iter()
+            .fold(Assertions::default(), |anchors, entry| {
+                anchors.union(entry.needs)
+            });
+
+        Some(Self {
+            entries,
+            starts,
+            anchors,
+        })
+    }
+
+    #[inline]
+    /// Returns the epsilon closure of the state passed in.
+    ///
+    /// * `state_ref`:
+    pub fn of(&self, state_ref: usize) -> &[ClosureEntry] {
+        &self.entries[self.starts[state_ref]..self.starts[state_ref + 1]]
+    }
+
+    /// Returns every anchor the NFA checks, so that the DFAs only tell apart the positions these
+    /// anchors do.
+    pub fn anchors(&self) -> Assertions {
+        self.anchors
+    }
+}
+
+/// Walks the empty edges from a state of the NFA passed in, at the current position, in priority
+/// order, as the Pike VM does: a state already reached at the position isn't walked through
+/// again. Used instead of the `EpsilonClosures` for the NFAs with lookarounds, which are run as
+/// they're reached, and for those whose closures would be too big.
+///
+/// * `nfa`:
+/// * `state_ref`: the state entered, the walk starts from its edges.
+/// * `held`: the anchors that hold at the current position.
+/// * `visited`: the states reached at the current position, shared by the walks from all the
+///   current states.
+/// * `stack`: reused between calls, to avoid reallocation.
+/// * `holds`: returns whether the lookaround at the index passed in holds at the position.
+/// * `visit`: called on each `Target::Consume` and `Target::Match` in order. Breaking out of it
+///   stops the walk.
+/// * Return: whether `visit` stopped the walk.
+pub(crate) fn walk(
+    nfa: &Nfa,
+    state_ref: usize,
+    held: Assertions,
+    visited: &mut SparseSet,
+    stack: &mut Vec<usize>,
+    mut holds: impl FnMut(usize) -> bool,
+    mut visit: impl FnMut(Target) -> ControlFlow<()>,
+) -> ControlFlow<()> {
+    if state_ref == nfa.end {
+        // the end state has been entered, that's a match already.
+        return visit(Target::Match);
+    }
+    // a state that doesn't consume has been entered at this position already.
+    if !nfa.states[state_ref].token.token_type.consumes() {
+        visited.insert(state_ref);
+    }
+    stack.clear();
+    stack.extend(nfa.states[state_ref].edges.iter().rev());
+    while let Some(next_ref) = stack.pop() {
+        if !visited.insert(next_ref) {
+            continue;
+        }
+        let token_type = &nfa.states[next_ref].token.token_type;
+        let passes = match token_type {
+            _ if token_type.consumes() => {
+                visit(Target::Consume(next_ref))?;
+                continue;
+            }
+            TokenType::Assert(idx) => holds(*idx),
+            _ => Assertions::of_anchor(token_type).is_none_or(|anchor| held.contains(anchor)),
+        };
+        if !passes {
+            continue;
+        }
+        if next_ref == nfa.end {
+            visit(Target::Match)?;
+            continue;
+        }
+        stack.extend(nfa.states[next_ref].edges.iter().rev());
+    }
+    ControlFlow::Continue(())
+}
@@ -0,0 +1,249 @@
+use crate::lexer::token_type::{Token, TokenType};
+
+/// The most positions a position automaton may have: one bit each, in a `u64`.
+pub(crate) const MAX_POSITIONS: usize = 64;
+
+/// The position automaton, or Glushkov automaton, of a pattern: one state per character of the
+/// pattern (a `Character`, `Dot` or `CharClass` token), and no empty transitions at all. Built
+/// from the postfix token list, the same way the `Parser` walks it, but only if the pattern has
+/// no more than `MAX_POSITIONS` positions, so that a set of them fits in a `u64`.
+///
+/// A position is entered by consuming a character its token accepts, from any position whose
+/// `follow` holds it. "a(b|c)*" has 3 positions: a, b and c. `first` holds a, `last` holds all 3,
+/// and b and c both follow a, b and c.
+///
+/// * `tokens`: the token of each position. A bounded repetition gets its own copy of the positions
+///   it repeats, the same as the NFA.
+/// * `first`: the positions a match may start with.
+/// * `last`: the positions a match may end with.
+/// * `follow`: the positions that may come right after each position.
+/// * `nullable`: whether the pattern matches the empty string.
+#[derive(Clone, Debug)]
+pub(crate) struct Glushkov {
+    pub tokens: Vec<TokenType>,
+    pub first: u64,
+    pub last: u64,
+    pub follow: Vec<u64>,
+    pub nullable: bool,
+}
+
+/// A part of the pattern, as built so far. Its positions are always the ones from `lo` to `hi`,
+/// since the postfix order builds each part right after the parts before it.
+///
+/// * `lo`: its first position.
+/// * `hi`: past its last position.
+/// * `first`:
+/// * `last`:
+/// * `nullable`:
+#[derive(Clone, Copy)]
+struct Fragment {
+    lo: usize,
+    hi: usize,
+    first: u64,
+    last: u64,
+    nullable: bool,
+}
+
+impl Fragment {
+    /// Constructs a part with no position, matching only the empty string.
+    ///
+    /// * `at`: where it would start.
+    fn empty(at: usize) -> Self {
+        Self {
+            lo: at,
+            hi: at,
+            first: 0,
+            last: 0,
+            nullable: true,
+        }
+    }
+}
+
+/// Returns the positions held by the set passed in, in increasing order.
+///
+/// * `set`:
+pub(crate) fn positions(mut set: u64) -> impl Iterator<Item = usize> {
+    std::iter::from_fn(move || {
+        if set == 0 {
+            return None;
+        }
+        let position = set.trailing_zeros() as usize;
+        set &= set - 1;
+        Some(position)
+    })
+}
+
+impl Glushkov {
+    /// Builds the position automaton of the pattern whose postfix token list is passed in.
+    ///
+    /// * `postfix`: the tokens, in postfix order, as the postfix converter outputs them.
+    /// * Return: `None` if the pattern has more than `MAX_POSITIONS` positions, or anchors, which a
+    ///   position automaton can't check.
+    pub fn new<'a>(postfix: impl Iterator<Item = &'a Token>) -> Option<Self> {
+        let mut ret = Self {
+            tokens: Vec::new(),
+            first: 0,
+            last: 0,
+            follow: Vec::new(),
+            nullable: true,
+        };
+        // like the NFA stack of the parser, which starts with an empty NFA.
+        let mut stack: Vec<Fragment> = vec![Fragment::empty(0)];
+        for token in postfix {
+            let fragment = match &token.token_type {
+                TokenType::Concat => {
+                    let second = stack.pop()?;
+                    let first = stack.pop()?;
+                    ret.concat(first, second)
+                }
+                TokenType::Beam => {
+                    let second = stack.pop()?;
+                    let first = stack.pop().unwrap_or(Fragment::empty(second.lo));
+                    Fragment {
+                        lo: first.lo,
+                        hi: second.hi,
+                        first: first.first | second.first,
+                        last: first.last | second.last,
+                        nullable: first.nullable || second.nullable,
+                    }
+                }
+                TokenType::Plus { .. } => ret.plus(stack.pop()?),
+                TokenType::Star { .. } => {
+                    let fragment = ret.plus(stack.pop()?);
+                    Fragment {
+                        nullable: true,
+                        ..fragment
+                    }
+                }
+                TokenType::QuestionMark { .. } => Fragment {
+                    nullable: true,
+                    ..stack.pop()?
+                },
+                TokenType::Repeat { min, max, .. } => ret.repeat(stack.pop()?, *min, *max)?,
+                TokenType::Group(_) => stack.pop()?,
+                TokenType::Character(_) | TokenType::Dot | TokenType::CharClass(_) => {
+                    let position = ret.tokens.len();
+                    if position == MAX_POSITIONS {
+                        return None;
+                    }
+                    ret.tokens.push(token.token_type.clone());
+                    ret.follow.push(0);
+                    Fragment {
+                        lo: position,
+                        hi: position + 1,
+                        first: 1 << position,
+                        last: 1 << position,
+                        nullable: false,
+                    }
+                }
+                TokenType::Empty => Fragment::empty(ret.tokens.len()),
+                _ => return None,
+            };
+            stack.push(fragment);
+        }
+
+        let last = stack.pop()?;
+        let whole = match stack.pop() {
+            Some(first) => ret.concat(first, last),
+            None => last,
+        };
+        ret.first = whole.first;
+        ret.last = whole.last;
+        ret.nullable = whole.nullable;
+        Some(ret)
+    }
+
+    /// Returns the first part followed by the second. Every last position of the first part gets
+    /// followed by every first position of the second.
+    ///
+    /// * `first`:
+    /// * `second`:
+    fn concat(&mut self, first: Fragment, second: Fragment) -> Fragment {
+        for position in positions(first.last) {
+            self.follow[position] |= second.first;
+        }
+        Fragment {
+            lo: first.lo,
+            hi: second.hi,
+            first: match first.nullable {
+                true => first.first | second.first,
+                false => first.first,
+            },
+            last: match second.nullable {
+                true => first.last | second.last,
+                false => second.last,
+            },
+            nullable: first.nullable && second.nullable,
+        }
+    }
+
+    /// Returns one or more repetitions of the part passed in: its last positions loop back to its
+    /// first ones.
+    ///
+    /// * `fragment`:
+    fn plus(&mut self, fragment: Fragment) -> Fragment {
+        for position in positions(fragment.last) {
+            self.follow[position] |= fragment.first;
+        }
+        fragment
+    }
+
+    /// Returns a copy of the part passed in, with new positions after every existing one. The part
+    /// must be the last one built, so that none of its positions is followed by any outside it.
+    ///
+    /// * `fragment`:
+    /// * Return: `None` if the copy doesn't fit in `MAX_POSITIONS`.
+    fn copy(&mut self, fragment: Fragment) -> Option<Fragment> {
+        let shift = self.tokens.len() - fragment.lo;
+        if self.tokens.len() + (fragment.hi - fragment.lo) > MAX_POSITIONS {
+            return None;
+        }
+        for position in fragment.lo..fragment.hi {
+            self.tokens.push(self.tokens[position].clone());
+            self.follow.push(self.follow[position] << shift);
+        }
+        // an empty part has no position to shift.
+        Some(Fragment {
+            lo: fragment.lo + shift,
+            hi: fragment.hi + shift,
+            first: fragment.first.checked_shl(shift as u32).unwrap_or(0),
+            last: fragment.last.checked_shl(shift as u32).unwrap_or(0),
+            nullable: fragment.nullable,
+        })
+    }
+
+    /// Returns the bounded repetition {min,max} of the part passed in. Like the NFA, x{2,4} is
+    /// built as xx(x(x)?)?, which matches the same strings as xxx?x?: only the latter is built
+    /// here, since the position automaton doesn't care how many ways there are to match.
+    ///
+    /// * `fragment`:
+    /// * `min`:
+    /// * `max`: `None` if unbounded.
+    /// * Return: `None` if the copies don't fit in `MAX_POSITIONS`.
+    fn repeat(&mut self, fragment: Fragment, min: usize, max: Option<usize>) -> Option<Fragment> {
+        let copies = max.unwrap_or(min.max(1));
+        if copies == 0 {
+            return Some(Fragment::empty(fragment.lo));
+        }
+        if (fragment.hi - fragment.lo).saturating_mul(copies) > MAX_POSITIONS {
+            return None;
+        }
+        // every copy is made before any of them gets linked to another.
+        let mut parts = vec![fragment];
+        for _ in 1..copies {
+            parts.push(self.copy(fragment)?);
+        }
+        for (idx, part) in parts.iter_mut().enumerate() {
+            if idx >= min {
+                part.nullable = true;
+            }
+        }
+        if max.is_none() {
+            let part = parts.last_mut().unwrap();
+            *part = self.plus(*part);
+        }
+        parts
+            .into_iter()
+            .reduce(|first, second| self.concat(first, second))
+    }
+}
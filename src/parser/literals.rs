@@ -0,0 +1,290 @@
+use crate::lexer::token_type::{Token, TokenType};
+
+/// The most strings a concatenation of two sets of alternatives may give, such as (a|b)(c|d).
+/// Past that, the pattern isn't treated as a set of literals.
+const MAX_ALTERNATIVES: usize = 10_000;
+/// The longest literal a bounded repetition of one may give, such as a{100}. The NFA of a longer
+/// one is likely over the size limit anyway, and is rejected after the literals are found.
+const MAX_REPEATED_LEN: usize = 10_000;
+
+/// The literal strings every match of a pattern contains, found by walking the postfix token list
+/// the same way the `Parser` does, but building these instead of NFAs.
+///
+/// * `prefix`: every match starts with it.
+/// * `inner`: every match contains it somewhere. The longest such string found, which may be the
+///   prefix or the suffix.
+/// * `suffix`: every match ends with it.
+/// * `alternatives`: if the pattern only matches a finite set of literal strings, such as
+///   "foo|bar|baz", those strings, in the order of the alternation.
+///
+/// Any of them may be empty, if nothing is known.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub(crate) struct Literals {
+    pub prefix: String,
+    pub inner: String,
+    pub suffix: String,
+    pub alternatives: Option<Vec<String>>,
+}
+
+/// What is known about the strings a part of the pattern matches.
+///
+/// * `exact`: the only string it matches, if there's one, wherever it is.
+/// * `anchored`: the only string it matches, if there's one, but only at some positions because of
+///   an anchor or a lookaround in it. It's no literal then, but what's around it is still next to
+///   that string.
+/// * `literals`:
+#[derive(Clone, Default)]
+struct Info {
+    exact: Option<String>,
+    anchored: Option<String>,
+    literals: Literals,
+}
+
+impl Info {
+    /// Constructs the info of a part of the pattern matching only the string passed in.
+    ///
+    /// * `string`:
+    fn exact(string: String) -> Self {
+        Self {
+            literals: Literals {
+                prefix: string.clone(),
+                inner: string.clone(),
+                suffix: string.clone(),
+                alternatives: Some(vec![string.clone()]),
+            },
+            exact: Some(string),
+            anchored: None,
+        }
+    }
+
+    /// Constructs the info of an anchor or a lookaround: it matches the empty string, but only at
+    /// some positions, so it's no literal, even repeated or next to one.
+    fn anchor() -> Self {
+        Self {
+            anchored: Some(String::new()),
+            ..Self::default()
+        }
+    }
+
+    /// Returns the only string the part matches, if there's one, whether anchored or not.
+    fn string(&self) -> Option<&String> {
+        self.exact.as_ref().or(self.anchored.as_ref())
+    }
+
+    /// Returns the info of the first part followed by the second.
+    ///
+    /// * `first`:
+    /// * `second`:
+    fn concat(first: Self, second: Self) -> Self {
+        let prefix = match first.string() {
+            Some(string) => string.clone() + &second.literals.prefix,
+            None => first.literals.prefix.clone(),
+        };
+        let suffix = match second.string() {
+            Some(string) => first.literals.suffix.clone() + string,
+            None => second.literals.suffix.clone(),
+        };
+        let string = first
+            .string()
+            .zip(second.string())
+            .map(|(a, b)| a.clone() + b);
+        let (exact, anchored) = if first.exact.is_some() && second.exact.is_some() {
+            (string, None)
+        } else {
+            (None, string)
+        };
+        // the end of the first part and the start of the second are always next to each other.
+        let across = first.literals.suffix + &second.literals.prefix;
+        let inner = longest([
+            first.literals.inner,
+            second.literals.inner,
+            across,
+            prefix.clone(),
+            suffix.clone(),
+        ]);
+        let alternatives = first
+            .literals
+            .alternatives
+            .zip(second.literals.alternatives)
+            .filter(|(a, b)| a.len() * b.len() <= MAX_ALTERNATIVES)
+            .map(|(a, b)| {
+                a.iter()
+                    .flat_map(|x| b.iter().map(move |y| x.clone() + y))
+                    .collect()
+            });
+        Self {
+            exact,
+            anchored,
+            literals: Literals {
+                prefix,
+                inner,
+                suffix,
+                alternatives,
+            },
+        }
+    }
+
+    /// Returns the info of either part.
+    ///
+    /// * `first`:
+    /// * `second`:
+    fn alternate(first: Self, second: Self) -> Self {
+        let (a, b) = (&first.literals, &second.literals);
+        let prefix: String = a
+            .prefix
+            .chars()
+            .zip(b.prefix.chars())
+            .take_while(|(x, y)| x == y)
+            .map(|(x, _)| x)
+            .collect();
+        let mut suffix: Vec<char> = a
+            .suffix
+            .chars()
+            .rev()
+            .zip(b.suffix.chars().rev())
+            .take_while(|(x, y)| x == y)
+            .map(|(x, _)| x)
+            .collect();
+        suffix.reverse();
+        let suffix: String = suffix.into_iter().collect();
+        let inner = longest([prefix.clone(), suffix.clone()]);
+        let alternatives = first
+            .literals
+            .alternatives
+            .zip(second.literals.alternatives)
+            .map(|(mut a, b)| {
+                a.extend(b);
+                a
+            });
+        Self {
+            exact: first
+                .exact
+                .filter(|exact| second.exact.as_ref() == Some(exact)),
+            anchored: None,
+            literals: Literals {
+                prefix,
+                inner,
+                suffix,
+                alternatives,
+            },
+        }
+    }
+
+    /// Returns the info of one or more repetitions of a part: it still starts, ends with and
+    /// contains the same strings, but it isn't exact anymore.
+    ///
+    /// * `info`:
+    fn at_least_once(info: Self) -> Self {
+        Self {
+            exact: None,
+            anchored: None,
+            literals: Literals {
+                alternatives: None,
+                ..info.literals
+            },
+        }
+    }
+
+    /// Returns the info of a part made optional: nothing is known about it anymore, except its
+    /// alternatives, which now include the empty string.
+    ///
+    /// * `info`:
+    fn optional(info: Self) -> Self {
+        let mut alternatives = info.literals.alternatives;
+        if let Some(alternatives) = alternatives.as_mut() {
+            alternatives.push(String::new());
+        }
+        Self {
+            exact: None,
+            anchored: None,
+            literals: Literals {
+                alternatives,
+                ..Literals::default()
+            },
+        }
+    }
+}
+
+/// Returns the longest of the strings passed in, the first one if there's a tie.
+///
+/// * `strings`:
+fn longest<const N: usize>(strings: [String; N]) -> String {
+    strings
+        .into_iter()
+        .reduce(|best, string| {
+            if string.len() > best.len() {
+                string
+            } else {
+                best
+            }
+        })
+        .unwrap_or_default()
+}
+
+impl Literals {
+    /// Finds the literals of the pattern whose postfix token list is passed in.
+    ///
+    /// * `postfix`: the tokens, in postfix order, as the postfix converter outputs them.
+    pub fn new<'a>(postfix: impl Iterator<Item = &'a Token>) -> Self {
+        // like the NFA stack of the parser, which starts with an empty NFA.
+        let mut stack: Vec<Info> = vec![Info::exact(String::new())];
+        for token in postfix {
+            let info = match &token.token_type {
+                TokenType::Concat => {
+                    let second = stack.pop().unwrap_or_default();
+                    let first = stack.pop().unwrap_or_default();
+                    Info::concat(first, second)
+                }
+                TokenType::Beam => {
+                    let second = stack.pop().unwrap_or_default();
+                    let first = stack.pop().unwrap_or_else(|| Info::exact(String::new()));
+                    Info::alternate(first, second)
+                }
+                TokenType::Plus { .. } => Info::at_least_once(stack.pop().unwrap_or_default()),
+                TokenType::QuestionMark { .. } => Info::optional(stack.pop().unwrap_or_default()),
+                TokenType::Star { .. } => {
+                    stack.pop();
+                    Info::default()
+                }
+                TokenType::Repeat { min, max, .. } => {
+                    let info = stack.pop().unwrap_or_default();
+                    match (&info.exact, max) {
+                        (Some(exact), Some(max))
+                            if max == min
+                                && exact.len().saturating_mul(*min) <= MAX_REPEATED_LEN =>
+                        {
+                            Info::exact(exact.repeat(*min))
+                        }
+                        _ if *min > 0 => Info::at_least_once(info),
+                        _ => Info::default(),
+                    }
+                }
+                TokenType::Group(_) => stack.pop().unwrap_or_default(),
+                // like an anchor: whatever the lookaround looks at isn't part of the match.
+                TokenType::Lookaround(_) => {
+                    stack.pop();
+                    Info::anchor()
+                }
+                TokenType::Character(c) => Info::exact(c.to_string()),
+                TokenType::Dot | TokenType::CharClass(_) => Info::default(),
+                // the anchors match the empty string, but only at some positions: they're no
+                // literal.
+                TokenType::Hat
+                | TokenType::Dollar
+                | TokenType::LineStart { .. }
+                | TokenType::LineEnd { .. }
+                | TokenType::EndNewLine
+                | TokenType::WordBoundary { .. }
+                | TokenType::NotWordBoundary { .. } => Info::anchor(),
+                _ => Info::exact(String::new()),
+            };
+            stack.push(info);
+        }
+
+        let last = stack.pop().unwrap_or_default();
+        match stack.pop() {
+            Some(first) => Info::concat(first, last).literals,
+            None => last.literals,
+        }
+    }
+}
@@ -7,11 +7,17 @@ use crate::lexer::token_type::{Token, TokenType};
 /// in that function call precedes the NFA passed in as the other parameter.
 ///
 /// * `states`: a vector of all states contained by the NFA. The beginning of the NFA is guaranteed
-///             to be element 0 of the NFA.
+///   to be element 0 of the NFA.
 /// * `end`: the final state of the current NFA.
+/// * `alternation`: whether this NFA is exactly an alternation, as built by `Parser::handle_beam`:
+///   state 0 goes to the start of each branch, and the end of each branch goes to `end`, which goes
+///   nowhere. Another branch can then be added at the same level, rather than nesting the
+///   alternation inside a new one.
+#[derive(Clone)]
 pub(crate) struct Nfa {
     pub states: Vec<State>,
     pub end: usize,
+    pub alternation: bool,
 }
 
 impl Nfa {
@@ -24,65 +30,167 @@ impl Nfa {
         Self {
             states: vec![State::new(first_input)],
             end: 0,
+            alternation: false,
         }
     }
 
     #[inline]
-    /// Adds the specified state to the NFA.
-    /// IMPORTANT: `Nfa::end` is assumed to be the last element in the NFA.
+    /// Adds the specified state to the NFA, which becomes the new end.
     ///
     /// * `state`:
     pub fn add_state(&mut self, state: State) {
         self.states.push(state);
-        self.end += 1;
+        self.end = self.states.len() - 1;
     }
 
     /// Merges 2 NFAs together. The `self` NFA precedes the `another` NFA, that is, `self::end`
     /// points to `another::start` (which is, the first element in `another`'s states list).
     ///
     /// * `another`:
-    pub fn merge(&mut self, mut another: Nfa) {
+    pub fn merge(&mut self, another: Nfa) {
+        self.merge_with(another, true);
+    }
+
+    /// Same as `Nfa::merge`, but never fuses `self::end` with `another::start`. Useful when more
+    /// edges will be added to `self::end` afterwards, which would otherwise leak into `another`.
+    ///
+    /// * `another`:
+    pub fn merge_unfused(&mut self, another: Nfa) {
+        self.merge_with(another, false);
+    }
+
+    /// The implementation of `Nfa::merge` and `Nfa::merge_unfused`.
+    ///
+    /// * `another`:
+    /// * `allow_fusion`: whether `self::end` and `another::start` may be fused into one state.
+    fn merge_with(&mut self, mut another: Nfa, allow_fusion: bool) {
         // skips?
         //
         // It's for a small optimization. When the start of `another` and the end of `self` are
         // both `TokenType::Empty`, this function simulates fusing those 2 states into one.
+        //
+        // The fusion is skipped if both states are part of a loop: `self::end` points back into
+        // `self` (the end of a star), and something in `another` points back to its start. Fusing
+        // those would let the matcher go from the end of `another` back into `self`, so (a|b)*c*
+        // would match "ca".
 
         let skips = {
-            let self_end_type = &self.states[self.end].token.token_type;
+            let self_end = &self.states[self.end];
             let another_start_type = &another.states[0].token.token_type;
+            let loops_twice = !self_end.edges.is_empty() && another.loops_to_start();
 
-            if *self_end_type == TokenType::Empty && *another_start_type == TokenType::Empty {
+            if allow_fusion
+                && self_end.token.token_type == TokenType::Empty
+                && *another_start_type == TokenType::Empty
+                && !loops_twice
+            {
                 1
             } else {
                 0
             }
         };
 
+        // where the states of `another` land. The start of `another` lands on `self::end` if the
+        // two are fused.
         let self_old_len = self.states.len() - skips;
-        if skips == 1 {
-            let self_end_state = &mut self.states[self.end];
-            for edge in another.states[0].edges.iter() {
-                // alternative to edge.0.clone() would be to remove the front state of another.
-                self_end_state.add_edge(edge + self_old_len);
+        let fused_end = self.end;
+        let relocate = |index: usize| {
+            if skips == 1 && index == 0 {
+                fused_end
+            } else {
+                index + self_old_len
             }
-        }
+        };
 
         for state in another.states.iter_mut() {
             for edge in state.edges.iter_mut() {
-                *edge += self_old_len;
+                *edge = relocate(*edge);
             }
         }
-        // skips == 1 already "connects" the start and end together.
-        if skips == 0 {
-            self.states[self.end]
-                .add_edge(self_old_len);
+        if skips == 1 {
+            let another_start = another.states[0].edges.clone();
+            self.states[self.end].edges.extend(another_start);
+        } else {
+            // skips == 1 already "connects" the start and end together.
+            self.states[self.end].add_edge(self_old_len);
         }
+        let another_end = relocate(another.end);
         self.states.extend(another.states.into_iter().skip(skips));
 
-        self.end = self.states.len() - 1;
+        self.end = another_end;
+        self.alternation = false;
     }
 
+    /// Returns whether some state points back to the start of this NFA.
+    pub fn loops_to_start(&self) -> bool {
+        self.states.iter().any(|state| state.edges.contains(&0))
+    }
 
+    /// Returns whether this NFA, merged after another one, matches the empty string, that is,
+    /// whether its end is reachable from its start without consuming a character. The anchors and
+    /// lookarounds are assumed to hold.
+    pub fn matches_empty(&self) -> bool {
+        if self.states[0].token.token_type.consumes() {
+            return false;
+        }
+        let mut visited = vec![false; self.states.len()];
+        let mut stack = vec![0];
+        while let Some(state_ref) = stack.pop() {
+            if state_ref == self.end {
+                return true;
+            }
+            for &next in self.states[state_ref].edges.iter() {
+                if !visited[next] && !self.states[next].token.token_type.consumes() {
+                    visited[next] = true;
+                    stack.push(next);
+                }
+            }
+        }
+        false
+    }
+
+    /// Returns the reverse of this NFA: it matches the reverse of the strings this one matches,
+    /// read from the end towards the start.
+    ///
+    /// Every edge is flipped, and the start and the end swap roles. Since a state's token is
+    /// checked when entering it, the consuming states still consume the same characters on the
+    /// flipped paths, just in the other order. But the start of a NFA never checks its own token,
+    /// and the end is never left, so both get an `Empty` state of their own:
+    ///
+    /// (start)─>─(end + 1)─<flipped states, shifted by 1>─<─(1)─>─(end)
+    ///
+    /// The anchors stay where they are. A position is checked against the same characters around
+    /// it whichever way the string is read.
+    pub fn reverse(&self) -> Nfa {
+        let len = self.states.len();
+        let mut states: Vec<State> = Vec::with_capacity(len + 2);
+        states.push(State::new(Token::new(0, TokenType::Empty)));
+        states[0].add_edge(self.end + 1);
+        states.extend(
+            self.states
+                .iter()
+                .map(|state| State::new(state.token.clone())),
+        );
+        for (idx, state) in self.states.iter().enumerate() {
+            // the matcher stops at the end: its edges are never taken.
+            if idx == self.end {
+                continue;
+            }
+            for &next in state.edges.iter() {
+                states[next + 1].add_edge(idx + 1);
+            }
+        }
+        states[1].add_edge(len + 1);
+        states.push(State::new(Token::new(0, TokenType::Empty)));
+
+        Nfa {
+            states,
+            end: len + 1,
+            alternation: false,
+        }
+    }
+
+    #[allow(dead_code)]
     #[inline]
     /// A more graceful way of accessing the NFA's state.
     /// But the author may be too lazy to use this function :(.
@@ -102,7 +210,10 @@ impl Nfa {
             println!("state (index {idx}): {}", state.token.token_type);
             print!("\tedges: ");
             for edge in state.edges.iter() {
-                print!("{}, to index {}; ", self.states[*edge].token.token_type, edge);
+                print!(
+                    "{}, to index {}; ",
+                    self.states[*edge].token.token_type, edge
+                );
             }
             println!();
         }
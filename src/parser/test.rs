@@ -18,3 +18,175 @@ fn simple_parse_test() {
     println!("Regex string: {rstr}");
     regex_handle.nfa.print_states();
 }
+
+#[test]
+fn epsilon_closure_test() {
+    use crate::parser::closure::{Assertions, EpsilonClosures, Target};
+
+    let regex = Parser::new("a?(^b|$)").unwrap().parse().unwrap();
+    let closures = EpsilonClosures::new(&regex.nfa).unwrap();
+    let from_start = closures.of(0);
+    let consumed = |c: char| {
+        from_start.iter().find_map(|entry| match entry.target {
+            Target::Consume(state_ref)
+                if regex.nfa.states[state_ref].token.token_type.accepts(c) =>
+            {
+                Some(entry.needs)
+            }
+            _ => None,
+        })
+    };
+    // "a" needs nothing, "b" needs the start of the string, and the match needs the end.
+    assert_eq!(consumed('a'), Some(Assertions::default()));
+    assert_eq!(consumed('b'), Some(Assertions::START_TEXT));
+    assert!(from_start
+        .iter()
+        .any(|entry| entry.target == Target::Match && entry.needs == Assertions::END_TEXT));
+    assert!(from_start
+        .iter()
+        .all(|entry| entry.target != Target::Match || entry.needs != Assertions::default()));
+
+    // the end state's own closure is always a match.
+    assert_eq!(closures.of(regex.nfa.end)[0].target, Target::Match);
+}
+
+#[test]
+fn byte_classes_test() {
+    use crate::parser::byte_classes::ByteClasses;
+
+    let regex = Parser::new("[a-z]+@x").unwrap().parse().unwrap();
+    let classes = ByteClasses::new(&regex.nfa);
+    // \n, '@', 'x', the rest of [a-z], and everything else.
+    assert_eq!(classes.len(), 5);
+    assert_eq!(classes.get('a'), classes.get('w'));
+    assert_eq!(classes.get('a'), classes.get('z'));
+    assert_ne!(classes.get('a'), classes.get('x'));
+    assert_ne!(classes.get('a'), classes.get('@'));
+    assert_ne!(classes.get('\n'), classes.get('A'));
+    // the characters the pattern never mentions all share a class, in the table or past it.
+    assert_eq!(classes.get('\0'), classes.get('A'));
+    assert_eq!(classes.get('A'), classes.get('{'));
+    assert_eq!(classes.get('A'), classes.get('é'));
+    assert_eq!(classes.get('A'), classes.get('👍'));
+    assert_eq!(classes.get('A'), classes.get(char::MAX));
+    for class in 0..classes.len() {
+        assert_eq!(classes.get(classes.representative(class)), class);
+    }
+
+    // a negated class splits the characters the same way.
+    let regex = Parser::new("[^a-z]é|.").unwrap().parse().unwrap();
+    let classes = ByteClasses::new(&regex.nfa);
+    assert_eq!(classes.len(), 4);
+    assert_eq!(
+        classes.get('é'),
+        classes.get(classes.representative(classes.get('é')))
+    );
+    assert_ne!(classes.get('é'), classes.get('è'));
+}
+
+#[test]
+fn literals_test() {
+    use crate::lexer::token_type::Token;
+    use crate::parser::literals::Literals;
+
+    let literals = |pattern: &str| {
+        let parser = Parser::new(pattern).unwrap();
+        let postfix: Vec<Token> = parser.postfix_stack.iter().rev().cloned().collect();
+        let literals = Literals::new(postfix.iter());
+        (literals.prefix, literals.inner, literals.suffix)
+    };
+    let owned = |(prefix, inner, suffix): (&str, &str, &str)| {
+        (prefix.to_owned(), inner.to_owned(), suffix.to_owned())
+    };
+
+    assert_eq!(literals("ERROR:.*"), owned(("ERROR:", "ERROR:", "")));
+    assert_eq!(
+        literals("^ERROR: [a-z]+$"),
+        owned(("ERROR: ", "ERROR: ", ""))
+    );
+    assert_eq!(literals("[a-z_]+\\.cpp"), owned(("", ".cpp", ".cpp")));
+    // only the common start of the alternatives is known.
+    assert_eq!(literals("\\.(cpp|cxx)$"), owned((".c", ".c", "")));
+    assert_eq!(literals("a(bc|dc)+e"), owned(("a", "ce", "ce")));
+    assert_eq!(literals("(ab){3}"), owned(("ababab", "ababab", "ababab")));
+    // the longest literal in the middle.
+    assert_eq!(
+        literals("[0-9]+ warnings? and [0-9]+ errors"),
+        owned(("", " warning", " errors"))
+    );
+    assert_eq!(literals("x?y"), owned(("", "y", "y")));
+    assert_eq!(literals("(?i)error"), owned(("", "", "")));
+    assert_eq!(literals("a|b"), owned(("", "", "")));
+    assert_eq!(literals(""), owned(("", "", "")));
+}
+
+#[test]
+fn flat_alternation_test() {
+    use crate::lexer::token_type::TokenType;
+    use crate::parser::closure::{EpsilonClosures, Target};
+
+    // one empty state fans out to every branch, and one joins them, however many there are.
+    let regex = Parser::new("ab|c|d|e").unwrap().parse().unwrap();
+    assert_eq!(regex.nfa.states.len(), 7);
+    let closures = EpsilonClosures::new(&regex.nfa).unwrap();
+    let firsts: Vec<char> = closures
+        .of(0)
+        .iter()
+        .map(|entry| match entry.target {
+            Target::Consume(state_ref) => match regex.nfa.states[state_ref].token.token_type {
+                TokenType::Character(c) => c,
+                _ => unreachable!(),
+            },
+            Target::Match => unreachable!(),
+        })
+        .collect();
+    // in the order of the branches.
+    assert_eq!(firsts, ['a', 'c', 'd', 'e']);
+
+    let literals = |pattern: &str| {
+        let parser = Parser::new(pattern).unwrap();
+        let postfix: Vec<_> = parser.postfix_stack.iter().rev().cloned().collect();
+        crate::parser::literals::Literals::new(postfix.iter()).alternatives
+    };
+    let owned = |strings: &[&str]| Some(strings.iter().map(|s| s.to_string()).collect());
+    assert_eq!(literals("ab|c|d|e"), owned(&["ab", "c", "d", "e"]));
+    assert_eq!(literals("(a|b)(c|d)"), owned(&["ac", "ad", "bc", "bd"]));
+    assert_eq!(literals("colou?r"), owned(&["colour", "color"]));
+    assert_eq!(literals("a|b*"), None);
+}
+
+#[test]
+fn glushkov_test() {
+    use crate::parser::glushkov::Glushkov;
+
+    let glushkov = |pattern: &str| {
+        let parser = Parser::new(pattern).unwrap();
+        Glushkov::new(parser.postfix_stack.iter().rev())
+    };
+
+    // a, b and c: b and c both follow a, b and c.
+    let a_bc = glushkov("a(b|c)*").unwrap();
+    assert_eq!(a_bc.tokens.len(), 3);
+    assert_eq!(
+        (a_bc.first, a_bc.last, a_bc.nullable),
+        (0b001, 0b111, false)
+    );
+    assert_eq!(a_bc.follow, [0b110, 0b110, 0b110]);
+
+    // x{2,3} copies x: xxx?.
+    let repeat = glushkov("(xy?){2,3}").unwrap();
+    assert_eq!(repeat.tokens.len(), 6);
+    assert_eq!((repeat.first, repeat.last), (0b000001, 0b111100));
+    assert_eq!(
+        repeat.follow,
+        [0b000110, 0b000100, 0b011000, 0b010000, 0b100000, 0]
+    );
+    assert!(glushkov("(a|b?)*").unwrap().nullable);
+    assert!(glushkov("").unwrap().nullable);
+
+    // 64 positions fit, not 65. Neither do anchors.
+    assert!(glushkov("[a-z]{64}").is_some());
+    assert!(glushkov("[a-z]{65}").is_none());
+    assert!(glushkov("a{40}b{40}").is_none());
+    assert!(glushkov("^a").is_none());
+}
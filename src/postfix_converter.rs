@@ -9,7 +9,7 @@ use std::vec::Vec;
 ///
 /// * `infix_token_stack`: basically the infix token list from `Scanner` but reversed.
 /// * `postfix_token_list`: the resultant token list. Remains invalid until
-///                         `PostfixConverter::convert` is called.
+///   `PostfixConverter::convert` is called.
 /// * `symbol_stack`: the symbol stack, used for temporarily holding symbols.
 /// * `done`: whether the conversion is finished.
 pub(crate) struct PostfixConverter {
@@ -42,14 +42,14 @@ impl PostfixConverter {
     pub fn convert(&mut self) -> Result<(), String> {
         while let Some(tok) = self.infix_token_stack.pop() {
             if !tok.token_type.is_symbol() {
-                self.push_non_symbol(tok)?;
+                self.push_non_symbol(tok);
             } else {
                 self.push_symbol(tok)?;
             }
         }
 
         while let Some(tok) = self.symbol_stack.pop() {
-            if tok.token_type == TokenType::LParen {
+            if let TokenType::LParen(_) = tok.token_type {
                 return Err(format!("Character ( at {0}: extra (", tok.pos + 1));
             }
             self.postfix_token_list.push(tok);
@@ -79,21 +79,8 @@ impl PostfixConverter {
     /// Handles the non-symbol characters passed in.
     ///
     /// * `tok`: the token passed in.
-    fn push_non_symbol(&mut self, tok: Token) -> Result<(), String> {
-        match tok.token_type {
-            // TokenType::Hat is handled by the `Scanner`.
-            TokenType::Dollar => {
-                if !self.is_end() {
-                    return Err(format!(
-                        "Character {0} at {1}: {0} not at end",
-                        tok.token_type, tok.pos
-                    ));
-                }
-                self.postfix_token_list.push(tok);
-            }
-            _ => self.postfix_token_list.push(tok),
-        }
-        Ok(())
+    fn push_non_symbol(&mut self, tok: Token) {
+        self.postfix_token_list.push(tok);
     }
 
     /// Handles the token passed in. This symbol is assumed to be the symbol just `pop`ped from
@@ -110,16 +97,21 @@ impl PostfixConverter {
         // LParen has lowest precedence, hence is pushed right away onto the symbol stack.
         // RParen will not push itself onto the symbol stack, but rather, it's a signal that every
         // symbol popped up until the first left parentheses should be pushed onto the postfix token
-        // list. Then, that left parentheses is also removed from the stack.
+        // list. Then, that left parentheses is also removed from the stack, and replaced by a
+        // group symbol on the postfix token list, so that the parser knows what to capture.
         // All the other tokens work as described in `TokenType::precedence`. Following this logic,
         // characters and quantifiers get pushed straight to the postfix token list.
         match tok.token_type {
-            TokenType::LParen => {
+            TokenType::LParen(_) => {
                 self.symbol_stack.push(tok);
             }
             TokenType::RParen => {
                 while let Some(pop_tok) = self.symbol_stack.pop() {
-                    if pop_tok.token_type == TokenType::LParen {
+                    if let TokenType::LParen(group) = pop_tok.token_type {
+                        if let Some(idx) = group {
+                            self.postfix_token_list
+                                .push(Token::new(pop_tok.pos, TokenType::Group(idx)));
+                        }
                         return Ok(());
                     }
                     self.postfix_token_list.push(pop_tok);
@@ -140,17 +132,21 @@ impl PostfixConverter {
             TokenType::Beam => match self.symbol_stack.last() {
                 None
                 | Some(Token {
-                    token_type: TokenType::LParen,
+                    token_type: TokenType::LParen(_),
                     ..
                 }) => {
                     self.symbol_stack.push(tok);
                 }
                 Some(_) => {
-                    self.postfix_token_list.push(
-                        self.symbol_stack
-                            .pop()
-                            .unwrap(),
-                    );
+                    // everything down to the parentheses binds tighter, or is an earlier beam.
+                    // Popping them all keeps a|b|c left-associative, (a|b)|c, so the parser can
+                    // keep adding branches to the same alternation.
+                    while let Some(pop_tok) = self
+                        .symbol_stack
+                        .pop_if(|top| !matches!(top.token_type, TokenType::LParen(_)))
+                    {
+                        self.postfix_token_list.push(pop_tok);
+                    }
                     self.symbol_stack.push(tok);
                 }
             },
@@ -162,18 +158,6 @@ impl PostfixConverter {
 
         Ok(())
     }
-
-    /// Returns whether the last symbol on the infix token stack (aka, the next symbol) is:
-    /// * A right parentheses or a beam,
-    /// * Non-existent.
-    ///
-    /// For now this is only used to catch syntax error related to the $ anchor.
-    fn is_end(&self) -> bool {
-        match self.infix_token_stack.last() {
-            None => true,
-            Some(tok) => tok.token_type == TokenType::RParen || tok.token_type == TokenType::Beam,
-        }
-    }
 }
 
 mod test;
@@ -40,17 +40,18 @@ fn complex_postfix_test() {
         TokenType::Character('b'),
         TokenType::Concat, // Concat a and b
         TokenType::Character('c'),
-        TokenType::QuestionMark,
+        TokenType::QuestionMark { greedy: true },
         TokenType::Concat, // Concat ab and c?
         TokenType::Character('c'),
-        TokenType::Beam,   // either abc? or c
-        TokenType::Plus,   // one or more of (abc?|c)
-        TokenType::Concat, // concat z with (abc?|c)+
+        TokenType::Beam,                  // either abc? or c
+        TokenType::Group(1),              // capture (abc?|c)
+        TokenType::Plus { greedy: true }, // one or more of (abc?|c)
+        TokenType::Concat,                // concat z with (abc?|c)+
         TokenType::Character('.'),
-        TokenType::QuestionMark,
+        TokenType::QuestionMark { greedy: true },
         TokenType::Concat, // concat z(abc?|c)+ with \.?
         TokenType::Dot,
-        TokenType::Star,
+        TokenType::Star { greedy: true },
         TokenType::Concat, // concat z(abc?|c)+\.? with .*
     ];
 
@@ -64,3 +65,28 @@ fn complex_postfix_test() {
             == test_vec.len()
     )
 }
+
+#[test]
+fn left_associative_beam_test() {
+    let mut test_scanner = Scanner::new("ab|c|d");
+    test_scanner.scan().unwrap();
+    let mut test_pfix_conv = PostfixConverter::from_scanner(test_scanner);
+    test_pfix_conv.convert().unwrap();
+
+    // ((ab)|c)|d, so that the parser adds each branch to the same alternation.
+    let test_vec = [
+        TokenType::Character('a'),
+        TokenType::Character('b'),
+        TokenType::Concat,
+        TokenType::Character('c'),
+        TokenType::Beam,
+        TokenType::Character('d'),
+        TokenType::Beam,
+    ];
+    let token_types: Vec<&TokenType> = test_pfix_conv
+        .postfix_token_list
+        .iter()
+        .map(|tok| &tok.token_type)
+        .collect();
+    assert_eq!(token_types, test_vec.iter().collect::<Vec<_>>());
+}
@@ -1,12 +1,50 @@
 #![allow(dead_code)]
-use std::collections::{BTreeSet, HashSet, LinkedList};
-
-use crate::lexer::token_type::TokenType;
+use std::collections::{HashMap, LinkedList};
+use std::ops::ControlFlow;
+use std::str::Chars;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::parser::byte_classes::ByteClasses;
+use crate::parser::closure::{self, Assertions, EpsilonClosures, Target};
+use crate::parser::glushkov::Glushkov;
+use crate::parser::literals::Literals;
 use crate::parser::nfa::Nfa;
 use crate::parser::Parser;
 
+mod aho_corasick;
+mod bit_parallel;
+mod builder;
+mod cache;
+mod captures;
+mod dfa;
+mod lazy_dfa;
+mod lookaround;
+mod pike_vm;
+mod prefilter;
+mod reverse_search;
+mod sparse_set;
+use aho_corasick::AhoCorasick;
+use bit_parallel::BitParallel;
+pub(crate) use builder::Options;
+pub use builder::{MatchKind, RareBuilder};
+pub use cache::Cache;
+use cache::CurrStatesData;
+pub use captures::{Captures, CapturesIter};
+use dfa::Dfa;
+pub use dfa::DfaSize;
+pub(crate) use lookaround::{Lookaround, Lookarounds};
+use pike_vm::PikeVm;
+use prefilter::Prefilter;
+use reverse_search::ReverseSearch;
+pub(crate) use sparse_set::SparseSet;
+
 // use rustc_hash::FxHashSet;
 
+/// How many times the length of the string the forward searches of `RARE::match_all` may read in
+/// total, before the reverse NFA takes over.
+const FORWARD_BUDGET: usize = 8;
+
 /// An encapsulated object over the parse result of the `Parser`. Obtained by calling the method
 /// `RARE::new`
 ///
@@ -14,53 +52,183 @@ use crate::parser::Parser;
 /// doesn't change the `RARE` instance they hold.
 ///
 /// Also the name of the project.
+///
+/// * `id`: unique to each instance.
+/// * `nfa`: the compiled regular expression.
+/// * `closures`: the epsilon closure of each NFA state, used by the matcher. `None` if they're too
+///   big, the empty edges are walked instead.
+/// * `reverse`: the reverse NFA, which `RARE::match_all` finds the matches with.
+/// * `classes`: the alphabet of `nfa`, used by the DFAs.
+/// * `bit_parallel`: replaces the NFA simulation if the expression has few enough positions.
+/// * `dfa`: the full DFA, if the options asked for it and it fit in the size limit.
+/// * `prefilter`: finds where matches may start, from the literals of the expression.
+/// * `literal_set`: replaces the automata if the expression is an alternation of literals.
+/// * `lookarounds`: the lookarounds of the expression, checked where the NFA goes through them.
+/// * `options`: the options the expression was compiled with.
+/// * `capture_names`: the name of each capture group, `None` if unnamed. Element 0 is group 0, the
+///   whole match.
+/// * `repeated_groups`: whether each capture group is inside a quantifier that may go through it
+///   more than once, indexed like `capture_names`. See `PikeVm`.
+/// * `name_table`: maps the name of each named group to its index. Shared with every `Captures`.
 pub struct RARE {
+    id: usize,
     pub(crate) nfa: Nfa,
+    closures: Option<EpsilonClosures>,
+    reverse: ReverseSearch,
+    classes: ByteClasses,
+    bit_parallel: Option<BitParallel>,
+    dfa: Option<Dfa>,
+    prefilter: Prefilter,
+    literal_set: Option<AhoCorasick>,
+    lookarounds: Lookarounds,
+    options: Options,
+    capture_names: Vec<Option<String>>,
+    repeated_groups: Vec<bool>,
+    name_table: Arc<HashMap<String, usize>>,
 }
 
-/// Memo lists for the current list of states that is being processed. Also provides a next_states
-/// list, which is swapped with curr_states after curr_states is emptied in the function
-/// `step_once`. The latter is mainly to avoid reallocation.
-///
-/// The fact that this is a struct separate from `RARE` also means that you can match RARE with
-/// however many threads you like, since each time a match function is called, an instance of this
-/// is constructed.
-///
-/// * `curr_states`:
-/// * `next_states`:
-struct CurrStatesData {
-    // the results between BTreeSet and HashSet is benchmarked by. It's somewhat surprising that
-    // BTreeSet runs faster than HashSet.
-    // The current theory I have is, if I use a HashSet, I need to drain the set and collect using
-    // a newly allocated vector before adding elements again; otherwise, the borrow checker isn't
-    // happy with me. For a BTreeSet though, since the elements are always in a specific order, I
-    // can pop an element off and insert a new one without much trouble.
-    curr_states: BTreeSet<usize>,
-    // a temporary list that gets swapped with curr_states after curr_states is emptied.
-    next_states: BTreeSet<usize>,
-    all_empty_match: bool,
-}
-
-// #[derive(Clone)]
 /// Holds information about the current string matched. The entire point of this struct is to
 /// remove any possibly modifiable members out of `RARE`, making any `RARE` instance thread-safe,
 /// at the cost of some memory to hold each of this object.
 ///
-/// * `strlen`:
-/// * `curr_pos`:
-/// * `curr_char`:
-struct StringIterData {
-    strlen: usize,
+/// This is a forward-only cursor over the string: each step decodes exactly one character off the
+/// front of what's left, so walking the whole string is linear. Going back is only possible with
+/// `StringIterData::restart`, which `RARE::match_all` uses to start over from the end of a match.
+/// Every position the cursor holds is a byte offset on a char boundary.
+///
+/// * `string`: the string matched.
+/// * `rest`: the characters after `curr_char`.
+/// * `curr_pos`: the byte offset of `curr_char`.
+/// * `curr_char`: the character at `curr_pos`, `None` at the end of the string.
+/// * `prev_char`: the character right before `curr_pos`, `None` at the start of the string. Used by
+///   the multi-line and word boundary anchors.
+struct StringIterData<'s> {
+    string: &'s str,
+    rest: Chars<'s>,
     curr_pos: usize,
     curr_char: Option<char>,
+    prev_char: Option<char>,
+}
+
+impl<'s> StringIterData<'s> {
+    /// Constructs a cursor at the start of the string passed in.
+    ///
+    /// * `string`:
+    fn new(string: &'s str) -> Self {
+        let mut rest = string.chars();
+        Self {
+            string,
+            curr_char: rest.next(),
+            rest,
+            curr_pos: 0,
+            prev_char: None,
+        }
+    }
+
+    #[inline]
+    /// Moves the cursor past the current character.
+    ///
+    /// * Return: `false` if the cursor is already at the end of the string, in which case it
+    ///   doesn't move.
+    fn advance(&mut self) -> bool {
+        let Some(c) = self.curr_char else {
+            return false;
+        };
+        self.curr_pos += c.len_utf8();
+        self.prev_char = Some(c);
+        self.curr_char = self.rest.next();
+        true
+    }
+
+    /// Moves the cursor to the byte offset passed in, forwards or backwards.
+    ///
+    /// * `pos`: a byte offset on a char boundary.
+    fn restart(&mut self, pos: usize) {
+        self.rest = self.string[pos..].chars();
+        self.curr_pos = pos;
+        self.curr_char = self.rest.next();
+        self.prev_char = self.string[..pos].chars().next_back();
+    }
 }
 
 impl RARE {
-    /// Constructs a `Regex` from a NFA. Should only be called by the `Parser`
+    /// Constructs a `Regex` from a NFA. Should only be called by the `Parser`.
     ///
     /// * `nfa`:
-    pub(crate) fn from_nfa(nfa: Nfa) -> Self {
-        Self { nfa }
+    /// * `capture_names`: the name of each capture group inside the NFA, including group 0.
+    /// * `repeated_groups`: whether each capture group is inside a quantifier that may go through
+    ///   it more than once.
+    /// * `literals`: the literals every match contains.
+    /// * `glushkov`: the position automaton of the expression, if it has few enough positions.
+    /// * `lookarounds`: the lookarounds the NFA refers to.
+    /// * `options`: the options the NFA was compiled with.
+    pub(crate) fn from_nfa(
+        nfa: Nfa,
+        capture_names: Vec<Option<String>>,
+        repeated_groups: Vec<bool>,
+        literals: Literals,
+        glushkov: Option<Glushkov>,
+        lookarounds: Lookarounds,
+        options: Options,
+    ) -> Self {
+        // every instance gets its own id, so that a `Cache` knows which one it was built for.
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        let name_table = capture_names
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, name)| Some((name.clone()?, idx)))
+            .collect();
+        let closures = EpsilonClosures::new(&nfa);
+        let mut options = options;
+        // \Z looks 2 characters ahead, and a lookaround any number of them, where a DFA state
+        // only knows the character before. And the DFA states are built from the closures.
+        let dfa_closures = closures
+            .as_ref()
+            .filter(|closures| !closures.anchors().intersects(Assertions::END_NEW_LINE))
+            .filter(|_| lookarounds.is_empty());
+        if dfa_closures.is_none() {
+            options.full_dfa = false;
+            options.dfa_cache_size = 0;
+        }
+        let reverse = ReverseSearch::new(&nfa);
+        let classes = ByteClasses::new(&nfa);
+        let bit_parallel = glushkov.map(|glushkov| BitParallel::new(&glushkov, &classes));
+        let dfa = match (options.full_dfa, dfa_closures) {
+            (true, Some(closures)) => Dfa::new(&nfa, closures, &classes, options.dfa_size_limit),
+            _ => None,
+        };
+        // Aho–Corasick finds the longest literal at the leftmost position, not the first one.
+        let literal_set = match &literals.alternatives {
+            Some(set)
+                if options.prefilter
+                    && options.match_kind == MatchKind::LeftmostLongest
+                    && set.len() > 1
+                    && !set.contains(&String::new()) =>
+            {
+                Some(AhoCorasick::new(set))
+            }
+            _ => None,
+        };
+        let prefilter = match options.prefilter {
+            true => Prefilter::new(literals),
+            false => Prefilter::default(),
+        };
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            closures,
+            reverse,
+            bit_parallel,
+            classes,
+            dfa,
+            prefilter,
+            literal_set,
+            lookarounds,
+            nfa,
+            options,
+            capture_names,
+            repeated_groups,
+            name_table: Arc::new(name_table),
+        }
     }
 
     /// Constructs a new `RARE` matcher.
@@ -75,104 +243,90 @@ impl RARE {
     ///
     /// * `string`:
     pub fn is_match(&self, string: &str) -> bool {
-        let mut str_data = StringIterData {
-            strlen: string.len(),
-            curr_pos: 0,
-            curr_char: string.chars().nth(0),
-        };
+        self.is_match_with(&mut self.create_cache(), string)
+    }
 
-        let mut curr_state_data = CurrStatesData {
-            curr_states: {
-                let mut ret = BTreeSet::new();
-                ret.insert(0);
-                ret
-            },
-            next_states: BTreeSet::new(),
-            all_empty_match: true,
+    /// Same as `RARE::is_match`, but uses the cache passed in instead of allocating a new one.
+    ///
+    /// The lazy DFA states built by earlier calls are kept in the cache, so the more strings are
+    /// matched with the same cache, the less work each of them takes.
+    ///
+    /// * `cache`: obtained by calling `RARE::create_cache`.
+    /// * `string`:
+    pub fn is_match_with(&self, cache: &mut Cache, string: &str) -> bool {
+        if let Some(set) = &self.literal_set {
+            return set.is_match(string);
+        }
+        // no match can start before the first place the literals allow.
+        let Some(start) = self.prefilter.next_start(string, 0, true) else {
+            return false;
         };
-
-        while str_data.curr_pos < str_data.strlen + 1 {
-            if curr_state_data.curr_states.is_empty() {
-                curr_state_data.curr_states.insert(0);
+        if let Some(dfa) = &self.dfa {
+            return dfa.is_match(&self.classes, string, start);
+        }
+        cache.reset_for(self);
+        let curr_state_data = &mut cache.state_data;
+        curr_state_data.curr_states.clear();
+        curr_state_data.look_cache.clear();
+        let mut str_data = StringIterData::new(string);
+        str_data.restart(start);
+
+        if let Some(closures) = self
+            .closures
+            .as_ref()
+            .filter(|_| self.options.dfa_cache_size > 0)
+        {
+            match cache
+                .lazy_dfa
+                .is_match(&self.nfa, closures, &self.classes, string, start)
+            {
+                Ok(ret) => return ret,
+                // the DFA cache kept filling up. Go on with the NFA simulation from where the DFA
+                // stopped. The bit-parallel simulation doesn't track NFA states: it starts over.
+                Err(gave_up) if self.bit_parallel.is_none() => {
+                    for &state_ref in gave_up.nfa_states.iter() {
+                        curr_state_data.curr_states.insert(state_ref);
+                    }
+                    str_data.restart(gave_up.pos);
+                }
+                Err(_) => (),
             }
-            if self.step_once(&mut curr_state_data, &str_data) {
+        }
+        if let Some(bit_parallel) = &self.bit_parallel {
+            return bit_parallel.is_match(&self.classes, string, start);
+        }
+
+        loop {
+            // a match may start at any position.
+            curr_state_data.curr_states.insert(0);
+            if self.step_once(curr_state_data, &str_data, false) {
                 return true;
             }
-            str_data.curr_pos += 1;
-            str_data.curr_char = string.chars().nth(str_data.curr_pos);
+            if !str_data.advance() {
+                return false;
+            }
         }
+    }
 
-        false
+    /// Returns the size of the full DFA, or `None` if there's none: either `RareBuilder::full_dfa`
+    /// wasn't set, or the DFA needed more states than `RareBuilder::dfa_size_limit`.
+    pub fn dfa_size(&self) -> Option<DfaSize> {
+        self.dfa.as_ref().map(Dfa::size)
     }
 
     /// Returns a list of pairs, whose start and end represents the substring that matches the
     /// expression of this `RARE` instance.
     ///
+    /// The matches are leftmost, as set by `RareBuilder::match_kind`, and don't overlap: the same
+    /// ones `RARE::captures_iter` finds. Both ends of each pair are byte offsets on char
+    /// boundaries, so the string can be sliced with them.
+    ///
     /// * `string`:
     pub fn match_all(&self, string: &str) -> Option<LinkedList<(usize, usize)>> {
         let mut ret_vec = LinkedList::new();
-        let mut curr_str_ptr = 0;
-
-        let mut str_data = StringIterData {
-            strlen: string.len(),
-            curr_pos: 0,
-            curr_char: string.chars().nth(0),
-        };
-
-        let mut curr_state_data = CurrStatesData {
-            curr_states: {
-                let mut ret = BTreeSet::new();
-                ret.insert(0);
-                ret
-            },
-            next_states: BTreeSet::new(),
-            all_empty_match: true,
-        };
-
-        let mut iter = string.chars();
-        while curr_str_ptr < string.len() {
-            str_data.curr_pos = curr_str_ptr;
-            str_data.curr_char = iter.next();
-
-            // Why do we accept the case it's equal to strlen (aka, 1 over the last valid string
-            // index)?
-            //
-            // At the very end of the string, the matcher may need to run once more. The simplest
-            // example is a dollar anchor: even if the entire string has already matches, the
-            // matcher isn't yet at the final state; between it and the final state, there's still
-            // the anchor; hence it needs to run once more. And of course, this last run only
-            // yields a match if there's a path from the list of current states to the end state,
-            // such that all other states on it are empty or anchors.
-            //
-            // This has consequences though, if it's not yet the end of the string, we overflowed
-            // one character. That's dealt with when there's a match.
-
-            // if we don't find any match for the substring from curr_str_ptr, increment
-            // curr_str_ptr.
-            let mut incre = 1;
-            while str_data.curr_pos <= str_data.strlen {
-                if curr_state_data.curr_states.is_empty() {
-                    curr_str_ptr = str_data.curr_pos;
-                    curr_state_data.curr_states.insert(0);
-                }
-                // there's a match.
-                if self.step_once(&mut curr_state_data, &str_data) {
-                    // if the match just run only matches empty states, the character increment
-                    // below this if statement is invalid - after all, the matcher didn't
-                    // match any character. Without this, and all_empty_match is true, the next
-                    // character automatically matches if some other paths already reach the end.
-                    if curr_state_data.all_empty_match {
-                        curr_state_data.curr_states.remove(&(self.nfa.end));
-                    }
-                    ret_vec.push_back((curr_str_ptr, str_data.curr_pos));
-                    curr_str_ptr = str_data.curr_pos;
-                    incre = 0;
-                }
-                str_data.curr_pos += 1;
-                str_data.curr_char = string.chars().nth(str_data.curr_pos);
-            }
-            curr_str_ptr += incre;
-        }
+        self.for_each_match(&mut self.create_cache(), string, |start, end| {
+            ret_vec.push_back((start, end))
+        });
 
         if ret_vec.is_empty() {
             None
@@ -181,152 +335,368 @@ impl RARE {
         }
     }
 
+    /// Same as `RARE::match_all`, but appends the matches to the vector passed in instead, so it
+    /// can be reused between calls.
+    ///
+    /// * `string`:
+    /// * `ret_vec`:
     pub fn write_match_all(&self, string: &str, ret_vec: &mut Vec<(usize, usize)>) {
-        // self.nfa.print_states();
-        let mut curr_str_ptr = 0;
+        self.write_match_all_with(&mut self.create_cache(), string, ret_vec);
+    }
 
-        let mut str_data = StringIterData {
-            strlen: string.len(),
-            curr_pos: 0,
-            curr_char: string.chars().nth(0),
-        };
+    /// Same as `RARE::write_match_all`, but uses the cache passed in instead of allocating a new
+    /// one.
+    ///
+    /// * `cache`: obtained by calling `RARE::create_cache`.
+    /// * `string`:
+    /// * `ret_vec`:
+    pub fn write_match_all_with(
+        &self,
+        cache: &mut Cache,
+        string: &str,
+        ret_vec: &mut Vec<(usize, usize)>,
+    ) {
+        self.for_each_match(cache, string, |start, end| ret_vec.push((start, end)));
+    }
 
-        let mut curr_state_data = CurrStatesData {
-            curr_states: {
-                let mut ret = BTreeSet::new();
-                ret.insert(0);
-                ret
-            },
-            next_states: BTreeSet::new(),
-            all_empty_match: true,
-        };
+    /// Creates the memory needed while matching, to be reused across calls to the `RARE::*_with`
+    /// methods.
+    pub fn create_cache(&self) -> Cache {
+        Cache::new(self)
+    }
 
-        let mut iter = string.chars();
-        while curr_str_ptr < string.len() {
-            str_data.curr_pos = curr_str_ptr;
-            str_data.curr_char = iter.next();
+    /// Calls the function passed in with the start and end of each non-overlapping leftmost match,
+    /// in order.
+    ///
+    /// An empty match right where the previous match ends is skipped, so "a*" over "baa" gives
+    /// (0, 0), (1, 3), and not an extra (3, 3).
+    ///
+    /// The match from each start is searched for forwards, which reads as far as the match could go
+    /// on: the rest of the string, for "a|a[^x]*b" over "aaa...a", from every a. With
+    /// `MatchKind::LeftmostLongest`, once the forward searches have read `FORWARD_BUDGET` times the
+    /// string, the rest of the matches are found by `RARE::for_each_match_backwards` instead, which
+    /// reads the rest of the string once.
+    ///
+    /// * `cache`:
+    /// * `string`:
+    /// * `on_match`:
+    fn for_each_match(
+        &self,
+        cache: &mut Cache,
+        string: &str,
+        mut on_match: impl FnMut(usize, usize),
+    ) {
+        if let Some(set) = &self.literal_set {
+            let mut from = 0;
+            while let Some((start, end)) = set.find_at(string, from) {
+                on_match(start, end);
+                from = end;
+            }
+            return;
+        }
+        cache.reset_for(self);
+        let mut str_data = StringIterData::new(string);
+        let mut last_end = None;
+        // the required literal only needs looking for once.
+        let mut check_required = true;
+        let longest = self.options.match_kind == MatchKind::LeftmostLongest;
+        let mut budget = match longest {
+            true => FORWARD_BUDGET.saturating_mul(string.len().max(1)),
+            false => usize::MAX,
+        };
 
-            let mut incre = 1;
-            while str_data.curr_pos <= str_data.strlen {
-                if curr_state_data.curr_states.is_empty() {
-                    curr_str_ptr = str_data.curr_pos;
-                    curr_state_data.curr_states.insert(0);
+        loop {
+            // skip to where the next match may start.
+            match self
+                .prefilter
+                .next_start(string, str_data.curr_pos, check_required)
+            {
+                None => break,
+                Some(pos) if pos > str_data.curr_pos => str_data.restart(pos),
+                Some(_) => (),
+            }
+            check_required = false;
+            let start = str_data.curr_pos;
+            // the bit-parallel simulation doesn't keep the states in priority order.
+            let (found, stop) = match &self.bit_parallel {
+                Some(bit_parallel) if longest => {
+                    bit_parallel.longest_match_at(&self.classes, string, start)
                 }
-                // there's a match.
-                if self.step_once(&mut curr_state_data, &str_data) {
-                    if curr_state_data.all_empty_match {
-                        curr_state_data.curr_states.remove(&(self.nfa.end));
-                    }
-                    ret_vec.push((curr_str_ptr, str_data.curr_pos));
-                    curr_str_ptr = str_data.curr_pos;
-                    incre = 0;
+                _ => {
+                    let found = self.match_at(&mut cache.state_data, &mut str_data);
+                    (found, str_data.curr_pos)
+                }
+            };
+            match budget.checked_sub(stop - start) {
+                Some(left) => budget = left,
+                None => {
+                    self.for_each_match_backwards(cache, string, start, last_end, on_match);
+                    return;
+                }
+            }
+            match found {
+                Some(end) if end > start => {
+                    on_match(start, end);
+                    last_end = Some(end);
+                    str_data.restart(end);
+                    continue;
+                }
+                Some(end) if last_end != Some(end) => {
+                    on_match(start, end);
+                    last_end = Some(end);
                 }
-                str_data.curr_pos += 1;
-                str_data.curr_char = string.chars().nth(str_data.curr_pos);
+                _ => (),
             }
-            curr_str_ptr += incre;
+            // nothing (non-empty) starts here, try from the next character.
+            str_data.restart(start);
+            if !str_data.advance() {
+                break;
+            }
+        }
+    }
+
+    /// Same as `RARE::for_each_match`, from the byte offset passed in, but in two passes: the
+    /// reverse NFA finds the longest match starting at each position, going backwards from the
+    /// end of the string. Then, going forwards, the leftmost one is kept, then the leftmost one
+    /// from where it ends, and so on.
+    ///
+    /// * `cache`:
+    /// * `string`:
+    /// * `from`: a byte offset on a char boundary.
+    /// * `last_end`: where the last match before `from` ended, if there's one.
+    /// * `on_match`:
+    fn for_each_match_backwards(
+        &self,
+        cache: &mut Cache,
+        string: &str,
+        from: usize,
+        mut last_end: Option<usize>,
+        mut on_match: impl FnMut(usize, usize),
+    ) {
+        let matches = &mut cache.matches;
+        matches.clear();
+        self.reverse.longest_matches(
+            &mut cache.reverse_states,
+            &self.lookarounds,
+            string,
+            from,
+            matches,
+        );
+        let mut next_start = from;
+        for &(start, end) in matches.iter().rev() {
+            if start < next_start || (start == end && last_end == Some(end)) {
+                continue;
+            }
+            on_match(start, end);
+            next_start = end;
+            last_end = Some(end);
         }
+    }
+
+    /// Finds the match that starts exactly at the cursor: the longest one, or the first one in
+    /// priority order, depending on the `MatchKind`. The cursor is left somewhere after that.
+    ///
+    /// With `MatchKind::LeftmostFirst`, the states entered after a match, which all have a higher
+    /// priority than the path that matched, go on. A match they find later replaces it.
+    ///
+    /// * `state_data`: reused between calls, to avoid reallocation.
+    /// * `str_data`: the cursor, at the start of the match.
+    /// * Return: the byte offset where the match ends, if there is one.
+    fn match_at(
+        &self,
+        state_data: &mut CurrStatesData,
+        str_data: &mut StringIterData,
+    ) -> Option<usize> {
+        let first = self.options.match_kind == MatchKind::LeftmostFirst;
+        state_data.curr_states.clear();
+        state_data.curr_states.insert(0);
+        state_data.look_cache.clear();
+        let mut end = None;
+        loop {
+            if self.step_once(state_data, str_data, first) {
+                end = Some(str_data.curr_pos);
+            }
+            if state_data.curr_states.is_empty() || !str_data.advance() {
+                return end;
+            }
+        }
+    }
 
+    /// Returns the number of capture groups in the expression, not counting group 0 (the whole
+    /// match).
+    pub fn captures_len(&self) -> usize {
+        self.capture_names.len() - 1
+    }
+
+    /// Returns the name of each capture group, in order, `None` for the unnamed ones. The first
+    /// element is always `None`, since it's group 0, the whole match.
+    ///
+    /// ```
+    /// use rare::RARE;
+    ///
+    /// let rare = RARE::new("(?P<year>[0-9]{4})-([0-9]{2})").unwrap();
+    /// let names: Vec<Option<&str>> = rare.capture_names().collect();
+    /// assert_eq!(names, [None, Some("year"), None]);
+    /// ```
+    pub fn capture_names(&self) -> impl Iterator<Item = Option<&str>> {
+        self.capture_names.iter().map(|name| name.as_deref())
+    }
+
+    /// Returns the leftmost match in the string passed in, as set by `RareBuilder::match_kind`,
+    /// along with the substrings each capture group matched.
+    ///
+    /// ```
+    /// use rare::RARE;
+    ///
+    /// let rare = RARE::new("([0-9]+)-([0-9]+)").unwrap();
+    /// let caps = rare.captures("from 2024-10 on").unwrap();
+    /// assert_eq!(&caps[0], "2024-10");
+    /// assert_eq!(&caps[1], "2024");
+    /// assert_eq!(caps.get(2), Some((10, 12)));
+    /// ```
+    ///
+    /// * `string`:
+    pub fn captures<'h>(&self, string: &'h str) -> Option<Captures<'h>> {
+        self.captures_at(&mut self.create_cache(), string, 0, None)
+    }
+
+    /// Returns an iterator over the non-overlapping matches in the string passed in, along with
+    /// their capture groups.
+    ///
+    /// * `string`:
+    pub fn captures_iter<'r, 'h>(&'r self, string: &'h str) -> CapturesIter<'r, 'h> {
+        CapturesIter::new(self, string)
+    }
+
+    /// Returns the leftmost match that starts at or after the byte offset passed in, with its
+    /// capture groups.
+    ///
+    /// * `cache`:
+    /// * `string`:
+    /// * `start`: a byte offset on a char boundary.
+    /// * `span`: where the match ends, if it's already known to start at `start` and end there, see
+    ///   `PikeVm::search`.
+    fn captures_at<'h>(
+        &self,
+        cache: &mut Cache,
+        string: &'h str,
+        start: usize,
+        span: Option<usize>,
+    ) -> Option<Captures<'h>> {
+        cache.reset_for(self);
+        PikeVm::new(
+            &self.nfa,
+            &self.lookarounds,
+            &self.repeated_groups,
+            self.options.match_kind,
+        )
+        .search(&mut cache.pike, string, start, span)
+        .map(|slots| Captures::new(string, slots, Arc::clone(&self.name_table)))
     }
 
     #[inline]
-    /// Given the list of states and the string input, move the list of current states by one step.
-    /// Returns whether the next step indicates a match.
+    /// Given the list of states and the string input, move the list of current states by one step,
+    /// consuming the current character.
+    /// Returns whether the end state is reachable at the current position, that is, whether a
+    /// match ends right before the current character.
     ///
     /// * `state_data`: the collection of current states.
     /// * `str_data`: the input data.
-    fn step_once(&self, state_data: &mut CurrStatesData, str_data: &StringIterData) -> bool {
-        debug_assert!(!state_data.curr_states.is_empty());
-        // print!("Current states: [");
-        // for &ref_state in ref_data.curr_states.iter() {
-        //     print!("{ref_state}, ");
-        // }
-        // println!("]");
-
-        // Order matters here. The resultant NFA always have the last state being the end state, so
-        // to match the longest string possible, I should go from the state with smaller reference.
-        //
-        // But, to be fair, this is relevant only for the pretty-printer, so if I don't want the
-        // pretty printer, I can use a simple HashSet instead.
-        while let Some(curr_ref) = state_data.curr_states.pop_first() {
-            if curr_ref == self.nfa.end {
-                // ref_data.next_states.remove(&self.nfa.end);
-                std::mem::swap(&mut state_data.curr_states, &mut state_data.next_states);
-                return true;
+    /// * `stop_at_match`: whether to drop the paths after the first one reaching the end, in
+    ///   priority order, as `MatchKind::LeftmostFirst` does.
+    fn step_once(
+        &self,
+        state_data: &mut CurrStatesData,
+        str_data: &StringIterData,
+        stop_at_match: bool,
+    ) -> bool {
+        // the anchors are checked against the same position for every state, so only once.
+        let held = Assertions::at(str_data.prev_char, str_data.curr_char)
+            .with_next(str_data.curr_char, str_data.rest.clone().next());
+        let mut matched = false;
+        state_data.next_states.clear();
+        state_data.visited.clear();
+        for idx in 0..state_data.curr_states.len() {
+            let curr_ref = state_data.curr_states.as_slice()[idx];
+            matched |= self.get_next_of(curr_ref, state_data, str_data, held, stop_at_match);
+            if matched && stop_at_match {
+                break;
             }
-            state_data.next_states.extend({
-                let (ret, empty_match) = self.get_next_of(curr_ref, str_data);
-                state_data.all_empty_match = empty_match;
-                ret
-            });
         }
 
         std::mem::swap(&mut state_data.curr_states, &mut state_data.next_states);
-        state_data.curr_states.contains(&self.nfa.end)
+        matched
     }
 
     #[inline]
     /// Gets the next state(s) given the current state and the input.
     ///
+    /// The empty transitions are skipped using the precomputed epsilon closure of the current
+    /// state, so this only checks the anchors on each path against the current position, and the
+    /// current character against the states at the end of each path. With lookarounds, the empty
+    /// transitions are walked instead, see `closure::walk`.
+    ///
     /// * `state_ref`: the current state.
+    /// * `state_data`: the states entered by consuming the current character are put in
+    ///   `next_states`.
     /// * `str_data`: the input data.
-    fn get_next_of(&self, state_ref: usize, str_data: &StringIterData) -> (HashSet<usize>, bool) {
-        let mut ret = HashSet::new();
-        let mut all_empty_match = true;
-        // We want to skip empty transitions.
-        // Hat (^) is the same as empty if the current position is the start of the string,
-        // and dollar ($) is the same if the current position is the end of the string.
-        //
-        // The skip set basically works like a stack or queue, but we don't want to traverse
-        // elements already inside. So, BTreeSet is chosen.
-        //
-        // Note to self, HashSet doesn't have a concrete order. So, doing both iteration and
-        // insertion at the same time is not a good idea (and Rust knows this).
-        let mut skip_set: BTreeSet<usize> = BTreeSet::new();
-        skip_set.insert(state_ref);
-
-        while let Some(skip_ref) = skip_set.pop_first() {
-            if skip_ref == self.nfa.end {
-                ret.insert(skip_ref);
-                break;
-            }
-            let skip_state = self.nfa.get_state(skip_ref).unwrap();
-
-            for next_ref in skip_state.edges.iter() {
-                let transition = &self.nfa.states[*next_ref].token.token_type;
-                // reminder; edge = (required match to transition, next state)
-                match transition {
-                    TokenType::Character(c) => {
-                        if str_data.curr_char.is_some() && *c == str_data.curr_char.unwrap() {
-                            ret.insert(*next_ref);
-                            all_empty_match = false;
-                        }
-                    }
-                    TokenType::Dot => {
-                        ret.insert(*next_ref);
-                        all_empty_match = false;
+    /// * `held`: the anchors that hold at the current position.
+    /// * `stop_at_match`: whether to drop the paths after the one reaching the end.
+    /// * Return: whether the end state can be reached from the current state without consuming
+    ///   anything.
+    fn get_next_of(
+        &self,
+        state_ref: usize,
+        state_data: &mut CurrStatesData,
+        str_data: &StringIterData,
+        held: Assertions,
+        stop_at_match: bool,
+    ) -> bool {
+        let CurrStatesData {
+            next_states,
+            visited,
+            walk_stack,
+            look_cache,
+            ..
+        } = state_data;
+        let mut reaches_end = false;
+        let mut visit = |target| {
+            match target {
+                Target::Match => {
+                    reaches_end = true;
+                    if stop_at_match {
+                        return ControlFlow::Break(());
                     }
-                    TokenType::Empty => {
-                        skip_set.insert(*next_ref);
-                    }
-                    // hat and dollar anchors: if they are at the matching positions in the string,
-                    // act as if they were empty states. Otherwise, they are not valid next states.
-                    TokenType::Hat => {
-                        if str_data.curr_pos == 0 {
-                            skip_set.insert(*next_ref);
-                        }
-                    }
-                    TokenType::Dollar => {
-                        if str_data.curr_pos >= str_data.strlen - 1 {
-                            skip_set.insert(*next_ref);
-                        }
+                }
+                Target::Consume(next_ref) => {
+                    let transition = &self.nfa.states[next_ref].token.token_type;
+                    if str_data.curr_char.is_some_and(|c| transition.accepts(c)) {
+                        next_states.insert(next_ref);
                     }
-                    _ => todo!(),
                 }
             }
+            ControlFlow::Continue(())
+        };
+        if let Some(closures) = self
+            .closures
+            .as_ref()
+            .filter(|_| self.lookarounds.is_empty())
+        {
+            for entry in closures.of(state_ref) {
+                if held.contains(entry.needs) && visit(entry.target).is_break() {
+                    break;
+                }
+            }
+        } else {
+            let holds = |idx| {
+                self.lookarounds
+                    .holds(idx, str_data.string, str_data.curr_pos, look_cache)
+            };
+            let _ = closure::walk(
+                &self.nfa, state_ref, held, visited, walk_stack, holds, visit,
+            );
         }
-
-        (ret, all_empty_match)
+        reaches_end
     }
 }
 
@@ -0,0 +1,160 @@
+use std::collections::VecDeque;
+
+/// The root of the trie, where every search starts.
+const ROOT: u32 = 0;
+
+/// An Aho–Corasick automaton, matching a set of literal strings at once in a single pass over the
+/// string. Used instead of the NFA when the whole pattern is an alternation of literals, such as a
+/// keyword list, "foo|bar|baz".
+///
+/// The strings are put into a trie, over their UTF-8 bytes. Each node also gets a failure link, to
+/// the node of the longest proper suffix of its own string that is also in the trie, so that the
+/// search never goes back in the string.
+///
+/// Matches are leftmost-longest, the same as `RARE::match_all`. A match found doesn't end the
+/// search right away: one starting earlier, or starting at the same place but longer, may still
+/// come. It stops once the current node is too shallow for any later match to start that early.
+///
+/// * `root`: the transitions of the root, on every byte. Most bytes of a string go there.
+/// * `transitions`: the transitions of every node, sorted by byte. Missing ones follow `fails`.
+/// * `fails`: the failure link of every node.
+/// * `depths`: the length, in bytes, of the string of every node.
+/// * `match_lens`: the length of the longest string of the set that ends at every node, 0 if none.
+#[derive(Clone, Debug)]
+pub(crate) struct AhoCorasick {
+    root: [u32; 256],
+    transitions: Vec<Vec<(u8, u32)>>,
+    fails: Vec<u32>,
+    depths: Vec<u32>,
+    match_lens: Vec<u32>,
+}
+
+impl AhoCorasick {
+    /// Builds the automaton of the strings passed in. None of them may be empty.
+    ///
+    /// * `strings`:
+    pub fn new(strings: &[String]) -> Self {
+        let mut ret = Self {
+            root: [ROOT; 256],
+            transitions: vec![Vec::new()],
+            fails: vec![ROOT],
+            depths: vec![0],
+            match_lens: vec![0],
+        };
+
+        // the trie.
+        for string in strings {
+            debug_assert!(!string.is_empty());
+            let mut node = ROOT;
+            for &byte in string.as_bytes() {
+                let found = ret.transitions[node as usize]
+                    .iter()
+                    .find(|&&(b, _)| b == byte)
+                    .map(|&(_, next)| next);
+                node = match found {
+                    Some(next) => next,
+                    None => {
+                        let next = ret.transitions.len() as u32;
+                        ret.transitions[node as usize].push((byte, next));
+                        ret.transitions.push(Vec::new());
+                        ret.fails.push(ROOT);
+                        ret.depths.push(ret.depths[node as usize] + 1);
+                        ret.match_lens.push(0);
+                        next
+                    }
+                };
+            }
+            ret.match_lens[node as usize] = string.len() as u32;
+        }
+        for transitions in ret.transitions.iter_mut() {
+            transitions.sort_unstable();
+        }
+        for &(byte, next) in ret.transitions[ROOT as usize].iter() {
+            ret.root[byte as usize] = next;
+        }
+
+        // the failure links, shallowest nodes first, since a link always goes to a shallower node.
+        let mut queue: VecDeque<u32> = ret.transitions[ROOT as usize]
+            .iter()
+            .map(|&(_, next)| next)
+            .collect();
+        while let Some(node) = queue.pop_front() {
+            for idx in 0..ret.transitions[node as usize].len() {
+                let (byte, next) = ret.transitions[node as usize][idx];
+                let fail = ret.next(ret.fails[node as usize], byte);
+                ret.fails[next as usize] = fail;
+                // a string of the set ending at the failure node also ends here, but it's shorter
+                // than one ending exactly here.
+                if ret.match_lens[next as usize] == 0 {
+                    ret.match_lens[next as usize] = ret.match_lens[fail as usize];
+                }
+                queue.push_back(next);
+            }
+        }
+        ret
+    }
+
+    #[inline]
+    /// Returns the node reached from the node passed in on the byte passed in.
+    ///
+    /// * `node`:
+    /// * `byte`:
+    fn next(&self, mut node: u32, byte: u8) -> u32 {
+        loop {
+            if node == ROOT {
+                return self.root[byte as usize];
+            }
+            let transitions = &self.transitions[node as usize];
+            if let Ok(idx) = transitions.binary_search_by_key(&byte, |&(b, _)| b) {
+                return transitions[idx].1;
+            }
+            node = self.fails[node as usize];
+        }
+    }
+
+    /// Returns whether any string of the set occurs in the string passed in.
+    ///
+    /// * `haystack`:
+    pub fn is_match(&self, haystack: &str) -> bool {
+        let mut node = ROOT;
+        for &byte in haystack.as_bytes() {
+            node = self.next(node, byte);
+            if self.match_lens[node as usize] > 0 {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Returns the leftmost-longest match at or after the byte offset passed in.
+    ///
+    /// Since the strings of the set are valid UTF-8, so are the matches: both ends are on char
+    /// boundaries.
+    ///
+    /// * `haystack`:
+    /// * `from`: a byte offset on a char boundary.
+    /// * Return: the start and end of the match, as byte offsets.
+    pub fn find_at(&self, haystack: &str, from: usize) -> Option<(usize, usize)> {
+        let mut node = ROOT;
+        let mut best: Option<(usize, usize)> = None;
+        for (idx, &byte) in haystack.as_bytes()[from..].iter().enumerate() {
+            let end = from + idx + 1;
+            node = self.next(node, byte);
+            let len = self.match_lens[node as usize] as usize;
+            // the longest string ending here starts the earliest. Starting at the same place as
+            // the best match so far, it's also longer.
+            if len > 0 && best.is_none_or(|(start, _)| end - len <= start) {
+                best = Some((end - len, end));
+            }
+            // every match ending later starts at or after `end - depth`.
+            if let Some((start, _)) = best {
+                if start < end - self.depths[node as usize] as usize {
+                    return best;
+                }
+            }
+        }
+        best
+    }
+}
+
+mod test;
@@ -0,0 +1,28 @@
+#[cfg(test)]
+use super::*;
+
+#[test]
+fn aho_corasick_test() {
+    let strings: Vec<String> = ["he", "she", "his", "hers", "é"]
+        .iter()
+        .map(|string| string.to_string())
+        .collect();
+    let set = AhoCorasick::new(&strings);
+    assert!(set.is_match("ushers"));
+    assert!(set.is_match("café"));
+    assert!(!set.is_match("hi s"));
+
+    // "she" starts before "he" and "hers", even though "he" ends first.
+    assert_eq!(set.find_at("ushers", 0), Some((1, 4)));
+    // "hers" is longer than "he", from the same start.
+    assert_eq!(set.find_at("ushers", 4), None);
+    assert_eq!(set.find_at("hers", 0), Some((0, 4)));
+    assert_eq!(set.find_at("ahis", 1), Some((1, 4)));
+    assert_eq!(set.find_at("cafés", 0), Some((3, 5)));
+
+    // a shorter string inside a longer one that doesn't finish.
+    let strings: Vec<String> = ["abcd", "bc"].iter().map(|s| s.to_string()).collect();
+    let set = AhoCorasick::new(&strings);
+    assert_eq!(set.find_at("abce", 0), Some((1, 3)));
+    assert_eq!(set.find_at("abcd", 0), Some((0, 4)));
+}
@@ -0,0 +1,128 @@
+use crate::parser::byte_classes::ByteClasses;
+use crate::parser::glushkov::{positions, Glushkov};
+
+/// Simulates a position automaton with bitwise operations: the set of current positions is a
+/// single `u64`, and a step over a character is a few table lookups, an or and an and, where the
+/// Thompson NFA simulation walks the epsilon closure of every current state.
+///
+/// The positions reached by consuming a character are those that follow a current position, and
+/// whose token accepts that character. The first part is an or of tables indexed by each byte of
+/// the current set, computed once when compiling, and the second an and with the positions of the
+/// character's class.
+///
+/// * `masks`: the positions accepting the characters of each class of the `ByteClasses`.
+/// * `follows`: for each byte of a set of positions, the positions that may follow those of that
+///   byte, indexed by the byte's value.
+/// * `first`: the positions a match may start with.
+/// * `last`: the positions a match may end with.
+/// * `nullable`: whether the empty string matches.
+#[derive(Clone, Debug)]
+pub(crate) struct BitParallel {
+    masks: Vec<u64>,
+    follows: Vec<[u64; 256]>,
+    first: u64,
+    last: u64,
+    nullable: bool,
+}
+
+impl BitParallel {
+    /// Constructs the tables of the position automaton passed in.
+    ///
+    /// * `glushkov`:
+    /// * `classes`: the alphabet of the NFA compiled from the same pattern.
+    pub fn new(glushkov: &Glushkov, classes: &ByteClasses) -> Self {
+        let masks = (0..classes.len())
+            .map(|class| {
+                let c = classes.representative(class);
+                glushkov
+                    .tokens
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, token)| token.accepts(c))
+                    .fold(0, |mask, (position, _)| mask | 1 << position)
+            })
+            .collect();
+        let follows = glushkov
+            .follow
+            .chunks(8)
+            .map(|chunk| {
+                let mut table = [0; 256];
+                for (byte, follow) in table.iter_mut().enumerate() {
+                    *follow = positions(byte as u64)
+                        .filter_map(|bit| chunk.get(bit))
+                        .fold(0, |follow, next| follow | next);
+                }
+                table
+            })
+            .collect();
+        Self {
+            masks,
+            follows,
+            first: glushkov.first,
+            last: glushkov.last,
+            nullable: glushkov.nullable,
+        }
+    }
+
+    #[inline]
+    /// Returns the positions that may follow any of the positions passed in.
+    ///
+    /// * `set`:
+    fn follow(&self, set: u64) -> u64 {
+        let mut ret = 0;
+        for (idx, table) in self.follows.iter().enumerate() {
+            ret |= table[(set >> (8 * idx)) as u8 as usize];
+        }
+        ret
+    }
+
+    /// Returns whether there is a match at or after the byte offset passed in.
+    ///
+    /// * `classes`:
+    /// * `string`:
+    /// * `start`: a byte offset on a char boundary.
+    pub fn is_match(&self, classes: &ByteClasses, string: &str, start: usize) -> bool {
+        if self.nullable {
+            return true;
+        }
+        let mut set = 0;
+        for c in string[start..].chars() {
+            // a match may start at any position.
+            set = (self.follow(set) | self.first) & self.masks[classes.get(c)];
+            if set & self.last != 0 {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Finds the longest match that starts exactly at the byte offset passed in.
+    ///
+    /// * `classes`:
+    /// * `string`:
+    /// * `start`: a byte offset on a char boundary.
+    /// * Return: the byte offset where the match ends, if there is one, and the one where the
+    ///   search stopped.
+    pub fn longest_match_at(
+        &self,
+        classes: &ByteClasses,
+        string: &str,
+        start: usize,
+    ) -> (Option<usize>, usize) {
+        let mut end = self.nullable.then_some(start);
+        let mut reachable = self.first;
+        for (idx, c) in string[start..].char_indices() {
+            let set = reachable & self.masks[classes.get(c)];
+            if set == 0 {
+                return (end, start + idx);
+            }
+            if set & self.last != 0 {
+                end = Some(start + idx + c.len_utf8());
+            }
+            reachable = self.follow(set);
+        }
+        (end, string.len())
+    }
+}
+
+mod test;
@@ -0,0 +1,120 @@
+#[cfg(test)]
+use crate::rare::{RareBuilder, RARE};
+
+/// A xorshift generator, so that the random patterns are the same on every run.
+#[cfg(test)]
+struct XorShift(u64);
+
+#[cfg(test)]
+impl XorShift {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+
+    /// Returns a random pattern over a, b and é, with every operator the position automaton
+    /// handles.
+    ///
+    /// * `depth`: how many more operators may be nested.
+    fn pattern(&mut self, depth: usize) -> String {
+        const ATOMS: [&str; 7] = ["a", "b", "é", ".", "[ab]", "[^a]", "(?i)A"];
+        if depth == 0 {
+            return ATOMS[self.below(ATOMS.len())].to_string();
+        }
+        match self.below(9) {
+            0 | 1 => self.pattern(depth - 1) + &self.pattern(depth - 1),
+            2 => format!("{}|{}", self.pattern(depth - 1), self.pattern(depth - 1)),
+            3 => format!("({})*", self.pattern(depth - 1)),
+            4 => format!("({})+", self.pattern(depth - 1)),
+            5 => format!("({})?", self.pattern(depth - 1)),
+            6 => format!("({}){{1,3}}", self.pattern(depth - 1)),
+            7 => format!("(?:{}){{2,}}", self.pattern(depth - 1)),
+            _ => ATOMS[self.below(ATOMS.len())].to_string(),
+        }
+    }
+
+    fn string(&mut self) -> String {
+        const CHARS: [char; 5] = ['a', 'b', 'é', '\n', 'A'];
+        let len = self.below(12);
+        (0..len).map(|_| CHARS[self.below(CHARS.len())]).collect()
+    }
+}
+
+#[test]
+fn bit_parallel_agrees_with_nfa_test() {
+    let mut rng = XorShift(0x9E37_79B9_7F4A_7C15);
+    let mut checked = 0;
+    for _ in 0..1000 {
+        let pattern = rng.pattern(4);
+        // the bare NFA simulation, and the same with the bit-parallel one in its place.
+        let bit_parallel = RareBuilder::new(&pattern)
+            .lazy_dfa_cache_size(0)
+            .prefilter(false)
+            .build()
+            .unwrap();
+        let mut nfa_only = RareBuilder::new(&pattern)
+            .lazy_dfa_cache_size(0)
+            .prefilter(false)
+            .build()
+            .unwrap();
+        nfa_only.bit_parallel = None;
+        let default = RARE::new(&pattern).unwrap();
+        if bit_parallel.bit_parallel.is_none() {
+            continue;
+        }
+        checked += 1;
+        for _ in 0..20 {
+            let string = rng.string();
+            let expected = nfa_only.is_match(&string);
+            assert_eq!(
+                bit_parallel.is_match(&string),
+                expected,
+                "{pattern:?} over {string:?}"
+            );
+            assert_eq!(
+                default.is_match(&string),
+                expected,
+                "{pattern:?} over {string:?}"
+            );
+            let expected = nfa_only.match_all(&string);
+            assert_eq!(
+                bit_parallel.match_all(&string),
+                expected,
+                "{pattern:?} over {string:?}"
+            );
+            assert_eq!(
+                default.match_all(&string),
+                expected,
+                "{pattern:?} over {string:?}"
+            );
+        }
+    }
+    // most of them fit in 64 positions.
+    assert!(checked > 750, "{checked}");
+}
+
+#[test]
+fn bit_parallel_selection_test() {
+    for pattern in [
+        "",
+        "a",
+        "[a-z]+@[a-z]+\\.com",
+        "(ab|cd){2,3}",
+        "x{64}",
+        "(a|b)*abb",
+    ] {
+        assert!(
+            RARE::new(pattern).unwrap().bit_parallel.is_some(),
+            "{pattern}"
+        );
+    }
+    // too many positions, or anchors.
+    for pattern in ["x{65}", "(abcdefgh){9}", "^a", "a$", "(?m)a$"] {
+        assert!(
+            RARE::new(pattern).unwrap().bit_parallel.is_none(),
+            "{pattern}"
+        );
+    }
+}
@@ -0,0 +1,255 @@
+use crate::parser::Parser;
+
+use super::RARE;
+
+/// The default cap on the number of NFA states a pattern may compile into.
+const DEFAULT_SIZE_LIMIT: usize = 100_000;
+/// The default number of lazy DFA states cached per `Cache`. Each takes 4 bytes per character
+/// class, plus its NFA states.
+const DEFAULT_DFA_CACHE_SIZE: usize = 2048;
+/// The default cap on the number of states the full DFA may have before minimization.
+const DEFAULT_DFA_SIZE_LIMIT: usize = 10_000;
+
+/// Which match wins when several start at the leftmost position, such as "a" and "ab" for "a|ab"
+/// over "ab". The leftmost match always wins, whatever the kind, and `RARE::is_match` doesn't
+/// depend on it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum MatchKind {
+    /// The longest one, as POSIX specifies: "a|ab" matches "ab". The default.
+    ///
+    /// When several ways to match give the same longest match, the capture groups follow POSIX
+    /// too: each group in turn starts as early and then ends as late as it can, so
+    /// "(a|ab)(c|bcd)(d*)" over "abcd" gives "ab", "c" and "d". Lazy quantifiers make no
+    /// difference there. The exception is a group inside a repetition, whose iterations POSIX
+    /// wants each to be the longest in turn: it takes the ones `LeftmostFirst` would prefer, as it
+    /// does where a group is left out on one of the ways.
+    #[default]
+    LeftmostLongest,
+    /// The first one the pattern lists, as Perl, PCRE and most backtracking engines do: "a|ab"
+    /// matches "a". The alternatives are tried in order, and the quantifiers take as many
+    /// repetitions as they can first.
+    LeftmostFirst,
+}
+
+/// The compile options of a `RARE` matcher. Carried by `RareBuilder` and read by the `Parser`.
+///
+/// * `size_limit`: the maximum number of states the compiled NFA may have. Bounded repetitions copy
+///   the NFA before them, so a small pattern can compile into a huge NFA.
+/// * `case_insensitive`: whether the whole pattern starts with the i flag set.
+/// * `multi_line`: whether the whole pattern starts with the m flag set.
+/// * `crlf`: whether the whole pattern starts with the R flag set.
+/// * `dfa_cache_size`: the maximum number of lazy DFA states cached at once. 0 turns the lazy DFA
+///   off.
+/// * `full_dfa`: whether to compile the whole DFA up front for `RARE::is_match`.
+/// * `prefilter`: whether to skip to the parts of the string that hold the literals every match
+///   starts with or contains, before running the automata, and to match alternations of literals
+///   with Aho–Corasick.
+/// * `dfa_size_limit`: the maximum number of states the full DFA may have before minimization. If
+///   it needs more, it isn't built, and matching uses the lazy DFA instead.
+/// * `match_kind`: which of the matches starting at the leftmost position wins.
+#[derive(Clone, Debug)]
+pub(crate) struct Options {
+    pub size_limit: usize,
+    pub case_insensitive: bool,
+    pub multi_line: bool,
+    pub crlf: bool,
+    pub dfa_cache_size: usize,
+    pub full_dfa: bool,
+    pub dfa_size_limit: usize,
+    pub prefilter: bool,
+    pub match_kind: MatchKind,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            size_limit: DEFAULT_SIZE_LIMIT,
+            case_insensitive: false,
+            multi_line: false,
+            crlf: false,
+            dfa_cache_size: DEFAULT_DFA_CACHE_SIZE,
+            full_dfa: false,
+            dfa_size_limit: DEFAULT_DFA_SIZE_LIMIT,
+            prefilter: true,
+            match_kind: MatchKind::default(),
+        }
+    }
+}
+
+/// Builds a `RARE` matcher with non-default compile options.
+///
+/// ```
+/// use rare::RareBuilder;
+///
+/// let rare = RareBuilder::new("[0-9]{4}-[0-9]{2}").size_limit(1000).build().unwrap();
+/// assert!(rare.is_match("2024-10"));
+/// assert!(RareBuilder::new("(a{1000}){1000}").build().is_err());
+/// ```
+///
+/// * `pattern`: the regular expression string.
+/// * `options`: the compile options set so far.
+pub struct RareBuilder<'a> {
+    pattern: &'a str,
+    options: Options,
+}
+
+impl<'a> RareBuilder<'a> {
+    /// Constructs a builder for the regular expression passed in, with all options at their
+    /// defaults.
+    ///
+    /// * `pattern`:
+    pub fn new(pattern: &'a str) -> Self {
+        Self {
+            pattern,
+            options: Options::default(),
+        }
+    }
+
+    /// Sets the maximum number of NFA states the pattern may compile into. If the pattern exceeds
+    /// it, `RareBuilder::build` fails instead of allocating the whole NFA.
+    ///
+    /// * `limit`:
+    pub fn size_limit(mut self, limit: usize) -> Self {
+        self.options.size_limit = limit;
+        self
+    }
+
+    /// Sets whether letters match regardless of their case, as if the pattern started with "(?i)".
+    /// Cases are folded when compiling, following the simple case folding of Unicode, so matching
+    /// costs the same either way. The pattern may still turn it off for a part of it with "(?-i)".
+    ///
+    /// ```
+    /// use rare::RareBuilder;
+    ///
+    /// let rare = RareBuilder::new("straße").case_insensitive(true).build().unwrap();
+    /// assert!(rare.is_match("STRAẞE"));
+    /// ```
+    ///
+    /// * `yes`:
+    pub fn case_insensitive(mut self, yes: bool) -> Self {
+        self.options.case_insensitive = yes;
+        self
+    }
+
+    /// Sets whether ^ and $ match at the start and end of every line, as if the pattern started
+    /// with "(?m)". \A and \z still only match at the start and end of the whole string.
+    ///
+    /// ```
+    /// use rare::RareBuilder;
+    ///
+    /// let rare = RareBuilder::new("^b$").multi_line(true).build().unwrap();
+    /// assert!(rare.is_match("a\nb\nc"));
+    /// ```
+    ///
+    /// * `yes`:
+    pub fn multi_line(mut self, yes: bool) -> Self {
+        self.options.multi_line = yes;
+        self
+    }
+
+    /// Sets whether lines may also end with \r\n or a lone \r under the multi-line flag, as if
+    /// the pattern started with "(?R)". ^ and $ then never match between the \r and the \n of a
+    /// \r\n.
+    ///
+    /// ```
+    /// use rare::RareBuilder;
+    ///
+    /// let rare = RareBuilder::new("^b$").multi_line(true).crlf(true).build().unwrap();
+    /// assert!(rare.is_match("a\r\nb\r\nc"));
+    /// ```
+    ///
+    /// * `yes`:
+    pub fn crlf(mut self, yes: bool) -> Self {
+        self.options.crlf = yes;
+        self
+    }
+
+    /// Sets the maximum number of states the lazy DFA behind `RARE::is_match` keeps in each
+    /// `Cache`. A bigger cache helps patterns with many DFA states, at the cost of memory: each
+    /// state takes 4 bytes per character class of the pattern, plus the NFA states it stands for.
+    /// If the cache keeps filling up, matching falls back to the NFA simulation. 0 turns the lazy
+    /// DFA off, so only the NFA simulation is used.
+    ///
+    /// * `states`:
+    pub fn lazy_dfa_cache_size(mut self, states: usize) -> Self {
+        self.options.dfa_cache_size = states;
+        self
+    }
+
+    /// Sets whether to compile the whole DFA of the pattern up front, and minimize it, rather than
+    /// building its states lazily while matching. `RARE::is_match` then costs a single table
+    /// lookup per character, with nothing to cache, but compiling takes longer, and the DFA can be
+    /// exponentially bigger than the pattern. If it would need more states than
+    /// `RareBuilder::dfa_size_limit`, it isn't built, and matching falls back to the lazy DFA.
+    /// `RARE::dfa_size` tells which way it went.
+    ///
+    /// ```
+    /// use rare::RareBuilder;
+    ///
+    /// let rare = RareBuilder::new("(a|b)*abb").full_dfa(true).build().unwrap();
+    /// assert!(rare.is_match("babb"));
+    /// assert_eq!(rare.dfa_size().unwrap().states, 5);
+    /// ```
+    ///
+    /// * `yes`:
+    pub fn full_dfa(mut self, yes: bool) -> Self {
+        self.options.full_dfa = yes;
+        self
+    }
+
+    /// Sets the maximum number of states the full DFA may have while it's built, before
+    /// minimization. Only used with `RareBuilder::full_dfa`.
+    ///
+    /// * `states`:
+    pub fn dfa_size_limit(mut self, states: usize) -> Self {
+        self.options.dfa_size_limit = states;
+        self
+    }
+
+    /// Sets whether to look for the literal strings every match of the pattern starts with or
+    /// contains, such as "ERROR:" in "ERROR: [a-z]+", before running the automata. On by default.
+    /// Matching then skips straight to where the literal is, with a fast substring search, and a
+    /// string without the literal is rejected without running the automata at all.
+    ///
+    /// A pattern that is only an alternation of literals, such as a keyword list "foo|bar|baz",
+    /// is matched by an Aho–Corasick automaton instead of the automata, in one pass over the
+    /// string however many keywords there are.
+    ///
+    /// Turning it off doesn't change any result.
+    ///
+    /// * `yes`:
+    pub fn prefilter(mut self, yes: bool) -> Self {
+        self.options.prefilter = yes;
+        self
+    }
+
+    /// Sets which match wins when several start at the leftmost position: the longest one, as in
+    /// POSIX (the default), or the first one the pattern lists, as in Perl.
+    ///
+    /// ```
+    /// use rare::{MatchKind, RareBuilder};
+    ///
+    /// let longest = RareBuilder::new("a|ab").build().unwrap();
+    /// assert_eq!(longest.captures("ab").unwrap().get(0), Some((0, 2)));
+    /// let first = RareBuilder::new("a|ab")
+    ///     .match_kind(MatchKind::LeftmostFirst)
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(first.captures("ab").unwrap().get(0), Some((0, 1)));
+    /// ```
+    ///
+    /// `RARE::match_all` only reads the string a bounded number of times with `LeftmostLongest`.
+    /// With `LeftmostFirst`, it may read the rest of the string from each match, as Perl does.
+    ///
+    /// * `kind`:
+    pub fn match_kind(mut self, kind: MatchKind) -> Self {
+        self.options.match_kind = kind;
+        self
+    }
+
+    /// Compiles the pattern into a `RARE` matcher.
+    pub fn build(self) -> Result<RARE, String> {
+        let mut parser = Parser::with_options(self.pattern, self.options)?;
+        parser.parse()
+    }
+}
@@ -0,0 +1,111 @@
+use super::lazy_dfa::LazyDfa;
+use super::lookaround::{LookCache, Lookarounds};
+use super::pike_vm::PikeCache;
+use super::reverse_search::ReverseStates;
+use super::sparse_set::SparseSet;
+use super::RARE;
+
+/// Memo lists for the current list of states that is being processed. Also provides a next_states
+/// list, which is swapped with curr_states after curr_states is emptied in the function
+/// `step_once`. The latter is mainly to avoid reallocation.
+///
+/// The fact that this is a struct separate from `RARE` also means that you can match RARE with
+/// however many threads you like, since each thread can hold an instance of this.
+///
+/// Both lists are sparse sets, so the states are kept in the order they are reached in, and
+/// clearing a list costs nothing.
+///
+/// * `curr_states`: the states entered by consuming the string up to the current position.
+/// * `next_states`: a temporary list that gets swapped with curr_states after curr_states is
+///   emptied.
+/// * `visited`: the states reached at the current position, when walking the empty edges. See
+///   `closure::walk`.
+/// * `walk_stack`: the stack of `closure::walk`.
+/// * `look_cache`: the memory of the lookarounds.
+#[derive(Clone, Debug)]
+pub(crate) struct CurrStatesData {
+    pub curr_states: SparseSet,
+    pub next_states: SparseSet,
+    pub visited: SparseSet,
+    pub walk_stack: Vec<usize>,
+    pub look_cache: LookCache,
+}
+
+impl CurrStatesData {
+    /// Constructs empty lists for a NFA with the number of states passed in.
+    ///
+    /// * `state_count`:
+    /// * `lookarounds`: the lookarounds the NFA refers to.
+    pub fn new(state_count: usize, lookarounds: &Lookarounds) -> Self {
+        Self {
+            curr_states: SparseSet::new(state_count),
+            next_states: SparseSet::new(state_count),
+            visited: SparseSet::new(state_count),
+            walk_stack: Vec::new(),
+            look_cache: LookCache::new(lookarounds),
+        }
+    }
+}
+
+/// The memory a `RARE` matcher needs while matching, kept between calls so that matching many
+/// strings, such as the lines of a stream, doesn't allocate for each one. Obtained by calling
+/// `RARE::create_cache`, and passed to the `RARE::*_with` methods.
+///
+/// A cache only belongs to one thread at a time, while the `RARE` matcher can be shared. A cache
+/// created by another `RARE` instance still works, but is emptied on its first use.
+///
+/// ```
+/// use rare::RARE;
+///
+/// let rare = RARE::new("[0-9]+").unwrap();
+/// let mut cache = rare.create_cache();
+/// let mut spans = Vec::new();
+/// for line in ["a1", "b", "22c"] {
+///     if rare.is_match_with(&mut cache, line) {
+///         rare.write_match_all_with(&mut cache, line, &mut spans);
+///     }
+/// }
+/// assert_eq!(spans, [(1, 2), (0, 2)]);
+/// ```
+///
+/// * `owner`: the id of the `RARE` instance this cache was created for.
+/// * `state_data`: the state lists of the NFA simulation.
+/// * `lazy_dfa`: the DFA states built so far, which only make sense for the owner.
+/// * `reverse_states`: the state lists of the reverse NFA simulation.
+/// * `matches`: the matches found by the reverse NFA, before the non-overlapping ones are picked.
+/// * `pike`: the thread lists of the Pike VM, which finds the capture groups.
+#[derive(Clone, Debug)]
+pub struct Cache {
+    owner: usize,
+    pub(crate) state_data: CurrStatesData,
+    pub(crate) lazy_dfa: LazyDfa,
+    pub(crate) reverse_states: ReverseStates,
+    pub(crate) matches: Vec<(usize, usize)>,
+    pub(crate) pike: PikeCache,
+}
+
+impl Cache {
+    /// Constructs a cache sized for the matcher passed in.
+    ///
+    /// * `rare`:
+    pub(crate) fn new(rare: &RARE) -> Self {
+        let state_count = rare.nfa.states.len();
+        Self {
+            owner: rare.id,
+            state_data: CurrStatesData::new(state_count, &rare.lookarounds),
+            lazy_dfa: LazyDfa::new(state_count, rare.classes.len(), rare.options.dfa_cache_size),
+            reverse_states: ReverseStates::new(rare.reverse.state_count(), &rare.lookarounds),
+            matches: Vec::new(),
+            pike: PikeCache::new(state_count, rare.captures_len(), &rare.lookarounds),
+        }
+    }
+
+    /// Makes sure the cache belongs to the matcher passed in, replacing it if not.
+    ///
+    /// * `rare`:
+    pub(crate) fn reset_for(&mut self, rare: &RARE) {
+        if self.owner != rare.id {
+            *self = Self::new(rare);
+        }
+    }
+}
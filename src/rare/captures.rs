@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+use std::ops::Index;
+use std::sync::Arc;
+
+use super::{Cache, RARE};
+
+/// The capture groups of one match. Obtained by calling `RARE::captures` or `RARE::captures_iter`.
+///
+/// Group 0 is the whole match. The other groups are numbered from 1, in the order of their opening
+/// parentheses. All positions are byte offsets into the string matched.
+///
+/// * `haystack`: the string matched.
+/// * `slots`: the start and end of each group. Group `i` is at slots `2i` and `2i + 1`.
+/// * `name_table`: maps the name of each named group to its index.
+pub struct Captures<'h> {
+    haystack: &'h str,
+    slots: Vec<Option<usize>>,
+    name_table: Arc<HashMap<String, usize>>,
+}
+
+impl<'h> Captures<'h> {
+    pub(crate) fn new(
+        haystack: &'h str,
+        slots: Vec<Option<usize>>,
+        name_table: Arc<HashMap<String, usize>>,
+    ) -> Self {
+        Self {
+            haystack,
+            slots,
+            name_table,
+        }
+    }
+
+    /// Returns the start and end of the group passed in, or `None` if the group didn't take part
+    /// in the match (or doesn't exist).
+    ///
+    /// * `idx`: the group index.
+    pub fn get(&self, idx: usize) -> Option<(usize, usize)> {
+        match (self.slots.get(2 * idx)?, self.slots.get(2 * idx + 1)?) {
+            (Some(start), Some(end)) => Some((*start, *end)),
+            _ => None,
+        }
+    }
+
+    /// Returns the substring captured by the group passed in, or `None` if the group didn't take
+    /// part in the match (or doesn't exist).
+    ///
+    /// * `idx`: the group index.
+    pub fn get_str(&self, idx: usize) -> Option<&'h str> {
+        self.get(idx).map(|(start, end)| &self.haystack[start..end])
+    }
+
+    /// Returns the start and end of the named group passed in, or `None` if the group didn't take
+    /// part in the match (or doesn't exist).
+    ///
+    /// * `name`: the group name.
+    pub fn name(&self, name: &str) -> Option<(usize, usize)> {
+        self.get(*self.name_table.get(name)?)
+    }
+
+    /// Returns the substring captured by the named group passed in, or `None` if the group didn't
+    /// take part in the match (or doesn't exist).
+    ///
+    /// * `name`: the group name.
+    pub fn name_str(&self, name: &str) -> Option<&'h str> {
+        self.get_str(*self.name_table.get(name)?)
+    }
+}
+
+impl<'h> Index<usize> for Captures<'h> {
+    type Output = str;
+
+    /// Returns the substring captured by the group passed in.
+    ///
+    /// Panics if the group didn't take part in the match. Use `Captures::get_str` to avoid that.
+    fn index(&self, idx: usize) -> &str {
+        self.get_str(idx)
+            .unwrap_or_else(|| panic!("group {idx} didn't take part in the match"))
+    }
+}
+
+impl<'h> Index<&str> for Captures<'h> {
+    type Output = str;
+
+    /// Returns the substring captured by the named group passed in.
+    ///
+    /// Panics if the group didn't take part in the match. Use `Captures::name_str` to avoid that.
+    fn index(&self, name: &str) -> &str {
+        self.name_str(name)
+            .unwrap_or_else(|| panic!("group {name} didn't take part in the match"))
+    }
+}
+
+/// An iterator over the non-overlapping matches in a string, with their capture groups. Obtained
+/// by calling `RARE::captures_iter`.
+///
+/// The matches are the ones `RARE::match_all` finds, all found on the first call to `next`. Then
+/// the Pike VM only reads each match for its capture groups, rather than searching from where the
+/// previous one ends, which may read the rest of the string every time. The whole iteration so
+/// reads the string a bounded number of times, as `RARE::match_all` does.
+///
+/// An empty match right where the previous match ends is skipped, so "a*" over "baa" yields
+/// (0, 0), (1, 3), and not an extra (3, 3).
+///
+/// * `rare`: the matcher.
+/// * `haystack`: the string matched.
+/// * `cache`: the memory used while matching.
+/// * `spans`: the start and end of each match not yielded yet, `None` before the first call.
+pub struct CapturesIter<'r, 'h> {
+    rare: &'r RARE,
+    haystack: &'h str,
+    cache: Cache,
+    spans: Option<std::vec::IntoIter<(usize, usize)>>,
+}
+
+impl<'r, 'h> CapturesIter<'r, 'h> {
+    pub(crate) fn new(rare: &'r RARE, haystack: &'h str) -> Self {
+        Self {
+            rare,
+            haystack,
+            cache: rare.create_cache(),
+            spans: None,
+        }
+    }
+}
+
+impl<'r, 'h> Iterator for CapturesIter<'r, 'h> {
+    type Item = Captures<'h>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let spans = self.spans.get_or_insert_with(|| {
+            let mut spans = Vec::new();
+            self.rare
+                .write_match_all_with(&mut self.cache, self.haystack, &mut spans);
+            spans.into_iter()
+        });
+        let (start, end) = spans.next()?;
+        self.rare
+            .captures_at(&mut self.cache, self.haystack, start, Some(end))
+    }
+}
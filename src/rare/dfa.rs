@@ -0,0 +1,283 @@
+use std::collections::{HashMap, VecDeque};
+
+use super::lazy_dfa::{DfaKey, PrevChar};
+use super::sparse_set::SparseSet;
+use crate::parser::byte_classes::ByteClasses;
+use crate::parser::closure::{Assertions, EpsilonClosures};
+use crate::parser::nfa::Nfa;
+
+/// The id of the state reached once a match is found, before minimization.
+const MATCH: usize = 0;
+
+/// The size of a compiled `Dfa`, returned by `RARE::dfa_size`.
+///
+/// * `states`: the number of states, after minimization.
+/// * `classes`: the number of character classes, that is, the number of transitions per state.
+///   Characters that no part of the pattern tells apart share a class.
+/// * `bytes`: the memory taken by the transition table.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DfaSize {
+    pub states: usize,
+    pub classes: usize,
+    pub bytes: usize,
+}
+
+/// A fully determinized and minimized automaton, for `RARE::is_match`.
+///
+/// It's built once, when compiling: the subset construction of the NFA (the same one the lazy DFA
+/// does on demand, see `DfaKey`), then Hopcroft's algorithm to merge the states that can't be told
+/// apart. Matching then costs one table lookup per character, with no cache to maintain. The price
+/// is the compile time and the memory, both of which can grow exponentially with the pattern, so
+/// the construction gives up past a size limit.
+///
+/// The transitions are indexed by character class rather than by character, see `ByteClasses`.
+///
+/// * `class_count`: the number of classes of the `ByteClasses` the DFA was built with.
+/// * `table`: the transitions. Those of state `s` are `table[s * class_count..(s + 1) *
+///   class_count]`.
+/// * `starts`: the state where a search starts, for each kind of character before it, indexed by
+///   `PrevChar`.
+/// * `anchors`: the anchors of the NFA, to tell the kind of a character.
+/// * `match_state`: the state reached once a match is found. It only goes to itself.
+/// * `matches_at_end`: whether there's a match if the string ends at each state.
+pub(crate) struct Dfa {
+    class_count: usize,
+    table: Vec<u32>,
+    starts: [u32; PrevChar::ALL.len()],
+    anchors: Assertions,
+    match_state: u32,
+    matches_at_end: Vec<bool>,
+}
+
+impl Dfa {
+    /// Builds the minimal DFA of the NFA passed in.
+    ///
+    /// * `nfa`:
+    /// * `closures`: the epsilon closures of `nfa`.
+    /// * `classes`: the alphabet of `nfa`.
+    /// * `size_limit`: the maximum number of states during the subset construction.
+    /// * Return: the DFA, or `None` if it needs more states than `size_limit`.
+    pub fn new(
+        nfa: &Nfa,
+        closures: &EpsilonClosures,
+        classes: &ByteClasses,
+        size_limit: usize,
+    ) -> Option<Self> {
+        let class_count = classes.len();
+
+        // subset construction. The match state has no key, and loops to itself.
+        let mut keys: Vec<Option<DfaKey>> = vec![None];
+        let mut ids: HashMap<DfaKey, usize> = HashMap::new();
+        let mut queue: VecDeque<usize> = VecDeque::new();
+        let mut starts = [0; PrevChar::ALL.len()];
+        for (start, &prev) in starts.iter_mut().zip(PrevChar::ALL.iter()) {
+            let key = DfaKey::start(prev);
+            *start = *ids.entry(key.clone()).or_insert_with(|| {
+                keys.push(Some(key));
+                queue.push_back(keys.len() - 1);
+                keys.len() - 1
+            });
+        }
+        let mut table: Vec<usize> = vec![MATCH; keys.len() * class_count];
+        let mut scratch = SparseSet::new(nfa.states.len());
+        while let Some(from) = queue.pop_front() {
+            for class in 0..class_count {
+                let key = keys[from].as_ref().unwrap();
+                let c = classes.representative(class);
+                let to = match key.next(nfa, closures, c, &mut scratch) {
+                    None => MATCH,
+                    Some(next) => match ids.get(&next) {
+                        Some(&id) => id,
+                        None => {
+                            if keys.len() >= size_limit {
+                                return None;
+                            }
+                            let id = keys.len();
+                            ids.insert(next.clone(), id);
+                            keys.push(Some(next));
+                            table.resize(table.len() + class_count, MATCH);
+                            queue.push_back(id);
+                            id
+                        }
+                    },
+                };
+                table[from * class_count + class] = to;
+            }
+        }
+        let matches_at_end: Vec<bool> = keys
+            .iter()
+            .map(|key| key.as_ref().is_none_or(|key| key.matches_at_end(closures)))
+            .collect();
+
+        // the states that are told apart from the start: the match state, and whether the string
+        // may end at each of the others.
+        let labels: Vec<usize> = (0..keys.len())
+            .map(|state| match state {
+                MATCH => 0,
+                _ => 1 + matches_at_end[state] as usize,
+            })
+            .collect();
+        let (block_of, block_count) = minimize(&table, class_count, &labels);
+
+        let mut min_table = vec![0; block_count * class_count];
+        let mut min_matches_at_end = vec![false; block_count];
+        for state in 0..keys.len() {
+            let block = block_of[state];
+            for class in 0..class_count {
+                min_table[block * class_count + class] =
+                    block_of[table[state * class_count + class]] as u32;
+            }
+            min_matches_at_end[block] = matches_at_end[state];
+        }
+
+        Some(Self {
+            class_count,
+            table: min_table,
+            starts: starts.map(|start| block_of[start] as u32),
+            anchors: closures.anchors(),
+            match_state: block_of[MATCH] as u32,
+            matches_at_end: min_matches_at_end,
+        })
+    }
+
+    /// Returns the number of states, classes, and the memory taken by the transition table.
+    pub fn size(&self) -> DfaSize {
+        DfaSize {
+            states: self.matches_at_end.len(),
+            classes: self.class_count,
+            bytes: self.table.len() * std::mem::size_of::<u32>(),
+        }
+    }
+
+    /// Returns whether there's a match anywhere in the string passed in, from the byte offset
+    /// passed in.
+    ///
+    /// * `classes`: the alphabet the DFA was built with.
+    /// * `string`:
+    /// * `start`: a byte offset on a char boundary. No match starts before it.
+    pub fn is_match(&self, classes: &ByteClasses, string: &str, start: usize) -> bool {
+        let prev = PrevChar::new(string[..start].chars().next_back(), self.anchors);
+        let mut state = self.starts[prev as usize];
+        for c in string[start..].chars() {
+            state = self.table[state as usize * self.class_count + classes.get(c)];
+            if state == self.match_state {
+                return true;
+            }
+        }
+        self.matches_at_end[state as usize]
+    }
+}
+
+/// Merges the states of a DFA that behave the same, with Hopcroft's algorithm.
+///
+/// States start out split into blocks by their labels. Then, a block is split whenever some of its
+/// states go into a given block on a given class, and the others don't. Once nothing can be split,
+/// each block is a state of the minimal DFA.
+///
+/// * `table`: the transitions of the DFA, `class_count` per state.
+/// * `class_count`:
+/// * `labels`: the states that may be merged must have the same label.
+/// * Return: the block of each state, and the number of blocks.
+fn minimize(table: &[usize], class_count: usize, labels: &[usize]) -> (Vec<usize>, usize) {
+    let state_count = labels.len();
+
+    // the states going to each state on each class, stored flat: those going to `t` on `c` are
+    // `sources[offsets[t * class_count + c]..offsets[t * class_count + c + 1]]`.
+    let mut offsets = vec![0; state_count * class_count + 1];
+    for &to in table.iter() {
+        offsets[to * class_count + 1] += 1;
+    }
+    let mut counts = vec![0; state_count * class_count];
+    for (idx, &to) in table.iter().enumerate() {
+        counts[to * class_count + idx % class_count] += 1;
+    }
+    for idx in 0..state_count * class_count {
+        offsets[idx + 1] = offsets[idx] + counts[idx];
+    }
+    let mut fill = offsets.clone();
+    let mut sources = vec![0; table.len()];
+    for (idx, &to) in table.iter().enumerate() {
+        let slot = to * class_count + idx % class_count;
+        sources[fill[slot]] = idx / class_count;
+        fill[slot] += 1;
+    }
+
+    // the partition: each block is a range of `elements`, with its marked states at the front.
+    let mut elements: Vec<usize> = (0..state_count).collect();
+    elements.sort_by_key(|&state| labels[state]);
+    let mut location = vec![0; state_count];
+    let mut block_of = vec![0; state_count];
+    let mut blocks: Vec<(usize, usize)> = Vec::new();
+    for (idx, &state) in elements.iter().enumerate() {
+        location[state] = idx;
+        if idx == 0 || labels[elements[idx - 1]] != labels[state] {
+            blocks.push((idx, idx));
+        }
+        blocks.last_mut().unwrap().1 = idx + 1;
+        block_of[state] = blocks.len() - 1;
+    }
+
+    let mut waiting: Vec<usize> = (0..blocks.len()).collect();
+    let mut in_waiting = vec![true; blocks.len()];
+    let mut marked = vec![0; blocks.len()];
+    let mut touched: Vec<usize> = Vec::new();
+    let mut splitter: Vec<usize> = Vec::new();
+    while let Some(block) = waiting.pop() {
+        in_waiting[block] = false;
+        splitter.clear();
+        splitter.extend_from_slice(&elements[blocks[block].0..blocks[block].1]);
+        for class in 0..class_count {
+            // mark the states going into the splitter on this class.
+            for &to in splitter.iter() {
+                let slot = to * class_count + class;
+                for &from in sources[offsets[slot]..offsets[slot + 1]].iter() {
+                    let from_block = block_of[from];
+                    let first_unmarked = blocks[from_block].0 + marked[from_block];
+                    let idx = location[from];
+                    if idx < first_unmarked {
+                        continue;
+                    }
+                    elements.swap(idx, first_unmarked);
+                    location[elements[idx]] = idx;
+                    location[from] = first_unmarked;
+                    if marked[from_block] == 0 {
+                        touched.push(from_block);
+                    }
+                    marked[from_block] += 1;
+                }
+            }
+
+            // split the blocks partly marked.
+            for split in touched.drain(..) {
+                let (start, end) = blocks[split];
+                let mid = start + std::mem::take(&mut marked[split]);
+                if mid == end {
+                    continue;
+                }
+                let new_block = blocks.len();
+                blocks[split] = (mid, end);
+                blocks.push((start, mid));
+                marked.push(0);
+                for &state in elements[start..mid].iter() {
+                    block_of[state] = new_block;
+                }
+                // if the old block still waits, both halves must. Otherwise, splitting with the
+                // smaller half is enough.
+                let pushed = if in_waiting[split] || mid - start <= end - mid {
+                    new_block
+                } else {
+                    split
+                };
+                in_waiting.push(false);
+                if !in_waiting[pushed] {
+                    in_waiting[pushed] = true;
+                    waiting.push(pushed);
+                }
+            }
+        }
+    }
+
+    (block_of, blocks.len())
+}
+
+mod test;
@@ -0,0 +1,87 @@
+#[cfg(test)]
+use super::*;
+#[cfg(test)]
+use crate::rare::{RareBuilder, RARE};
+
+#[test]
+fn minimize_test() {
+    // 0 and 1 both go to 2 on every class: they can't be told apart. 2 and 3 can, by their
+    // labels.
+    let table = [2, 2, 2, 2, 2, 2, 3, 3];
+    let (block_of, block_count) = minimize(&table, 2, &[0, 0, 1, 2]);
+    assert_eq!(block_count, 3);
+    assert_eq!(block_of[0], block_of[1]);
+    assert_ne!(block_of[1], block_of[2]);
+    assert_ne!(block_of[2], block_of[3]);
+
+    // a chain 0 -> 1 -> 2 -> 3, where only 3 is labeled differently: every state is at a
+    // different distance from 3, so nothing merges.
+    let table = [1, 2, 3, 3];
+    let (_, block_count) = minimize(&table, 1, &[0, 0, 0, 1]);
+    assert_eq!(block_count, 4);
+}
+
+#[test]
+fn full_dfa_test() {
+    // searching "abb" only needs to remember how much of it was just seen, whatever the
+    // alternation before it. The match is noticed one character later, or at the end of the
+    // string, so "abb" just seen is a state of its own, apart from the match state: 5 states.
+    let rare = RareBuilder::new("(a|b)*abb")
+        .full_dfa(true)
+        .build()
+        .unwrap();
+    let size = rare.dfa_size().unwrap();
+    assert_eq!(size.states, 5);
+    assert!(rare.is_match("aababb"));
+    assert!(!rare.is_match("abab"));
+
+    // \n, 'a', 'b', and everything else.
+    assert_eq!(size.classes, 4);
+    assert_eq!(size.bytes, 5 * 4 * 4);
+
+    let cases: &[(&str, &[&str])] = &[
+        ("ab+|c+", &["abbbbbb", "bccccccccc", "adbbbbb", "abc", ""]),
+        (
+            "(.c(xx|pp))$",
+            &[".hcxx.cpp", ".hcxx", ".cpp", "cxx and c.cxx"],
+        ),
+        ("^(Never)+", &["Neverrrrrrr", "NeverNever", " Never"]),
+        ("(?m)^b$", &["a\nb\nc", "a\nbb\nc", "b", "\nb\n"]),
+        ("(?i)grüße", &["GRÜẞE", "grüsse"]),
+        ("ö$|^👍", &["schö", "schön", "👍a", "a👍"]),
+        ("[^a]x", &["\u{10FFFF}x", "\u{E000}x", "ax", "x"]),
+    ];
+    for &(pattern, strings) in cases {
+        let dfa = RareBuilder::new(pattern).full_dfa(true).build().unwrap();
+        assert!(dfa.dfa_size().is_some());
+        let nfa_only = RareBuilder::new(pattern)
+            .lazy_dfa_cache_size(0)
+            .build()
+            .unwrap();
+        for &string in strings {
+            assert_eq!(
+                dfa.is_match(string),
+                nfa_only.is_match(string),
+                "{pattern:?} over {string:?}"
+            );
+        }
+    }
+}
+
+#[test]
+fn dfa_size_limit_test() {
+    // the DFA has to remember the last 12 characters: thousands of states.
+    let pattern = "a.{11}$";
+    let rare = RareBuilder::new(pattern)
+        .full_dfa(true)
+        .dfa_size_limit(100)
+        .build()
+        .unwrap();
+    assert_eq!(rare.dfa_size(), None);
+    // the lazy DFA takes over.
+    assert!(rare.is_match("xxa01234567890"));
+    assert!(!rare.is_match("xxa0123456789x0"));
+
+    // not asked for.
+    assert_eq!(RARE::new("abc").unwrap().dfa_size(), None);
+}
@@ -0,0 +1,344 @@
+use std::collections::HashMap;
+
+use super::sparse_set::SparseSet;
+use crate::parser::byte_classes::ByteClasses;
+use crate::parser::closure::{
+    is_ascii_word_char, is_word_char, Assertions, EpsilonClosures, Target,
+};
+use crate::parser::nfa::Nfa;
+
+/// The id of a DFA state: its index in `LazyDfa::states`.
+type StateId = u32;
+
+/// A transition that hasn't been computed yet.
+const UNKNOWN: StateId = StateId::MAX;
+/// A transition that goes through the end state of the NFA: the string matches.
+const MATCH: StateId = StateId::MAX - 1;
+
+/// How many times the cache may fill up during one search before giving up on the DFA.
+const MAX_CACHE_CLEARS: usize = 8;
+
+/// What the anchors need to know about the character before a position. Part of each DFA state,
+/// since two positions with the same NFA states but different characters before them can still
+/// behave differently.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub(crate) enum PrevChar {
+    /// The start of the string.
+    None,
+    NewLine,
+    /// A \r, only told apart if the NFA has an anchor under the CRLF flag.
+    CarriageReturn,
+    Other,
+    /// A word character outside ASCII, only told apart if the NFA has a Unicode \b or \B.
+    Word,
+    /// A word character of ASCII, only told apart if the NFA has a \b or \B.
+    AsciiWord,
+}
+
+impl PrevChar {
+    /// Every kind of character, in the order of their discriminants.
+    pub const ALL: [Self; 6] = [
+        PrevChar::None,
+        PrevChar::NewLine,
+        PrevChar::CarriageReturn,
+        PrevChar::Other,
+        PrevChar::Word,
+        PrevChar::AsciiWord,
+    ];
+
+    /// Returns the kind of the character passed in. The word characters and \r are only told apart
+    /// from the others if some anchor looks at them, so that no DFA state is duplicated for
+    /// nothing.
+    ///
+    /// * `c`: `None` at the start of the string.
+    /// * `anchors`: the anchors of the NFA, see `EpsilonClosures::anchors`.
+    pub fn new(c: Option<char>, anchors: Assertions) -> Self {
+        match c {
+            None => PrevChar::None,
+            Some('\n') => PrevChar::NewLine,
+            Some('\r') if anchors.intersects(Assertions::CRLF_ANCHORS) => PrevChar::CarriageReturn,
+            Some(c)
+                if anchors.intersects(Assertions::ASCII_WORD_ANCHORS) && is_ascii_word_char(c) =>
+            {
+                PrevChar::AsciiWord
+            }
+            Some(c) if anchors.intersects(Assertions::WORD_ANCHORS) && is_word_char(c) => {
+                PrevChar::Word
+            }
+            Some(_) => PrevChar::Other,
+        }
+    }
+
+    /// Returns a character that the anchors treat the same way as every character of this kind.
+    fn representative(self) -> Option<char> {
+        match self {
+            PrevChar::None => None,
+            PrevChar::NewLine => Some('\n'),
+            PrevChar::CarriageReturn => Some('\r'),
+            PrevChar::Other => Some('\0'),
+            PrevChar::Word => Some('é'),
+            PrevChar::AsciiWord => Some('a'),
+        }
+    }
+}
+
+/// A state of a DFA searching for a match anywhere in the string: the NFA states the simulation
+/// would hold at some position, and the kind of character before that position. Shared by the
+/// lazy DFA and the full `Dfa`.
+///
+/// * `nfa_states`: sorted, so that the same set always gives the same DFA state. Always holds NFA
+///   state 0, since a match may start at any position.
+/// * `prev`:
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub(crate) struct DfaKey {
+    nfa_states: Box<[usize]>,
+    prev: PrevChar,
+}
+
+impl DfaKey {
+    /// Returns the DFA state where a search starts.
+    ///
+    /// * `prev`: the kind of the character right before the search starts.
+    pub fn start(prev: PrevChar) -> Self {
+        Self {
+            nfa_states: Box::new([0]),
+            prev,
+        }
+    }
+
+    /// Returns the NFA states of this DFA state.
+    pub fn nfa_states(&self) -> &[usize] {
+        &self.nfa_states
+    }
+
+    /// Computes the DFA state reached from this one by consuming the character passed in. This is
+    /// one step of the subset construction.
+    ///
+    /// * `nfa`:
+    /// * `closures`: the epsilon closures of `nfa`.
+    /// * `c`:
+    /// * `scratch`: where the NFA states are collected, sized to `nfa`.
+    /// * Return: the next DFA state, or `None` if a match ends right before `c`.
+    pub fn next(
+        &self,
+        nfa: &Nfa,
+        closures: &EpsilonClosures,
+        c: char,
+        scratch: &mut SparseSet,
+    ) -> Option<Self> {
+        let held = Assertions::at(self.prev.representative(), Some(c));
+        scratch.clear();
+        for &state_ref in self.nfa_states.iter() {
+            for entry in closures.of(state_ref) {
+                if !held.contains(entry.needs) {
+                    continue;
+                }
+                match entry.target {
+                    Target::Match => return None,
+                    Target::Consume(next_ref) => {
+                        if nfa.states[next_ref].token.token_type.accepts(c) {
+                            scratch.insert(next_ref);
+                        }
+                    }
+                }
+            }
+        }
+
+        // a match may start at the next position too.
+        scratch.insert(0);
+        let mut nfa_states: Box<[usize]> = scratch.as_slice().into();
+        nfa_states.sort_unstable();
+        Some(Self {
+            nfa_states,
+            prev: PrevChar::new(Some(c), closures.anchors()),
+        })
+    }
+
+    /// Returns whether a match ends at the end of the string, if the string ends at this state.
+    ///
+    /// * `closures`:
+    pub fn matches_at_end(&self, closures: &EpsilonClosures) -> bool {
+        let held = Assertions::at(self.prev.representative(), None);
+        self.nfa_states
+            .iter()
+            .flat_map(|&state_ref| closures.of(state_ref))
+            .any(|entry| entry.target == Target::Match && held.contains(entry.needs))
+    }
+}
+
+/// A lazy DFA: the subset construction of the NFA, done as the input needs it.
+///
+/// Each DFA state (a `DfaKey`) is the set of NFA states the simulation in `RARE::step_once` would
+/// be holding at some position. Its transitions are computed the first time they are taken, using
+/// the precomputed epsilon closures, and cached, so that from then on, each character costs a
+/// single table lookup. The transitions are indexed by character class, see `ByteClasses`. Only
+/// `RARE::is_match` uses it, so every DFA state also holds NFA state 0: a match may start anywhere.
+///
+/// The cache is bounded. When it's full, it's cleared and rebuilt from the current state. If that
+/// happens too often during one search, the DFA isn't paying off, and the search goes on with the
+/// NFA simulation instead.
+///
+/// * `capacity`: the maximum number of DFA states held at once.
+/// * `states`: the key of each DFA state, by id.
+/// * `ids`: the id of each DFA state, by key.
+/// * `class_count`: the number of classes of the NFA's `ByteClasses`.
+/// * `transitions`: the transitions of every state. Those of state `s` are `transitions[s *
+///   class_count..(s + 1) * class_count]`.
+/// * `scratch`: the NFA states being collected for a new DFA state.
+#[derive(Clone, Debug)]
+pub(crate) struct LazyDfa {
+    capacity: usize,
+    states: Vec<DfaKey>,
+    ids: HashMap<DfaKey, StateId>,
+    class_count: usize,
+    transitions: Vec<StateId>,
+    scratch: SparseSet,
+}
+
+/// Why a lazy DFA search stopped without an answer.
+///
+/// * `pos`: the byte offset where the search stopped.
+/// * `nfa_states`: the NFA states the simulation holds at `pos`, to continue from there.
+pub(crate) struct GaveUp {
+    pub pos: usize,
+    pub nfa_states: Box<[usize]>,
+}
+
+impl LazyDfa {
+    /// Constructs an empty lazy DFA for a NFA with the number of states and classes passed in.
+    ///
+    /// * `state_count`:
+    /// * `class_count`:
+    /// * `capacity`: the maximum number of DFA states held at once.
+    pub fn new(state_count: usize, class_count: usize, capacity: usize) -> Self {
+        Self {
+            // the largest ids are taken by `UNKNOWN` and `MATCH`.
+            capacity: capacity.clamp(1, MATCH as usize - 1),
+            states: Vec::new(),
+            ids: HashMap::new(),
+            class_count,
+            transitions: Vec::new(),
+            scratch: SparseSet::new(state_count),
+        }
+    }
+
+    /// Returns the number of DFA states currently cached.
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    /// Returns whether there's a match anywhere in the string passed in, from the byte offset
+    /// passed in.
+    ///
+    /// * `nfa`:
+    /// * `closures`: the epsilon closures of `nfa`.
+    /// * `classes`: the alphabet of `nfa`.
+    /// * `string`:
+    /// * `start`: a byte offset on a char boundary. No match starts before it.
+    /// * Return: the answer, or where the DFA gave up if the cache kept filling up.
+    pub fn is_match(
+        &mut self,
+        nfa: &Nfa,
+        closures: &EpsilonClosures,
+        classes: &ByteClasses,
+        string: &str,
+        start: usize,
+    ) -> Result<bool, GaveUp> {
+        let mut clears = 0;
+        let prev = PrevChar::new(string[..start].chars().next_back(), closures.anchors());
+        let mut state = self.start_state(prev);
+        for (pos, c) in string[start..].char_indices() {
+            let pos = start + pos;
+            let class = classes.get(c);
+            let next = match self.transitions[state as usize * self.class_count + class] {
+                UNKNOWN => {
+                    if self.states.len() >= self.capacity {
+                        clears += 1;
+                        let key = self.states[state as usize].clone();
+                        if clears > MAX_CACHE_CLEARS {
+                            return Err(GaveUp {
+                                pos,
+                                nfa_states: key.nfa_states,
+                            });
+                        }
+                        self.clear();
+                        state = self.add_state(key);
+                    }
+                    self.compute_transition(nfa, closures, state, c, class)
+                }
+                next => next,
+            };
+            if next == MATCH {
+                return Ok(true);
+            }
+            state = next;
+        }
+
+        // the end of the string can still let anchors through.
+        Ok(self.states[state as usize].matches_at_end(closures))
+    }
+
+    /// Returns the DFA state where a search starts, adding it if it isn't cached.
+    ///
+    /// * `prev`: the kind of the character right before the search starts.
+    fn start_state(&mut self, prev: PrevChar) -> StateId {
+        let key = DfaKey::start(prev);
+        match self.ids.get(&key) {
+            Some(&id) => id,
+            None => self.add_state(key),
+        }
+    }
+
+    /// Computes where the DFA state passed in goes on the character passed in, adding the target
+    /// state if it's new, and caches the transition for the whole class of the character.
+    ///
+    /// * `nfa`:
+    /// * `closures`:
+    /// * `from`:
+    /// * `c`:
+    /// * `class`: the class of `c`.
+    fn compute_transition(
+        &mut self,
+        nfa: &Nfa,
+        closures: &EpsilonClosures,
+        from: StateId,
+        c: char,
+        class: usize,
+    ) -> StateId {
+        let next = match DfaKey::next(
+            &self.states[from as usize],
+            nfa,
+            closures,
+            c,
+            &mut self.scratch,
+        ) {
+            None => MATCH,
+            Some(key) => match self.ids.get(&key) {
+                Some(&id) => id,
+                None => self.add_state(key),
+            },
+        };
+
+        self.transitions[from as usize * self.class_count + class] = next;
+        next
+    }
+
+    /// Adds a new DFA state, with no transition computed yet.
+    ///
+    /// * `key`:
+    fn add_state(&mut self, key: DfaKey) -> StateId {
+        let id = self.states.len() as StateId;
+        self.ids.insert(key.clone(), id);
+        self.states.push(key);
+        self.transitions
+            .resize(self.transitions.len() + self.class_count, UNKNOWN);
+        id
+    }
+
+    /// Empties the cache.
+    fn clear(&mut self) {
+        self.states.clear();
+        self.ids.clear();
+        self.transitions.clear();
+    }
+}
@@ -0,0 +1,221 @@
+use std::ops::ControlFlow;
+
+use super::sparse_set::SparseSet;
+use crate::lexer::token_type::{LookKind, Token, TokenType};
+use crate::parser::closure::{self, Assertions, EpsilonClosures, Target};
+use crate::parser::nfa::Nfa;
+
+/// A lookaround assertion, such as `(?=bar)` or `(?<!\\)`, checked by running the NFA of its
+/// group from the position, rather than by the automaton of the whole expression.
+///
+/// A lookahead runs the NFA of the group forwards from the position, and holds if it reaches its
+/// end. A lookbehind runs the reverse NFA backwards from the position instead, so its group may
+/// match strings of any length. Either way, the run stops as soon as the end is reached, or no
+/// state is left, and the anchors inside are checked against the whole string.
+///
+/// * `kind`:
+/// * `nfa`: the NFA of the group, reversed for a lookbehind.
+/// * `closures`: the epsilon closures of `nfa`, `None` if they're too big.
+pub(crate) struct Lookaround {
+    kind: LookKind,
+    nfa: Nfa,
+    closures: Option<EpsilonClosures>,
+}
+
+impl Lookaround {
+    /// Compiles the lookaround of the kind passed in, over the NFA of its group.
+    ///
+    /// * `kind`:
+    /// * `group_nfa`: the NFA of what's inside the parentheses. Has no lookaround itself.
+    pub fn new(kind: LookKind, group_nfa: Nfa) -> Self {
+        // the start of a NFA never checks its own token.
+        let mut nfa = Nfa::new(Token::new(0, TokenType::Empty));
+        nfa.merge(group_nfa);
+        if !kind.ahead() {
+            nfa = nfa.reverse();
+        }
+        let closures = EpsilonClosures::new(&nfa);
+        Self {
+            kind,
+            nfa,
+            closures,
+        }
+    }
+
+    /// Returns whether the assertion holds at the byte offset passed in.
+    ///
+    /// * `string`:
+    /// * `pos`: a byte offset on a char boundary.
+    /// * `states`: reused between calls, to avoid reallocation. Sized to the NFA at least.
+    fn holds(&self, string: &str, pos: usize, states: &mut LookStates) -> bool {
+        self.matches_from(string, pos, states) != self.kind.negated()
+    }
+
+    /// Returns whether the group matches the text right after the position for a lookahead, or
+    /// right before it for a lookbehind.
+    ///
+    /// * `string`:
+    /// * `pos`: a byte offset on a char boundary.
+    /// * `states`: see `Lookaround::holds`.
+    fn matches_from(&self, string: &str, mut pos: usize, states: &mut LookStates) -> bool {
+        let LookStates {
+            curr_states,
+            next_states,
+            visited,
+            walk_stack,
+        } = states;
+        let ahead = self.kind.ahead();
+        curr_states.clear();
+        curr_states.insert(0);
+        loop {
+            let held = Assertions::at_offset(string, pos);
+            let c = match ahead {
+                true => string[pos..].chars().next(),
+                false => string[..pos].chars().next_back(),
+            };
+            next_states.clear();
+            visited.clear();
+            for &state_ref in curr_states.as_slice() {
+                let visit = |target| match target {
+                    Target::Match => ControlFlow::Break(()),
+                    Target::Consume(next_ref) => {
+                        let transition = &self.nfa.states[next_ref].token.token_type;
+                        if c.is_some_and(|c| transition.accepts(c)) {
+                            next_states.insert(next_ref);
+                        }
+                        ControlFlow::Continue(())
+                    }
+                };
+                let walked = match &self.closures {
+                    Some(closures) => closures
+                        .of(state_ref)
+                        .iter()
+                        .filter(|entry| held.contains(entry.needs))
+                        .map(|entry| entry.target)
+                        .try_for_each(visit),
+                    // the group has no lookaround inside.
+                    None => closure::walk(
+                        &self.nfa,
+                        state_ref,
+                        held,
+                        visited,
+                        walk_stack,
+                        |_| false,
+                        visit,
+                    ),
+                };
+                if walked.is_break() {
+                    return true;
+                }
+            }
+            let Some(c) = c.filter(|_| !next_states.is_empty()) else {
+                return false;
+            };
+            match ahead {
+                true => pos += c.len_utf8(),
+                false => pos -= c.len_utf8(),
+            }
+            std::mem::swap(curr_states, next_states);
+        }
+    }
+}
+
+/// The lookarounds of an expression. The `TokenType::Assert` states of its NFA refer to them by
+/// index.
+#[derive(Default)]
+pub(crate) struct Lookarounds(Vec<Lookaround>);
+
+impl Lookarounds {
+    /// Adds a lookaround.
+    ///
+    /// * `lookaround`:
+    /// * Return: its index.
+    pub fn push(&mut self, lookaround: Lookaround) -> usize {
+        self.0.push(lookaround);
+        self.0.len() - 1
+    }
+
+    /// Returns whether the expression has no lookaround.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns whether the lookaround passed in holds at the byte offset passed in. Each one is
+    /// only run once per position: the result is kept in the cache until another position is
+    /// asked about.
+    ///
+    /// * `idx`: the index of the lookaround, as in `TokenType::Assert`.
+    /// * `string`:
+    /// * `pos`: a byte offset on a char boundary.
+    /// * `cache`: must have been cleared since `string` was last changed, see `LookCache::clear`.
+    pub fn holds(&self, idx: usize, string: &str, pos: usize, cache: &mut LookCache) -> bool {
+        let LookCache {
+            pos: known_pos,
+            known,
+            states,
+        } = cache;
+        if *known_pos != pos {
+            known.fill(None);
+            *known_pos = pos;
+        }
+        *known[idx].get_or_insert_with(|| self.0[idx].holds(string, pos, states))
+    }
+}
+
+/// The memory the lookarounds of an expression need while matching: their results at the current
+/// position, and the state lists their NFAs are run with.
+///
+/// * `pos`: the byte offset `known` is about.
+/// * `known`: whether each lookaround holds at `pos`, `None` if it hasn't been run there yet.
+/// * `states`:
+#[derive(Clone, Debug)]
+pub(crate) struct LookCache {
+    pos: usize,
+    known: Vec<Option<bool>>,
+    states: LookStates,
+}
+
+/// The state lists the NFA of a lookaround is run with, sized to the biggest NFA.
+///
+/// * `curr_states`: the states entered at the current position.
+/// * `next_states`: the states entered by consuming the next character.
+/// * `visited`: the states reached at the current position, when walking the empty edges. See
+///   `closure::walk`.
+/// * `walk_stack`: the stack of `closure::walk`.
+#[derive(Clone, Debug)]
+struct LookStates {
+    curr_states: SparseSet,
+    next_states: SparseSet,
+    visited: SparseSet,
+    walk_stack: Vec<usize>,
+}
+
+impl LookCache {
+    /// Constructs a cache sized for the lookarounds passed in.
+    ///
+    /// * `lookarounds`:
+    pub fn new(lookarounds: &Lookarounds) -> Self {
+        let state_count = lookarounds
+            .0
+            .iter()
+            .map(|lookaround| lookaround.nfa.states.len())
+            .max()
+            .unwrap_or(0);
+        Self {
+            pos: 0,
+            known: vec![None; lookarounds.0.len()],
+            states: LookStates {
+                curr_states: SparseSet::new(state_count),
+                next_states: SparseSet::new(state_count),
+                visited: SparseSet::new(state_count),
+                walk_stack: Vec::new(),
+            },
+        }
+    }
+
+    /// Forgets the results kept, since they only make sense for the string they were computed
+    /// over. Called when a search starts.
+    pub fn clear(&mut self) {
+        self.known.fill(None);
+    }
+}
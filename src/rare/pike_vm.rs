@@ -0,0 +1,387 @@
+use super::lookaround::{LookCache, Lookarounds};
+use super::sparse_set::SparseSet;
+use super::MatchKind;
+use crate::lexer::token_type::TokenType;
+use crate::parser::closure::Assertions;
+use crate::parser::nfa::Nfa;
+
+/// A Pike VM: simulates the NFA like `RARE::step_once` does, but each state being tracked (a
+/// "thread") also carries the capture slots recorded on the way to it.
+///
+/// Threads are kept in priority order. A thread that reaches a state already taken by another
+/// thread at the same string position is dropped, so each step costs at most one visit per state,
+/// and the whole search stays $O(mn)$.
+///
+/// The match starting the earliest always wins. Among those, with `MatchKind::LeftmostLongest`,
+/// the longest one does, and the capture slots follow the POSIX rule: each group, in the order of
+/// their opening parentheses, starts as early and then ends as late as it can, but for the
+/// repeated groups, see `posix_prefers`. So there, a thread that reaches a state already taken
+/// replaces the one there if the rule prefers its slots, and the states past it are visited
+/// again. With `MatchKind::LeftmostFirst`, the thread with the
+/// highest priority that matches wins: once a thread matches, every thread after it is dropped,
+/// and only those before it may still replace its match.
+///
+/// * `nfa`: the NFA to simulate.
+/// * `lookarounds`: the lookarounds the NFA refers to.
+/// * `slot_count`: the number of capture slots, which is twice the number of groups, including
+///   group 0 (the whole match).
+/// * `match_kind`: which match wins.
+/// * `repeated_groups`: whether each capture group is inside a quantifier that may go through it
+///   more than once, group 0 first.
+pub(crate) struct PikeVm<'r> {
+    nfa: &'r Nfa,
+    lookarounds: &'r Lookarounds,
+    slot_count: usize,
+    match_kind: MatchKind,
+    repeated_groups: &'r [bool],
+}
+
+/// A list of threads, all at the same string position.
+///
+/// * `visited`: the states already reached at this position.
+/// * `threads`: the states waiting to consume a character, in priority order.
+/// * `slots`: the capture slots of each thread. The slots of the thread at state `s` are `slots[s *
+///   slot_count..(s + 1) * slot_count]`. With `MatchKind::LeftmostLongest`, every state visited
+///   holds the slots of the thread that got there.
+/// * `look_cache`: the memory of the lookarounds, checked at the position of the list.
+#[derive(Clone, Debug)]
+struct ThreadList {
+    visited: SparseSet,
+    threads: Vec<usize>,
+    slots: Vec<Option<usize>>,
+    look_cache: LookCache,
+}
+
+/// The memory the Pike VM needs while matching, kept in the `Cache`.
+///
+/// * `clist`: the threads at the current position.
+/// * `nlist`: the threads after the current character.
+/// * `scratch`: the capture slots of the thread being followed.
+/// * `stack`: the depth-first walk over the empty transitions.
+#[derive(Clone, Debug)]
+pub(crate) struct PikeCache {
+    clist: ThreadList,
+    nlist: ThreadList,
+    scratch: Vec<Option<usize>>,
+    stack: Vec<Frame>,
+}
+
+impl PikeCache {
+    /// Constructs the memory for a NFA with the number of states and groups passed in.
+    ///
+    /// * `state_count`:
+    /// * `group_count`: the number of capture groups, not counting group 0.
+    /// * `lookarounds`: the lookarounds the NFA refers to.
+    pub fn new(state_count: usize, group_count: usize, lookarounds: &Lookarounds) -> Self {
+        let slot_count = 2 * (group_count + 1);
+        Self {
+            clist: ThreadList::new(state_count, slot_count, lookarounds),
+            nlist: ThreadList::new(state_count, slot_count, lookarounds),
+            scratch: vec![None; slot_count],
+            stack: Vec::new(),
+        }
+    }
+}
+
+/// One step of the depth-first walk over the empty transitions.
+#[derive(Clone, Debug)]
+enum Frame {
+    /// Visits the state held.
+    Explore(usize),
+    /// Undoes a slot update after everything past the `Save` state has been visited.
+    Restore(usize, Option<usize>),
+}
+
+impl ThreadList {
+    fn new(state_count: usize, slot_count: usize, lookarounds: &Lookarounds) -> Self {
+        Self {
+            visited: SparseSet::new(state_count),
+            threads: Vec::with_capacity(state_count),
+            slots: vec![None; state_count * slot_count],
+            look_cache: LookCache::new(lookarounds),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.visited.clear();
+        self.threads.clear();
+    }
+
+    /// Empties the list, and forgets the lookarounds checked, before a search over another string.
+    fn reset(&mut self) {
+        self.clear();
+        self.look_cache.clear();
+    }
+}
+
+impl<'r> PikeVm<'r> {
+    /// Constructs a Pike VM over the NFA passed in.
+    ///
+    /// * `nfa`:
+    /// * `lookarounds`: the lookarounds the NFA refers to.
+    /// * `repeated_groups`: whether each capture group in the NFA is repeated, group 0 first.
+    /// * `match_kind`:
+    pub fn new(
+        nfa: &'r Nfa,
+        lookarounds: &'r Lookarounds,
+        repeated_groups: &'r [bool],
+        match_kind: MatchKind,
+    ) -> Self {
+        Self {
+            nfa,
+            lookarounds,
+            slot_count: 2 * repeated_groups.len(),
+            match_kind,
+            repeated_groups,
+        }
+    }
+
+    /// Finds the leftmost match starting at or after `start`.
+    ///
+    /// Without `span`, the search reads as far as a thread may still find a better match, which
+    /// can be the rest of the string, as for "a|a[^x]*b" over "aaa...a". Where the match is
+    /// already known, from `RARE::match_all`, only its span is read.
+    ///
+    /// * `cache`: must have been created for the same NFA.
+    /// * `haystack`: the whole input string. Anchors are checked against the whole string, not
+    ///   against `haystack[start..]`.
+    /// * `start`: the byte offset to start searching from. Must be on a char boundary.
+    /// * `span`: where the match ends, if it's known to start at `start` and end there.
+    /// * Return: the capture slots of the match, as byte offsets, if there is one.
+    pub fn search(
+        &self,
+        cache: &mut PikeCache,
+        haystack: &str,
+        start: usize,
+        span: Option<usize>,
+    ) -> Option<Vec<Option<usize>>> {
+        let PikeCache {
+            clist,
+            nlist,
+            scratch,
+            stack,
+        } = cache;
+        clist.reset();
+        nlist.reset();
+        let mut best: Option<Vec<Option<usize>>> = None;
+
+        let mut pos = start;
+        loop {
+            // a new thread starts at every position, until something matches. It has the lowest
+            // priority, since every thread already in the list started earlier.
+            if best.is_none() && (span.is_none() || pos == start) {
+                scratch.fill(None);
+                scratch[0] = Some(pos);
+                stack.push(Frame::Explore(0));
+                self.add_threads(clist, stack, scratch, haystack, pos, &mut best);
+            }
+            // with a match found, no new thread starts, so nothing is left to do. Otherwise, an
+            // anchor may still let a thread started later through. At the end of the known
+            // match, nothing is left to read.
+            if (clist.threads.is_empty() && best.is_some()) || span == Some(pos) {
+                break;
+            }
+
+            let curr_char = haystack[pos..].chars().next();
+            let next_pos = pos + curr_char.map_or(0, char::len_utf8);
+            for idx in 0..clist.threads.len() {
+                let state_ref = clist.threads[idx];
+                let slots = &clist.slots[state_ref * self.slot_count..][..self.slot_count];
+                // leftmost: a thread starting after the match found so far can't win.
+                if best.as_ref().is_some_and(|best| slots[0] > best[0]) {
+                    continue;
+                }
+                let state = &self.nfa.states[state_ref];
+                if !curr_char.is_some_and(|c| state.token.token_type.accepts(c)) {
+                    continue;
+                }
+                scratch.copy_from_slice(slots);
+                let matched = if state_ref == self.nfa.end {
+                    self.record_match(&mut best, scratch, next_pos);
+                    true
+                } else {
+                    stack.extend(state.edges.iter().rev().map(|&next| Frame::Explore(next)));
+                    self.add_threads(nlist, stack, scratch, haystack, next_pos, &mut best)
+                };
+                // the threads left have a lower priority than the one that matched.
+                if matched && self.match_kind == MatchKind::LeftmostFirst {
+                    break;
+                }
+            }
+
+            std::mem::swap(clist, nlist);
+            nlist.clear();
+            if curr_char.is_none() {
+                break;
+            }
+            pos = next_pos;
+        }
+
+        best
+    }
+
+    /// Follows the empty transitions from the states on the stack, and adds the states that need
+    /// a character to move on to the thread list. `Save` states write the current position into
+    /// `scratch` on the way.
+    ///
+    /// * `list`: the thread list at `pos`.
+    /// * `stack`: the states to explore, the one to explore first on top.
+    /// * `scratch`: the capture slots of the thread being followed.
+    /// * `haystack`: the whole input string.
+    /// * `pos`: the current byte offset.
+    /// * `best`: the best match so far, updated if the end state is reached.
+    /// * Return: whether the end state was reached.
+    fn add_threads(
+        &self,
+        list: &mut ThreadList,
+        stack: &mut Vec<Frame>,
+        scratch: &mut [Option<usize>],
+        haystack: &str,
+        pos: usize,
+        best: &mut Option<Vec<Option<usize>>>,
+    ) -> bool {
+        let mut matched = false;
+        while let Some(frame) = stack.pop() {
+            let state_ref = match frame {
+                Frame::Explore(state_ref) => state_ref,
+                Frame::Restore(slot, value) => {
+                    scratch[slot] = value;
+                    continue;
+                }
+            };
+            let state = &self.nfa.states[state_ref];
+            let taken = &mut list.slots[state_ref * self.slot_count..][..self.slot_count];
+            let revisit = !list.visited.insert(state_ref);
+            // with the POSIX rule, a thread may replace the one already there, and then goes on
+            // from the state again.
+            if self.match_kind == MatchKind::LeftmostLongest {
+                if revisit && !posix_prefers(scratch, taken, self.repeated_groups) {
+                    continue;
+                }
+                taken.copy_from_slice(scratch);
+            } else if revisit {
+                continue;
+            }
+
+            let passes = match state.token.token_type {
+                TokenType::Empty => true,
+                TokenType::Save(slot) => {
+                    stack.push(Frame::Restore(slot, scratch[slot]));
+                    scratch[slot] = Some(pos);
+                    true
+                }
+                TokenType::Hat => pos == 0,
+                TokenType::Dollar => pos == haystack.len(),
+                TokenType::LineStart { crlf: false } => {
+                    pos == 0 || haystack.as_bytes()[pos - 1] == b'\n'
+                }
+                TokenType::LineEnd { crlf: false } => {
+                    pos == haystack.len() || haystack.as_bytes()[pos] == b'\n'
+                }
+                TokenType::Assert(idx) => {
+                    self.lookarounds
+                        .holds(idx, haystack, pos, &mut list.look_cache)
+                }
+                // the other anchors look further around.
+                ref anchor if !anchor.consumes() => {
+                    let needs = Assertions::of_anchor(anchor).unwrap();
+                    Assertions::at_offset(haystack, pos).contains(needs)
+                }
+                // everything else consumes a character. A thread already there only had its slots
+                // replaced.
+                _ => {
+                    if !revisit {
+                        list.threads.push(state_ref);
+                        taken.copy_from_slice(scratch);
+                    }
+                    false
+                }
+            };
+            if !passes {
+                continue;
+            }
+            if state_ref == self.nfa.end {
+                self.record_match(best, scratch, pos);
+                matched = true;
+                if self.match_kind == MatchKind::LeftmostFirst {
+                    // the states left to explore have a lower priority. Only the slots they
+                    // changed need undoing.
+                    for frame in stack.drain(..).rev() {
+                        if let Frame::Restore(slot, value) = frame {
+                            scratch[slot] = value;
+                        }
+                    }
+                    break;
+                }
+                continue;
+            }
+            stack.extend(state.edges.iter().rev().map(|&next| Frame::Explore(next)));
+        }
+        matched
+    }
+
+    /// Replaces the best match so far with the one found, if the latter starts earlier. Otherwise,
+    /// with `MatchKind::LeftmostLongest`, if it starts at the same position but is longer, or as
+    /// long with capture groups the POSIX rule prefers, and with `MatchKind::LeftmostFirst`,
+    /// always: the threads that may still match have a higher priority than the one that found the
+    /// best match so far.
+    ///
+    /// * `best`: the best match so far.
+    /// * `slots`: the capture slots of the thread that reached the end state.
+    /// * `end`: the byte offset the match ends at.
+    fn record_match(
+        &self,
+        best: &mut Option<Vec<Option<usize>>>,
+        slots: &[Option<usize>],
+        end: usize,
+    ) {
+        let better = match best {
+            None => true,
+            Some(best) => match self.match_kind {
+                MatchKind::LeftmostLongest => {
+                    slots[0] < best[0]
+                        || (slots[0] == best[0] && Some(end) > best[1])
+                        || (slots[0] == best[0]
+                            && Some(end) == best[1]
+                            && posix_prefers(&slots[2..], &best[2..], &self.repeated_groups[1..]))
+                }
+                MatchKind::LeftmostFirst => slots[0] <= best[0],
+            },
+        };
+        if better {
+            let mut found = slots.to_vec();
+            found[1] = Some(end);
+            *best = Some(found);
+        }
+    }
+}
+
+/// Returns whether the POSIX rule prefers the capture slots passed first over the second ones: the
+/// first group that differs starts earlier, or starts at the same position and ends later.
+///
+/// POSIX also wants each iteration of a repeated group to be the longest in turn, which the last
+/// one alone doesn't tell, and it doesn't say whether a group that is unset is better than one
+/// that is set. So where the first group that differs is repeated, or unset on either side, the
+/// rule doesn't prefer either, and the thread with the highest priority keeps its slots, as with
+/// `MatchKind::LeftmostFirst`.
+///
+/// * `slots`:
+/// * `other`:
+/// * `repeated_groups`: whether each group of the slots is repeated.
+fn posix_prefers(
+    slots: &[Option<usize>],
+    other: &[Option<usize>],
+    repeated_groups: &[bool],
+) -> bool {
+    let groups = slots.chunks(2).zip(other.chunks(2)).zip(repeated_groups);
+    for ((group, other), &repeated) in groups {
+        if group == other {
+            continue;
+        }
+        if repeated || group[0].is_none() || other[0].is_none() {
+            return false;
+        }
+        return (group[0], other[1]) < (other[0], group[1]);
+    }
+    false
+}
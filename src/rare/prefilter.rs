@@ -0,0 +1,52 @@
+use crate::parser::literals::Literals;
+
+/// Skips over the parts of a string that can't hold a match, using the literals every match of
+/// the pattern contains, before the automata get to run.
+///
+/// The substring searches go through `str::find`, which is the Two-Way algorithm: linear, and no
+/// allocation, whatever the literal.
+///
+/// * `prefix`: every match starts with it. Empty if unknown.
+/// * `required`: every match contains it, checked once before searching. `None` if unknown, or if
+///   `prefix` already covers it.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Prefilter {
+    prefix: String,
+    required: Option<String>,
+}
+
+impl Prefilter {
+    /// Constructs a prefilter from the literals of a pattern.
+    ///
+    /// * `literals`:
+    pub fn new(literals: Literals) -> Self {
+        // a required literal no longer than the prefix is most likely the prefix itself.
+        let required = Some(literals.inner).filter(|inner| inner.len() > literals.prefix.len());
+        Self {
+            prefix: literals.prefix,
+            required,
+        }
+    }
+
+    /// Returns where the first match may start, at or after the byte offset passed in, or `None` if
+    /// there can't be any match from there.
+    ///
+    /// The required literal is only checked here if asked to, since it's a search through the rest
+    /// of the string: once per string is enough.
+    ///
+    /// * `string`:
+    /// * `from`: a byte offset on a char boundary.
+    /// * `check_required`: whether to also check that the required literal is there.
+    pub fn next_start(&self, string: &str, from: usize, check_required: bool) -> Option<usize> {
+        let start = match self.prefix.is_empty() {
+            true => from,
+            false => from + string[from..].find(&self.prefix)?,
+        };
+        match &self.required {
+            Some(required) if check_required && !string[start..].contains(required.as_str()) => {
+                None
+            }
+            _ => Some(start),
+        }
+    }
+}
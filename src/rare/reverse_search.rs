@@ -0,0 +1,177 @@
+use std::ops::ControlFlow;
+
+use crate::parser::closure::{self, Assertions, EpsilonClosures, Target};
+use crate::parser::nfa::Nfa;
+
+use super::lookaround::{LookCache, Lookarounds};
+use super::sparse_set::SparseSet;
+
+/// Finds the longest match starting at every position of a string at once, by running the reverse
+/// NFA over it from its end, so that `RARE::match_all` reads the string a bounded number of times
+/// however many matches there are.
+///
+/// Restarting the forward simulation at every position costs, for each start, as many characters
+/// as the match could go on for: "a|a[^x]*b" over "aaa...a" reads the rest of the string from
+/// every a. Reading backwards instead, a match ending at `end` is a path of the reverse NFA from
+/// `end` down to its start, and paths from every end are followed at once, the same way the
+/// forward simulation follows paths from every start. Two paths at the same state and position go
+/// on the same way from there, so only the one from the furthest end needs keeping: where the
+/// reverse NFA reaches its end, the path kept is the longest match starting there.
+///
+/// This is the first of the two passes of `RARE::match_all`. The second one goes forwards through
+/// the matches found, and keeps the leftmost ones that don't overlap.
+///
+/// * `nfa`: the reverse of the NFA of the expression.
+/// * `closures`: the epsilon closures of `nfa`, `None` if they're too big.
+pub(crate) struct ReverseSearch {
+    nfa: Nfa,
+    closures: Option<EpsilonClosures>,
+}
+
+/// The state lists of the reverse pass, kept in the `Cache`.
+///
+/// * `curr_states`: the reverse NFA states entered at the current position.
+/// * `next_states`: the states entered by consuming the character before the current position.
+/// * `curr_ends`: where the furthest path to each state of `curr_states` started from, indexed by
+///   state.
+/// * `next_ends`: the same for `next_states`.
+/// * `visited`: the states reached at the current position, when walking the empty edges. See
+///   `closure::walk`.
+/// * `walk_stack`: the stack of `closure::walk`.
+/// * `look_cache`: the memory of the lookarounds.
+#[derive(Clone, Debug)]
+pub(crate) struct ReverseStates {
+    curr_states: SparseSet,
+    next_states: SparseSet,
+    curr_ends: Vec<usize>,
+    next_ends: Vec<usize>,
+    visited: SparseSet,
+    walk_stack: Vec<usize>,
+    look_cache: LookCache,
+}
+
+impl ReverseStates {
+    /// Constructs empty lists for a reverse NFA with the number of states passed in.
+    ///
+    /// * `state_count`:
+    /// * `lookarounds`: the lookarounds the NFA refers to.
+    pub fn new(state_count: usize, lookarounds: &Lookarounds) -> Self {
+        Self {
+            curr_states: SparseSet::new(state_count),
+            next_states: SparseSet::new(state_count),
+            curr_ends: vec![0; state_count],
+            next_ends: vec![0; state_count],
+            visited: SparseSet::new(state_count),
+            walk_stack: Vec::new(),
+            look_cache: LookCache::new(lookarounds),
+        }
+    }
+}
+
+impl ReverseSearch {
+    /// Compiles the reverse of the NFA passed in.
+    ///
+    /// * `nfa`:
+    pub fn new(nfa: &Nfa) -> Self {
+        let nfa = nfa.reverse();
+        let closures = EpsilonClosures::new(&nfa);
+        Self { nfa, closures }
+    }
+
+    /// Returns the number of states of the reverse NFA, to size `ReverseStates` with.
+    pub fn state_count(&self) -> usize {
+        self.nfa.states.len()
+    }
+
+    /// Finds the longest match starting at each position of the string, from the end of the string
+    /// down to the byte offset passed in.
+    ///
+    /// * `states`: reused between calls, to avoid reallocation.
+    /// * `lookarounds`: the lookarounds the NFA refers to.
+    /// * `string`:
+    /// * `from`: a byte offset on a char boundary. No match starting before it is looked for.
+    /// * `matches`: where the start and end of each match found are appended, by decreasing start.
+    pub fn longest_matches(
+        &self,
+        states: &mut ReverseStates,
+        lookarounds: &Lookarounds,
+        string: &str,
+        from: usize,
+        matches: &mut Vec<(usize, usize)>,
+    ) {
+        let ReverseStates {
+            curr_states,
+            next_states,
+            curr_ends,
+            next_ends,
+            visited,
+            walk_stack,
+            look_cache,
+        } = states;
+        curr_states.clear();
+        look_cache.clear();
+        let mut pos = string.len();
+        let mut chars = string[from..].chars();
+        let mut curr_char = None;
+        let mut next_char = None;
+        loop {
+            let prev_char = chars.next_back();
+            // a match may end at any position.
+            if curr_states.insert(0) {
+                curr_ends[0] = pos;
+            }
+
+            let held = Assertions::at(
+                prev_char.or_else(|| string[..pos].chars().next_back()),
+                curr_char,
+            )
+            .with_next(curr_char, next_char);
+            let mut longest = None;
+            next_states.clear();
+            visited.clear();
+            for &state_ref in curr_states.as_slice() {
+                let end = curr_ends[state_ref];
+                let mut visit = |target| {
+                    match target {
+                        Target::Match => longest = longest.max(Some(end)),
+                        Target::Consume(next_ref) => {
+                            let transition = &self.nfa.states[next_ref].token.token_type;
+                            if prev_char.is_some_and(|c| transition.accepts(c))
+                                && (next_states.insert(next_ref) || next_ends[next_ref] < end)
+                            {
+                                next_ends[next_ref] = end;
+                            }
+                        }
+                    }
+                    ControlFlow::Continue(())
+                };
+                if let Some(closures) = self.closures.as_ref().filter(|_| lookarounds.is_empty()) {
+                    for entry in closures.of(state_ref) {
+                        if held.contains(entry.needs) {
+                            let _ = visit(entry.target);
+                        }
+                    }
+                } else {
+                    // the states are listed by decreasing end, state 0 last, so the first walk to
+                    // reach a state is from the furthest end, and the later ones can skip it.
+                    let holds = |idx| lookarounds.holds(idx, string, pos, look_cache);
+                    let _ = closure::walk(
+                        &self.nfa, state_ref, held, visited, walk_stack, holds, visit,
+                    );
+                }
+            }
+            if let Some(end) = longest {
+                matches.push((pos, end));
+            }
+
+            let Some(c) = prev_char else {
+                return;
+            };
+            pos -= c.len_utf8();
+            next_char = curr_char;
+            curr_char = Some(c);
+            std::mem::swap(curr_states, next_states);
+            std::mem::swap(curr_ends, next_ends);
+        }
+    }
+}
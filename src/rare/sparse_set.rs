@@ -0,0 +1,76 @@
+/// A set of NFA state references, all below a fixed capacity, with O(1) insertion, membership
+/// test and clearing. Iterating over it gives the elements in insertion order, which is the
+/// priority order of the states being tracked.
+///
+/// This is the sparse set of Briggs and Torczon: `dense` lists the elements in insertion order,
+/// and `sparse` maps each element to its index in `dense`. An element is in the set only if both
+/// agree, so neither needs to be reset when clearing, and stale entries in `sparse` are harmless.
+///
+/// * `dense`: the elements, in insertion order.
+/// * `sparse`: for each possible element, its index in `dense`, if it's there.
+#[derive(Clone, Debug)]
+pub(crate) struct SparseSet {
+    dense: Vec<usize>,
+    sparse: Vec<usize>,
+}
+
+impl SparseSet {
+    /// Constructs an empty set that can hold the elements from 0 up to (excluding) `capacity`.
+    ///
+    /// * `capacity`:
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            dense: Vec::with_capacity(capacity),
+            sparse: vec![0; capacity],
+        }
+    }
+
+    /// Returns the number of different elements the set can hold.
+    pub fn capacity(&self) -> usize {
+        self.sparse.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.dense.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dense.is_empty()
+    }
+
+    #[inline]
+    /// Returns whether the element passed in is in the set.
+    ///
+    /// * `value`: must be below the capacity.
+    pub fn contains(&self, value: usize) -> bool {
+        let idx = self.sparse[value];
+        idx < self.dense.len() && self.dense[idx] == value
+    }
+
+    #[inline]
+    /// Adds an element at the end of the set, unless it's already there.
+    ///
+    /// * `value`: must be below the capacity.
+    /// * Return: whether the element was added.
+    pub fn insert(&mut self, value: usize) -> bool {
+        if self.contains(value) {
+            return false;
+        }
+        self.sparse[value] = self.dense.len();
+        self.dense.push(value);
+        true
+    }
+
+    #[inline]
+    /// Removes every element, in constant time.
+    pub fn clear(&mut self) {
+        self.dense.clear();
+    }
+
+    /// Returns the elements, in insertion order.
+    pub fn as_slice(&self) -> &[usize] {
+        &self.dense
+    }
+}
+
+mod test;
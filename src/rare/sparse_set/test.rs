@@ -0,0 +1,28 @@
+#[cfg(test)]
+use super::*;
+
+#[test]
+fn sparse_set_test() {
+    let mut set = SparseSet::new(10);
+    assert_eq!(set.capacity(), 10);
+    assert!(set.is_empty());
+    assert!(set.insert(7));
+    assert!(set.insert(2));
+    assert!(set.insert(9));
+    assert!(!set.insert(2));
+    assert_eq!(set.len(), 3);
+    // insertion order, not sorted order.
+    assert_eq!(set.as_slice(), &[7, 2, 9]);
+    assert!(set.contains(9));
+    assert!(!set.contains(0));
+
+    set.clear();
+    assert!(set.is_empty());
+    // the stale indices left behind don't count.
+    for value in 0..10 {
+        assert!(!set.contains(value));
+    }
+    assert!(set.insert(2));
+    assert!(set.insert(0));
+    assert_eq!(set.as_slice(), &[2, 0]);
+}
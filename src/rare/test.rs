@@ -1,5 +1,8 @@
 #[cfg(test)]
-use crate::parser::Parser;
+use crate::{
+    parser::Parser,
+    rare::{MatchKind, RareBuilder, RARE},
+};
 
 #[test]
 fn test_regex_matcher_simple() {
@@ -92,3 +95,1206 @@ fn matcher_sus_test() {
         panic!();
     }
 }
+
+#[test]
+fn matcher_char_class_test() {
+    let regex = RARE::new("[A-Za-z_][A-Za-z0-9_]*=[^;]+;").unwrap();
+    assert!(regex.is_match("let some_var1=12;"));
+    assert!(regex.is_match("_=x;"));
+    assert!(!regex.is_match("1=x;"));
+    assert!(!regex.is_match("abc=;"));
+
+    let regex = RARE::new("\"[^\\\\\"]*\"").unwrap();
+    assert!(regex.is_match("say \"hello\""));
+    assert!(!regex.is_match("say \"hel\\lo\""));
+
+    let regex = RARE::new("x[\\]-]y").unwrap();
+    assert!(regex.is_match("x]y"));
+    assert!(regex.is_match("x-y"));
+    assert!(!regex.is_match("x\\y"));
+}
+
+#[test]
+fn matcher_repeat_test() {
+    let regex = RARE::new("[0-9]{4}-[0-9]{2}").unwrap();
+    assert!(regex.is_match("date: 2024-10-18"));
+    assert!(!regex.is_match("date: 224-10-18"));
+
+    let regex = RARE::new("^x(ab|cd){2,3}y").unwrap();
+    assert!(!regex.is_match("xaby"));
+    assert!(regex.is_match("xabcdy"));
+    assert!(regex.is_match("xcdabcdy"));
+    assert!(!regex.is_match("xababcdcdy"));
+
+    let regex = RARE::new("^a{2,}b").unwrap();
+    assert!(!regex.is_match("ab"));
+    assert!(regex.is_match("aab"));
+    assert!(regex.is_match("aaaaaab"));
+
+    let regex = RARE::new("^-a{,2}-").unwrap();
+    assert!(regex.is_match("--"));
+    assert!(regex.is_match("-aa-"));
+    assert!(!regex.is_match("-aaa-"));
+
+    // fusing the empty states of 2 stars together used to let (a|b)* repeat after c*.
+    let regex = RARE::new("^(a|b)*(c|d)*x").unwrap();
+    assert!(regex.is_match("abcdx"));
+    assert!(!regex.is_match("cax"));
+}
+
+#[test]
+fn repeat_size_limit_test() {
+    assert!(RARE::new("(a{1000}){1000}").is_err());
+    assert!(RARE::new("a{9876543210}").is_err());
+    assert!(RareBuilder::new("a{100}").size_limit(50).build().is_err());
+    assert!(RareBuilder::new("a{100}").size_limit(500).build().is_ok());
+}
+
+#[test]
+fn big_closures_test() {
+    // the NFAs are small enough, but each optional part reaches all the later ones without
+    // consuming, so the epsilon closures grow quadratically.
+    let a_optional = "a?".repeat(300) + "b";
+    let cases = [
+        (a_optional.as_str(), "xaaab", vec![(1, 5)]),
+        ("(?:a?){300}b", "xaaab", vec![(1, 5)]),
+        ("(?:a*){400}b", "xaaab", vec![(1, 5)]),
+        ("(?:\\b?x?){200}b", "yxxb", vec![(1, 4)]),
+        ("(?:a?){10000}b", "aaxaab", vec![(3, 6)]),
+        ("(?:a?){10000}b", "aaa", vec![]),
+        ("(?=(?:a?){10000}b)a", "xab", vec![(1, 2)]),
+    ];
+    for (pattern, string, expected) in cases {
+        let regex = RARE::new(pattern).unwrap();
+        assert_eq!(regex.is_match(string), !expected.is_empty(), "{pattern:?}");
+        assert_eq!(
+            regex
+                .match_all(string)
+                .unwrap_or_default()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            expected,
+            "{pattern:?}"
+        );
+        assert_eq!(
+            regex.captures(string).map(|caps| caps.get(0)),
+            expected.first().map(|&span| Some(span)),
+            "{pattern:?}"
+        );
+    }
+    // past the cap, the empty edges are walked at every position instead.
+    let regex = RARE::new("(?:a?){10000}b").unwrap();
+    assert!(regex.closures.is_none());
+    assert!(regex.dfa.is_none());
+}
+
+#[test]
+fn captures_test() {
+    let regex = RARE::new("([0-9]{4})-([0-9]{2})(-([0-9]{2}))?").unwrap();
+    assert_eq!(regex.captures_len(), 4);
+    let caps = regex
+        .captures("released on 2024-10, patched 2024-11-02")
+        .unwrap();
+    assert_eq!(&caps[0], "2024-10");
+    assert_eq!(&caps[1], "2024");
+    assert_eq!(&caps[2], "10");
+    assert_eq!(caps.get(3), None);
+    assert_eq!(caps.get_str(4), None);
+
+    let all: Vec<(String, Option<String>)> = regex
+        .captures_iter("released on 2024-10, patched 2024-11-02")
+        .map(|caps| (caps[0].to_string(), caps.get_str(4).map(String::from)))
+        .collect();
+    assert_eq!(
+        all,
+        [
+            ("2024-10".to_string(), None),
+            ("2024-11-02".to_string(), Some("02".to_string()))
+        ]
+    );
+
+    assert!(regex.captures("no dates here").is_none());
+}
+
+#[test]
+fn captures_leftmost_longest_test() {
+    // the leftmost match wins, even though "c" is found first.
+    let regex = RARE::new("abcd|c").unwrap();
+    assert_eq!(regex.captures("xabcd").unwrap().get(0), Some((1, 5)));
+
+    // then the longest one, with each group in turn starting as early and then ending as late
+    // as it can.
+    let groups = |pattern: &str, string: &str| {
+        let regex = RARE::new(pattern).unwrap();
+        let caps = regex.captures(string).unwrap();
+        (0..=regex.captures_len())
+            .map(|idx| caps.get(idx))
+            .collect::<Vec<_>>()
+    };
+    for pattern in [
+        "(a|ab)(c|bcd)(d*)",
+        "(ab|a)(c|bcd)(d*)",
+        "(a|ab)(bcd|c)(d*)",
+        "(ab|a)(bcd|c)(d*)",
+    ] {
+        assert_eq!(
+            groups(pattern, "abcd"),
+            [Some((0, 4)), Some((0, 2)), Some((2, 3)), Some((3, 4))],
+            "{pattern}"
+        );
+    }
+    assert_eq!(
+        groups("(a*)(b|abc)(c*)", "abc"),
+        [Some((0, 3)), Some((0, 1)), Some((1, 2)), Some((2, 3))]
+    );
+    assert_eq!(groups("(a+|b){0,}", "ab"), [Some((0, 2)), Some((1, 2))]);
+    assert_eq!(groups("(a+|b){1,}", "ab"), [Some((0, 2)), Some((1, 2))]);
+    assert_eq!(groups("(a+|b){0,1}", "ab"), [Some((0, 1)), Some((0, 1))]);
+    // a group left out stays unset, rather than being matched empty.
+    assert_eq!(
+        groups("(a|(b)?)c", "ac"),
+        [Some((0, 2)), Some((0, 1)), None]
+    );
+    // and a repeated group takes the iterations of the greedy path, here the longest first one.
+    assert_eq!(
+        groups("(a*){2}(x)", "ax"),
+        [Some((0, 2)), Some((1, 1)), Some((1, 2))]
+    );
+
+    let regex = RARE::new("(ab|c)+").unwrap();
+    let caps = regex.captures("xabcabz").unwrap();
+    assert_eq!(caps.get(0), Some((1, 6)));
+    assert_eq!(caps.get(1), Some((4, 6)));
+
+    // a quantifier at the very end still loops.
+    let regex = RARE::new("(?:[ab]A?){2,}").unwrap();
+    assert_eq!(regex.captures("abbA").unwrap().get(0), Some((0, 4)));
+    let regex = RARE::new("x(?:ab)*").unwrap();
+    assert_eq!(regex.captures("xabab").unwrap().get(0), Some((0, 5)));
+
+    // empty groups and empty branches.
+    let regex = RARE::new("x()(|y)z").unwrap();
+    let caps = regex.captures("xz").unwrap();
+    assert_eq!(caps.get(1), Some((1, 1)));
+    assert_eq!(caps.get(2), Some((1, 1)));
+    assert_eq!(regex.captures("xyz").unwrap().get(2), Some((1, 2)));
+}
+
+#[test]
+fn captures_iter_empty_match_test() {
+    let regex = RARE::new("a*").unwrap();
+    let spans: Vec<(usize, usize)> = regex
+        .captures_iter("baab")
+        .map(|caps| caps.get(0).unwrap())
+        .collect();
+    assert_eq!(spans, [(0, 0), (1, 3), (4, 4)]);
+}
+
+#[test]
+fn named_captures_test() {
+    let regex = RARE::new("(?P<year>[0-9]{4})-(?<month>[0-9]{2})(-(?<day>[0-9]{2}))?").unwrap();
+    let names: Vec<Option<&str>> = regex.capture_names().collect();
+    assert_eq!(
+        names,
+        [None, Some("year"), Some("month"), None, Some("day")]
+    );
+
+    let caps = regex.captures("due 2024-10").unwrap();
+    assert_eq!(&caps["year"], "2024");
+    assert_eq!(caps.name("month"), Some((9, 11)));
+    assert_eq!(caps.name_str("day"), None);
+    assert_eq!(caps.name("no_such_group"), None);
+    assert_eq!(&caps[2], "10");
+}
+
+#[test]
+fn flags_test() {
+    let regex = RARE::new("(?i)hello").unwrap();
+    assert!(regex.is_match("say HeLLo"));
+    assert_eq!(regex.captures("HELLO").unwrap().get(0), Some((0, 5)));
+
+    // the flag ends with the group it's set in.
+    let regex = RARE::new("((?i)a)b").unwrap();
+    assert!(regex.is_match("Ab"));
+    assert!(!regex.is_match("AB"));
+    let regex = RARE::new("(?i:[a-c]x)y").unwrap();
+    assert!(regex.is_match("BXy"));
+    assert!(!regex.is_match("BXY"));
+    let regex = RARE::new("(?i)a(?-i)b").unwrap();
+    assert!(regex.is_match("Ab"));
+    assert!(!regex.is_match("AB"));
+
+    // the dot skips \n unless s is set.
+    assert!(!RARE::new("a.b").unwrap().is_match("a\nb"));
+    assert!(RARE::new("(?s)a.b").unwrap().is_match("a\nb"));
+
+    let regex = RARE::new("(?m)^b$").unwrap();
+    assert!(regex.is_match("a\nb\nc"));
+    assert_eq!(regex.captures("a\nb\nc").unwrap().get(0), Some((2, 3)));
+    assert!(!RARE::new("^b$").unwrap().is_match("a\nb\nc"));
+
+    let regex = RARE::new("(?x) ( [0-9]+ ) - ( [0-9]+ ) # a range\n").unwrap();
+    let caps = regex.captures("pages 12-34").unwrap();
+    assert_eq!(&caps[1], "12");
+    assert_eq!(&caps[2], "34");
+}
+
+#[test]
+fn case_insensitive_test() {
+    let regex = RareBuilder::new("grüße [a-z]+")
+        .case_insensitive(true)
+        .build()
+        .unwrap();
+    assert!(regex.is_match("GRÜẞE aus Köln"));
+    assert_eq!(
+        regex.captures("say GrÜßE Dir").unwrap().get(0),
+        Some((4, 15))
+    );
+    // simple case folding maps one character to one character.
+    assert!(!regex.is_match("GRÜSSE DIR"));
+
+    let regex = RareBuilder::new("a(?-i)b")
+        .case_insensitive(true)
+        .build()
+        .unwrap();
+    assert!(regex.is_match("Ab"));
+    assert!(!regex.is_match("AB"));
+    assert!(!RareBuilder::new("abc").build().unwrap().is_match("ABC"));
+}
+
+#[test]
+fn utf8_match_all_test() {
+    let spans = |pattern: &str, string: &str| -> Vec<(usize, usize)> {
+        let regex = RARE::new(pattern).unwrap();
+        let spans: Vec<(usize, usize)> = regex.match_all(string).into_iter().flatten().collect();
+        // the same matches as the Pike VM, and all of them can slice the string.
+        let caps_spans: Vec<(usize, usize)> = regex
+            .captures_iter(string)
+            .map(|caps| caps.get(0).unwrap())
+            .collect();
+        assert_eq!(spans, caps_spans, "{pattern:?} over {string:?}");
+        for &(start, end) in spans.iter() {
+            assert!(string.is_char_boundary(start) && string.is_char_boundary(end));
+        }
+        spans
+    };
+
+    // multi-byte text.
+    assert_eq!(spans("ö+", "schön, schööön"), [(3, 5), (11, 17)]);
+    assert_eq!(spans("[а-я]+", "abc привет xyz"), [(4, 16)]);
+    assert_eq!(spans("日本.$", "日本語"), [(0, 9)]);
+    // emoji, which take 4 bytes each.
+    assert_eq!(spans("👍+", "a👍👍b👍"), [(1, 9), (10, 14)]);
+    assert_eq!(spans(".", "🦀é"), [(0, 4), (4, 6)]);
+    // a combining mark is a character of its own: "e\u{301}" is 2 characters, 3 bytes.
+    assert_eq!(spans("e", "cafe\u{301}"), [(3, 4)]);
+    assert_eq!(spans("e.", "cafe\u{301}!"), [(3, 6)]);
+    assert_eq!(spans("e.$", "cafe\u{301}"), [(3, 6)]);
+    // empty matches fall between characters, never inside one.
+    assert_eq!(spans("x*", "ä👍"), [(0, 0), (2, 2), (6, 6)]);
+
+    let regex = RARE::new("ö$").unwrap();
+    assert!(regex.is_match("schö"));
+    assert!(!regex.is_match("schön"));
+    let regex = RARE::new("(?m)^ö").unwrap();
+    assert_eq!(
+        regex
+            .match_all("a\nöb\nö")
+            .unwrap()
+            .into_iter()
+            .collect::<Vec<_>>(),
+        [(2, 4), (6, 8)]
+    );
+}
+
+#[test]
+fn cache_reuse_test() {
+    let digits = RARE::new("[0-9]+").unwrap();
+    let words = RARE::new("(?i)[a-z]+( [a-z]+)*").unwrap();
+    let mut cache = digits.create_cache();
+    let mut spans = Vec::new();
+    for line in ["abc 123", "", "42", "no digits"] {
+        spans.clear();
+        digits.write_match_all_with(&mut cache, line, &mut spans);
+        assert_eq!(spans.is_empty(), !digits.is_match_with(&mut cache, line));
+        assert_eq!(
+            digits
+                .match_all(line)
+                .map(Vec::from_iter)
+                .unwrap_or_default(),
+            spans
+        );
+    }
+    // a cache from another matcher is resized on use.
+    assert!(words.is_match_with(&mut cache, "Two Words"));
+    spans.clear();
+    words.write_match_all_with(&mut cache, "1 Two Words", &mut spans);
+    assert_eq!(spans, [(2, 11)]);
+    assert!(digits.is_match_with(&mut cache, "7"));
+}
+
+#[test]
+fn lazy_dfa_agrees_with_nfa_test() {
+    // the patterns and strings of the tests above.
+    let cases: &[(&str, &[&str])] = &[
+        ("ab+|c+", &["abbbbbb", "bccccccccc", "adbbbbb", "abc", ""]),
+        (
+            "(.c(xx|pp))$",
+            &[".hcxx.cpp", ".hcxx", ".cpp", "cxx and c.cxx"],
+        ),
+        (
+            "a?a?a?a?a?a?a?a?a?a?aaaaaaaaaa",
+            &["aaaaaaaaaa", "aaaaaaaaa", "aaaaaaaaabaaaaaaaaaa"],
+        ),
+        ("^(Never)+", &["Neverrrrrrr", "NeverNever", " Never"]),
+        (
+            "[a-zA-Z_][a-zA-Z0-9_]*=[0-9]+;",
+            &["let some_var1=12;", "1=x;", "abc=;"],
+        ),
+        ("\"[^\"\\\\]*\"", &["say \"hello\"", "say \"hel\\lo\""]),
+        (
+            "(ab|cd){2,3}",
+            &["xaby", "xabcdy", "xcdabcdy", "xababcdcdy"],
+        ),
+        ("(?m)^b$", &["a\nb\nc", "a\nbb\nc", "b", "\nb\n"]),
+        ("(?i)grüße", &["GRÜẞE", "grüsse"]),
+        ("ö$|^👍", &["schö", "schön", "👍a", "a👍"]),
+        (
+            "\\bint\\b",
+            &["int x", "print", "int", "x=int;", "ınt int_"],
+        ),
+        ("\\Bé\\b|(?a)\\bé", &["café", "é", "cafés", "aé"]),
+        (
+            "(?mR)^b$",
+            &["a\r\nb\r\nc", "b\r", "\rb\n", "a\rb", "a\r\nbc"],
+        ),
+        ("a(^|,)b|x$y", &["ab", "a,b", "xy"]),
+        ("a\\Z", &["a\n", "a\n\n", "a", "ab"]),
+    ];
+    for &(pattern, strings) in cases {
+        let nfa_only = RareBuilder::new(pattern)
+            .lazy_dfa_cache_size(0)
+            .build()
+            .unwrap();
+        let dfa = RARE::new(pattern).unwrap();
+        // a cache this small fills up at every new state, so the NFA takes over midway.
+        let thrashing = RareBuilder::new(pattern)
+            .lazy_dfa_cache_size(1)
+            .build()
+            .unwrap();
+        let mut cache = dfa.create_cache();
+        for &string in strings {
+            let expected = nfa_only.is_match(string);
+            assert_eq!(expected, nfa_only.captures(string).is_some());
+            assert_eq!(
+                dfa.is_match(string),
+                expected,
+                "{pattern:?} over {string:?}"
+            );
+            assert_eq!(dfa.is_match_with(&mut cache, string), expected);
+            assert_eq!(
+                thrashing.is_match(string),
+                expected,
+                "{pattern:?} over {string:?}"
+            );
+        }
+    }
+}
+
+#[test]
+fn prefilter_test() {
+    let cases: &[(&str, &[&str])] = &[
+        (
+            "ERROR: [a-z]+",
+            &["ERROR: disk", "WARN: disk", "xERROR: ERROR: a", "ERROR:"],
+        ),
+        ("^ERROR", &["ERROR", "xERROR", "ERROR ERROR"]),
+        (
+            "(?m)^ERROR$",
+            &["a\nERROR\nb", "aERROR\n", "ERRORERROR\nERROR"],
+        ),
+        (
+            "[a-z_]+\\.cpp",
+            &["main.cpp and util.cpp", ".cpp", "main.cp"],
+        ),
+        ("\\.(cpp|cxx)$", &["a.cxx", "a.cpp.h", "a.c"]),
+        ("grüße|grüß", &["Schöne grüße", "grüßgrüß", "gruss"]),
+        ("x?y", &["", "xxy", "z"]),
+        ("\\bint\\b", &["print int", "int_x", "x(int)"]),
+    ];
+    for &(pattern, strings) in cases {
+        let unfiltered = RareBuilder::new(pattern).prefilter(false).build().unwrap();
+        let filtered = RARE::new(pattern).unwrap();
+        let full_dfa = RareBuilder::new(pattern).full_dfa(true).build().unwrap();
+        for &string in strings {
+            let expected = unfiltered.is_match(string);
+            assert_eq!(
+                filtered.is_match(string),
+                expected,
+                "{pattern:?} over {string:?}"
+            );
+            assert_eq!(
+                full_dfa.is_match(string),
+                expected,
+                "{pattern:?} over {string:?}"
+            );
+            assert_eq!(filtered.match_all(string), unfiltered.match_all(string));
+        }
+    }
+}
+
+#[test]
+fn literal_set_test() {
+    let keywords: Vec<String> = (0..2000).map(|idx| format!("kw{}", idx * 7)).collect();
+    let pattern = keywords.join("|");
+    let rare = RARE::new(&pattern).unwrap();
+    assert!(rare.literal_set.is_some());
+    let string = "kw7 kw70 kw700 kw7000 kw14x kw";
+    assert!(rare.is_match(string));
+    assert!(!rare.is_match("kw1 kw"));
+    // the longest keyword at each position: kw7000 is there, kw70000 isn't.
+    let expected = RareBuilder::new(&pattern)
+        .prefilter(false)
+        .build()
+        .unwrap()
+        .match_all(string);
+    assert_eq!(rare.match_all(string), expected);
+    assert_eq!(
+        rare.match_all(string)
+            .unwrap()
+            .into_iter()
+            .collect::<Vec<_>>(),
+        [(0, 3), (4, 8), (9, 14), (15, 21), (22, 26)]
+    );
+
+    // alternatives through groups and optional parts are literals too.
+    assert!(RARE::new("colou?r|(gr(a|e)y)")
+        .unwrap()
+        .literal_set
+        .is_some());
+    // but not with anchors, classes, or an empty alternative.
+    for pattern in [
+        "^foo|bar",
+        "(?:^foo){1}|bar",
+        "\\b{2}foo|bar",
+        "foo|ba[rz]",
+        "foo|bar|",
+        "foo",
+    ] {
+        assert!(
+            RARE::new(pattern).unwrap().literal_set.is_none(),
+            "{pattern}"
+        );
+    }
+}
+
+#[test]
+fn literal_set_anchor_test() {
+    // a repeated anchor or lookaround is still one, not the empty literal.
+    let cases: &[(&str, &[&str])] = &[
+        ("(?:^a){1}|b", &["ya", "a", "yb"]),
+        ("\\b{2}ab?", &["éa", "a", "xab b"]),
+        ("(?:$){2}a|b", &["ab", "b"]),
+        ("(?:(?<=x)a){1}|b", &["ya", "xa", "yb"]),
+        ("(?=a){2}a|b", &["a", "b"]),
+    ];
+    for &(pattern, strings) in cases {
+        let filtered = RareBuilder::new(pattern).prefilter(true).build().unwrap();
+        let unfiltered = RareBuilder::new(pattern).prefilter(false).build().unwrap();
+        for &string in strings {
+            assert_eq!(
+                filtered.is_match(string),
+                unfiltered.is_match(string),
+                "{pattern:?} over {string:?}"
+            );
+            assert_eq!(
+                filtered.match_all(string),
+                unfiltered.match_all(string),
+                "{pattern:?} over {string:?}"
+            );
+            assert_eq!(
+                filtered.captures(string).map(|caps| caps.get(0)),
+                unfiltered.captures(string).map(|caps| caps.get(0)),
+                "{pattern:?} over {string:?}"
+            );
+        }
+    }
+}
+
+#[test]
+fn backwards_matches_test() {
+    let cases: &[(&str, &[&str])] = &[
+        ("abcd|c", &["xabcd", "abc", "cabcd"]),
+        ("a|a[^x]*b", &["aaaa", "aaab", "aaxab"]),
+        ("(a|ab)(c|bcd)(d*)", &["abcd", "abcdabcd"]),
+        ("a*", &["baa", "", "aba"]),
+        ("x()(|y)z", &["xz xyz", "xyyz"]),
+        ("(?:[ab]A?){2,}", &["abbA", "aAbAb"]),
+        ("^(Never)+", &["NeverNever", " Never"]),
+        ("(.c(xx|pp))$", &[".hcxx.cpp", ".cpp.cxx"]),
+        ("(?m)^b$|^$", &["a\nb\n\nc", "b", "\nb\n"]),
+        ("e.$|ö|^👍+", &["cafe\u{301}", "👍👍ö", "schön"]),
+        ("\\b\\w+\\b|\\B", &["héllo wörld", "  a_b  ", ""]),
+        ("(?mR)^|$", &["a\r\nb\rc\n", "\r\n\r\n"]),
+        ("a\\Z|\\Ab", &["ba\n", "a\n\na\n", "bab"]),
+        ("(?<=a)b|a(?=b)", &["abab", "aab b"]),
+        ("(?:(?!ab).)+|(?:(?=a)|(?<=b))a", &["xxabyy", "aba"]),
+    ];
+    for &(pattern, strings) in cases {
+        let rare = RARE::new(pattern).unwrap();
+        let mut cache = rare.create_cache();
+        for &string in strings {
+            let mut backwards = Vec::new();
+            rare.for_each_match_backwards(&mut cache, string, 0, None, |start, end| {
+                backwards.push((start, end))
+            });
+            let expected: Vec<(usize, usize)> = rare
+                .captures_iter(string)
+                .map(|caps| caps.get(0).unwrap())
+                .collect();
+            assert_eq!(backwards, expected, "{pattern:?} over {string:?}");
+            let forwards = rare
+                .match_all(string)
+                .map(Vec::from_iter)
+                .unwrap_or_default();
+            assert_eq!(forwards, expected, "{pattern:?} over {string:?}");
+        }
+    }
+
+    // every a is a match, but the forward search from each of them reads the rest of the string,
+    // looking for a b.
+    let string = "a".repeat(100_000);
+    let rare = RARE::new("a|a[^x]*b").unwrap();
+    assert_eq!(rare.match_all(&string).unwrap().len(), 100_000);
+    // and so would the Pike VM, if it searched from the end of each match.
+    let rare = RARE::new("(a)|a[^x]*b").unwrap();
+    let mut count = 0;
+    for caps in rare.captures_iter(&string) {
+        assert_eq!(caps.get(0), Some((count, count + 1)));
+        assert_eq!(caps.get(1), Some((count, count + 1)));
+        count += 1;
+    }
+    assert_eq!(count, 100_000);
+}
+
+#[test]
+fn leftmost_first_test() {
+    let cases: &[(&str, &[&str])] = &[
+        ("a|ab", &["abab", "b"]),
+        ("ab|a", &["abab", "aab"]),
+        ("(a|ab)(c|bcd)(d*)", &["abcd", "abcdabcd"]),
+        ("a*|b", &["b", "aab"]),
+        ("samwise|sam", &["samwise", "samwis"]),
+        ("(?:[ab]A?){2,}|a", &["abbA", "aAbAb", "a"]),
+        ("^(Never)+|N", &["NeverNever", " Never"]),
+        ("a[^x]*b|a", &["aaaa", "aaab", "aaxab"]),
+        // paths looping back through anchors to where they started.
+        ("(?:|a)+", &["a"]),
+        (".(((?:\\B|[ab]a{2,2})?|a+))+.{2,3}", &["babbba"]),
+        ("a(((\\B|\\Bbb?)+)*.b?|a??([ab]a*)??.)c", &["abcbccb"]),
+        ("((?:^|.|b)+)", &["abb"]),
+    ];
+    for &(pattern, strings) in cases {
+        let rare = RareBuilder::new(pattern)
+            .match_kind(MatchKind::LeftmostFirst)
+            .build()
+            .unwrap();
+        for &string in strings {
+            let expected: Vec<(usize, usize)> = rare
+                .captures_iter(string)
+                .map(|caps| caps.get(0).unwrap())
+                .collect();
+            let found = rare
+                .match_all(string)
+                .map(Vec::from_iter)
+                .unwrap_or_default();
+            assert_eq!(found, expected, "{pattern:?} over {string:?}");
+        }
+    }
+
+    let rare = RareBuilder::new("samwise|sam")
+        .match_kind(MatchKind::LeftmostFirst)
+        .build()
+        .unwrap();
+    assert_eq!(rare.match_all("samwise").unwrap().front(), Some(&(0, 7)));
+    let rare = RareBuilder::new("sam|samwise")
+        .match_kind(MatchKind::LeftmostFirst)
+        .build()
+        .unwrap();
+    assert_eq!(rare.match_all("samwise").unwrap().front(), Some(&(0, 3)));
+}
+
+/// A xorshift generator, so that the random patterns are the same on every run.
+#[cfg(test)]
+struct XorShift(u64);
+
+#[cfg(test)]
+impl XorShift {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+
+    /// Returns a random pattern over a and b, with empty alternatives, anchors, lookarounds, and
+    /// greedy and lazy loops, which give the most ways to match the empty string.
+    ///
+    /// * `depth`: how many more operators may be nested.
+    fn pattern(&mut self, depth: usize) -> String {
+        const ATOMS: [&str; 10] = [
+            "a", "b", ".", "[ab]", "", "\\b", "\\B", "^", "(?=a)", "(?<!b.)",
+        ];
+        if depth == 0 {
+            return ATOMS[self.below(ATOMS.len())].to_string();
+        }
+        match self.below(11) {
+            0 | 1 => self.pattern(depth - 1) + &self.pattern(depth - 1),
+            2 => format!("{}|{}", self.pattern(depth - 1), self.pattern(depth - 1)),
+            3 => format!("(?:{})*", self.pattern(depth - 1)),
+            4 => format!("(?:{})+", self.pattern(depth - 1)),
+            5 => format!("(?:{})?", self.pattern(depth - 1)),
+            6 => format!("(?:{}){{1,3}}", self.pattern(depth - 1)),
+            7 => format!("(?:{})*?", self.pattern(depth - 1)),
+            8 => format!("(?:{})??", self.pattern(depth - 1)),
+            9 => format!("({})", self.pattern(depth - 1)),
+            _ => ATOMS[self.below(ATOMS.len())].to_string(),
+        }
+    }
+
+    fn string(&mut self) -> String {
+        const CHARS: [char; 3] = ['a', 'b', ' '];
+        let len = self.below(8);
+        (0..len).map(|_| CHARS[self.below(CHARS.len())]).collect()
+    }
+}
+
+#[test]
+fn leftmost_first_agrees_with_captures_test() {
+    // `match_all` runs the NFA simulation, with the epsilon closures computed up front, and
+    // `captures_iter` the Pike VM, which walks the empty edges at every position.
+    let mut rng = XorShift(0x9E37_79B9_7F4A_7C15);
+    for _ in 0..2000 {
+        let pattern = rng.pattern(4);
+        let rare = RareBuilder::new(&pattern)
+            .match_kind(MatchKind::LeftmostFirst)
+            .build()
+            .unwrap();
+        for _ in 0..10 {
+            let string = rng.string();
+            let expected: Vec<(usize, usize)> = rare
+                .captures_iter(&string)
+                .map(|caps| caps.get(0).unwrap())
+                .collect();
+            let found = rare
+                .match_all(&string)
+                .map(Vec::from_iter)
+                .unwrap_or_default();
+            assert_eq!(found, expected, "{pattern:?} over {string:?}");
+        }
+    }
+}
+
+#[test]
+fn lazy_quantifier_test() {
+    let first = |pattern: &str| {
+        RareBuilder::new(pattern)
+            .match_kind(MatchKind::LeftmostFirst)
+            .build()
+            .unwrap()
+    };
+    let spans = |rare: &RARE, string: &str| {
+        rare.match_all(string)
+            .map(Vec::from_iter)
+            .unwrap_or_default()
+    };
+
+    // the shortest text between delimiters.
+    let quoted = first("\".*?\"");
+    assert_eq!(spans(&quoted, "say \"a\" and \"b\""), [(4, 7), (12, 15)]);
+    assert_eq!(spans(&first("\".*\""), "say \"a\" and \"b\""), [(4, 15)]);
+    assert_eq!(spans(&first("a+?"), "aaa"), [(0, 1), (1, 2), (2, 3)]);
+    assert_eq!(spans(&first("a{2,3}?"), "aaaaa"), [(0, 2), (2, 4)]);
+    assert_eq!(spans(&first("a{2,}?"), "aaaaa"), [(0, 2), (2, 4)]);
+    assert_eq!(spans(&first("ba??"), "baa"), [(0, 1)]);
+    assert_eq!(
+        spans(&first("<.+?>|x"), "<a><b>x"),
+        [(0, 3), (3, 6), (6, 7)]
+    );
+    // a lazy quantifier still takes what the rest of the pattern needs.
+    assert_eq!(spans(&first("a*?b"), "aab"), [(0, 3)]);
+    // a lazy loop inside another loop doesn't come back in through the outer one before that
+    // one leaves.
+    for pattern in ["(?:.*?)+b", "(?:x|.*?)+b", "(?:.*?)*b"] {
+        let rare = first(pattern);
+        assert_eq!(spans(&rare, "abb"), [(0, 2), (2, 3)], "{pattern}");
+        let caps: Vec<_> = rare.captures_iter("abb").map(|c| c.get(0)).collect();
+        assert_eq!(caps, [Some((0, 2)), Some((2, 3))], "{pattern}");
+    }
+    // and an empty repetition still leaves the loop.
+    assert_eq!(spans(&first("(?:a??)*"), "ab"), [(0, 0), (1, 1), (2, 2)]);
+
+    let caps = first("(a*?)(a*)").captures("aaa").unwrap();
+    assert_eq!((caps.get(1), caps.get(2)), (Some((0, 0)), Some((0, 3))));
+    let caps = first("(a+?)(a*)").captures("aaa").unwrap();
+    assert_eq!((caps.get(1), caps.get(2)), (Some((0, 1)), Some((1, 3))));
+
+    // the longest match is the same either way. Among the ways to match it, the POSIX rule picks
+    // the groups, so the first one takes as much as it can.
+    let longest = RARE::new("\".*?\"").unwrap();
+    assert_eq!(spans(&longest, "say \"a\" and \"b\""), [(4, 15)]);
+    let caps = RARE::new("(a*?)(a*)").unwrap().captures("aaa").unwrap();
+    assert_eq!((caps.get(1), caps.get(2)), (Some((0, 3)), Some((3, 3))));
+}
+
+#[test]
+fn word_boundary_test() {
+    let spans = |pattern: &str, string: &str| {
+        RARE::new(pattern)
+            .unwrap()
+            .match_all(string)
+            .map(Vec::from_iter)
+            .unwrap_or_default()
+    };
+    assert_eq!(
+        spans("\\bint\\b", "int print int_x int"),
+        [(0, 3), (16, 19)]
+    );
+    assert_eq!(spans("\\Bnt", "print int"), [(3, 5), (7, 9)]);
+    // word characters are those of \w: é is one, unless under the a flag.
+    assert_eq!(spans("\\b\\w+\\b", "wörld"), [(0, 6)]);
+    assert_eq!(spans("(?a)\\b\\w+\\b", "wörld"), [(0, 1), (3, 6)]);
+    assert_eq!(spans("(?a:\\w+)", "wörld"), [(0, 1), (3, 6)]);
+    assert_eq!(spans("x\\b", "x"), [(0, 1)]);
+    assert_eq!(spans("\\B", ""), [(0, 0)]);
+
+    let caps = RARE::new("(\\w+)\\b\\s*=")
+        .unwrap()
+        .captures("let kéy = 1")
+        .unwrap();
+    assert_eq!(caps.get(1), Some((4, 8)));
+    assert!(RARE::new("[\\b]").is_err());
+}
+
+#[test]
+fn anchor_test() {
+    let spans = |pattern: &str, string: &str| {
+        RARE::new(pattern)
+            .unwrap()
+            .match_all(string)
+            .map(Vec::from_iter)
+            .unwrap_or_default()
+    };
+    // the anchors may go anywhere. They just don't match where they can't hold.
+    assert_eq!(spans("a(^|,)b", "ab a,b"), [(3, 6)]);
+    assert_eq!(spans("(^|,)x", "x,x"), [(0, 1), (1, 3)]);
+    assert_eq!(spans("$^", ""), [(0, 0)]);
+    assert_eq!(spans("(?m)$^", "a\n\nb"), [(2, 2)]);
+    assert_eq!(spans("a^b|c$d", "ab cd"), []);
+
+    // \A and \z ignore the multi-line flag, \Z also matches before a \n ending the string.
+    assert_eq!(spans("(?m)\\Ab|^c", "a\nb\nc"), [(4, 5)]);
+    assert_eq!(spans("(?m)b\\z|a$", "a\nb\nb"), [(0, 1), (4, 5)]);
+    assert_eq!(spans("a\\Z", "a\na\n"), [(2, 3)]);
+    assert_eq!(spans("a\\Z", "a\n\n"), []);
+    assert_eq!(spans("a\\z", "a\n"), []);
+    assert!(RareBuilder::new("a\\Z")
+        .full_dfa(true)
+        .build()
+        .unwrap()
+        .is_match("a\n"));
+
+    // lines may end with \r\n, or \r, under the R flag. Never between the \r and the \n.
+    assert_eq!(spans("(?mR)$", "a\r\nb"), [(1, 1), (4, 4)]);
+    assert_eq!(spans("(?mR)^", "a\r\nb\rc"), [(0, 0), (3, 3), (5, 5)]);
+    assert_eq!(spans("(?mR)^b$", "a\r\nb\r\nc"), [(3, 4)]);
+    assert_eq!(spans("(?m)^b$", "a\r\nb\r\nc"), []);
+    let rare = RareBuilder::new("^b$")
+        .multi_line(true)
+        .crlf(true)
+        .build()
+        .unwrap();
+    assert_eq!(
+        rare.match_all("b\r\nb").map(Vec::from_iter),
+        Some(vec![(0, 1), (3, 4)])
+    );
+}
+
+#[test]
+fn unicode_property_test() {
+    let matches = |pattern: &str, string: &str| RARE::new(pattern).unwrap().is_match(string);
+    // general categories, by short and long names, and their groups.
+    assert!(matches("^\\p{Lu}\\p{Ll}+$", "Ωmega"));
+    assert!(matches("^\\p{Uppercase_Letter}\\pL+$", "Ωmega"));
+    assert!(!matches("^\\p{Lu}", "ωmega"));
+    assert!(matches("^\\p{gc=Nd}+$", "٣4५"));
+    // scripts, and binary properties. The names are loosely matched.
+    assert!(matches("^\\p{Greek}+$", "αβγ"));
+    assert!(matches("^\\p{script=grek}+$", "αβγ"));
+    assert!(matches("^\\p{IsCyrillic}+$", "мир"));
+    assert!(!matches("\\p{Greek}", "abc"));
+    assert!(matches("^\\p{emoji presentation}$", "👍"));
+    assert!(matches("^\\p{White-Space}$", "\u{2003}"));
+    assert!(matches("^\\p{Any}$", "\u{10FFFF}"));
+
+    // they compose with brackets and negation.
+    let rare = RARE::new("[\\p{Han}\\p{Hiragana}\\p{Katakana}ー]+").unwrap();
+    assert_eq!(
+        rare.match_all("abc 日本語のテキスト def")
+            .map(Vec::from_iter),
+        Some(vec![(4, 28)])
+    );
+    assert!(matches("^[^\\P{L}\\p{Latin}]+$", "αβγмир"));
+    assert!(!matches("^[^\\P{L}\\p{Latin}]+$", "αβγa"));
+    assert!(matches("(?i)^\\p{Lu}+$", "abc"));
+
+    // a property is a single class state, however many ranges it has.
+    let rare = RARE::new("\\p{L}").unwrap();
+    assert_eq!(rare.nfa.states.len(), 2);
+
+    assert!(RARE::new("\\p{Klingon}").is_err());
+    assert!(RARE::new("\\p{sc=Lu}").is_err());
+}
+
+#[test]
+fn posix_class_test() {
+    let matches = |pattern: &str, string: &str| RARE::new(pattern).unwrap().is_match(string);
+    let rare = RARE::new("[[:alpha:]_][[:alnum:]_]*").unwrap();
+    assert_eq!(
+        rare.match_all("let x_1 = 2e3;").map(Vec::from_iter),
+        Some(vec![(0, 3), (4, 7), (11, 13)])
+    );
+    assert!(matches("^[[:xdigit:]]+$", "09afAF"));
+    assert!(!matches("[[:xdigit:]]", "xyz"));
+    assert!(matches("^[[:space:][:punct:]]+$", " \t!,;"));
+    assert!(matches("^[^[:cntrl:][:blank:]]+$", "a-b"));
+    assert!(!matches("[^[:cntrl:][:blank:]]", "\t \x7F"));
+    // ASCII only, as in the C locale.
+    assert!(!matches("[[:alpha:]]", "é"));
+    assert!(matches("(?i)^[[:upper:]]+$", "Up"));
+    assert!(matches("^[[=a=][.-.]]+$", "a-a"));
+
+    assert!(RARE::new("[[:word:]]").is_err());
+    assert!(RARE::new("[[=e=][.space.]]").is_err());
+}
+
+#[test]
+fn lookaround_test() {
+    let matched = |pattern: &str, string: &str| {
+        RARE::new(pattern)
+            .unwrap()
+            .match_all(string)
+            .map(Vec::from_iter)
+    };
+    // the text looked at isn't part of the match.
+    assert_eq!(matched("foo(?=bar)", "foobaz foobar"), Some(vec![(7, 10)]));
+    assert_eq!(matched("foo(?!bar)", "foobar foobaz"), Some(vec![(7, 10)]));
+    assert_eq!(matched("(?<=\\$)[0-9]+", "3 for $42"), Some(vec![(7, 9)]));
+    // the quotes that aren't escaped.
+    assert_eq!(
+        matched("(?<!\\\\)\"", "say \"hi \\\" there\""),
+        Some(vec![(4, 5), (16, 17)])
+    );
+    // lookbehinds of any length, and anchors inside, checked against the whole string.
+    assert_eq!(matched("(?<=^a+)b", "aab ab cb"), Some(vec![(2, 3)]));
+    assert_eq!(
+        matched("(?<=(?:ab|c)+)x", "ababx cx dx"),
+        Some(vec![(4, 5), (7, 8)])
+    );
+    assert_eq!(
+        matched("\\w+(?=,|$)", "a, bc d"),
+        Some(vec![(0, 1), (6, 7)])
+    );
+    // several at the same position, quantified, or in a loop.
+    assert_eq!(
+        matched("(?=\\d)(?!0)\\d+", "07 70"),
+        Some(vec![(1, 2), (3, 5)])
+    );
+    assert_eq!(
+        matched("(?:(?!ab).)+", "xxabyy"),
+        Some(vec![(0, 2), (3, 6)])
+    );
+    assert_eq!(matched("a(?=b)?", "ab ac"), Some(vec![(0, 1), (3, 4)]));
+    // any number of them, optional ones too, without a path through each subset of them.
+    assert!(RARE::new(&"(?=a)".repeat(100)).unwrap().is_match("a"));
+    let rare = RARE::new(&("(?:(?=a)|(?=.))".repeat(20) + "a")).unwrap();
+    assert_eq!(
+        rare.match_all("aba").map(Vec::from_iter),
+        Some(vec![(0, 1), (2, 3)])
+    );
+    assert!(!rare.is_match("bbb"));
+    // flags apply inside.
+    assert_eq!(matched("x(?=(?i)Y)", "xy xY"), Some(vec![(0, 1), (3, 4)]));
+    assert_eq!(matched("(?i)x(?=y)", "XY"), Some(vec![(0, 1)]));
+
+    // the same matches with the other match kind, the reverse search, and captures.
+    let rare = RareBuilder::new("(\\w+)(?=:)|(?<=:)(\\w+)")
+        .match_kind(MatchKind::LeftmostFirst)
+        .build()
+        .unwrap();
+    let caps: Vec<_> = rare
+        .captures_iter("key:value k:v")
+        .map(|caps| (caps.get(1), caps.get(2)))
+        .collect();
+    assert_eq!(
+        caps,
+        [
+            (Some((0, 3)), None),
+            (None, Some((4, 9))),
+            (Some((10, 11)), None),
+            (None, Some((12, 13)))
+        ]
+    );
+    let rare = RARE::new("(?<!a)b*(?!c)").unwrap();
+    for string in ["abbbc", "bbb abbc", "cbcb"] {
+        let found: Vec<_> = rare
+            .captures_iter(string)
+            .map(|caps| caps.get(0).unwrap())
+            .collect();
+        assert_eq!(rare.match_all(string).map(Vec::from_iter), Some(found));
+    }
+    // each forward search reads the rest of the string, so the reverse search takes over.
+    let rare = RARE::new("(?<!x)a|a[^x]*b").unwrap();
+    let string = "xa".to_string() + &"a".repeat(2000);
+    let mut found = Vec::new();
+    rare.write_match_all(&string, &mut found);
+    assert_eq!(found, (2..2002).map(|i| (i, i + 1)).collect::<Vec<_>>());
+
+    // the DFAs can't tell whether a lookaround holds.
+    let rare = RareBuilder::new("a(?=b)").full_dfa(true).build().unwrap();
+    assert!(rare.dfa_size().is_none());
+    assert!(rare.is_match("ab") && !rare.is_match("ac"));
+}
+
+#[test]
+fn lookaround_error_test() {
+    let error = |pattern: &str| RARE::new(pattern).err().unwrap_or_default();
+    assert!(error("(?=a(?!b))").contains("nested"));
+    assert!(error("(?<=(a))b").contains("capture groups"));
+    assert!(error("(?!(?<x>a))").contains("capture groups"));
+    for bad in ["(?=a", "(?P<=a)", "(?<>a)", "(?<a)", "(?<=a))"] {
+        assert!(RARE::new(bad).is_err(), "{bad} should not compile");
+    }
+    // an empty lookaround always holds, or never does.
+    assert!(RARE::new("(?=)a").unwrap().is_match("a"));
+    assert!(!RARE::new("(?!)a").unwrap().is_match("a"));
+}
+
+#[test]
+fn escape_test() {
+    let rare = RARE::new("\\d+(\\.\\d+)?\\s*\\w+").unwrap();
+    let matched = |string| rare.match_all(string).map(Vec::from_iter);
+    assert_eq!(matched("3.25 kg, ٤٢ كغ"), Some(vec![(0, 7), (9, 18)]));
+    assert_eq!(matched("3. kg"), None);
+
+    let rare = RARE::new("[^\\w\\s]\\x{263A}\\t\\x2A").unwrap();
+    assert!(rare.is_match("é!\u{263A}\t*"));
+    assert!(!rare.is_match("é_\u{263A}\t*"));
+
+    // \W and \D take every character outside the class, including the multibyte ones.
+    let rare = RARE::new("^\\W\\D$").unwrap();
+    assert!(rare.is_match("€\u{10FFFF}"));
+    assert!(!rare.is_match("€٣"));
+
+    assert!(RARE::new("\\p").is_err());
+    assert!(RARE::new("(a)\\1").is_err());
+}
+
+/// The span of each capture group of a match, group 0 first.
+#[cfg(test)]
+type Groups = Vec<Option<(usize, usize)>>;
+
+/// A test of the AT&T corpus under testdata/fowler: a tab-separated line of flags, pattern, string
+/// and expected match. Only the tests with the E flag, for extended regular expressions, are read.
+/// The lines commented out with # are skipped, and the braces around a block of tests whose
+/// features may be unsupported are ignored. A pattern written SAME is the one of the test before.
+///
+/// * `line`: the line number, for the failure messages.
+/// * `pattern`:
+/// * `string`:
+/// * `case_insensitive`: the i flag.
+/// * `groups`: how many groups are checked, set by a digit flag. All of them by default.
+/// * `expected`: the span of each group, `None` if the string doesn't match, or the error code if
+///   the pattern doesn't compile.
+#[cfg(test)]
+struct FowlerTest {
+    line: usize,
+    pattern: String,
+    string: String,
+    case_insensitive: bool,
+    groups: Option<usize>,
+    expected: Result<Option<Groups>, String>,
+}
+
+/// Parses the tests of a corpus file.
+///
+/// * `data`: the content of the file.
+#[cfg(test)]
+fn fowler_tests(data: &str) -> Vec<FowlerTest> {
+    // with the $ flag, the pattern and the string are written with C escapes.
+    fn unescape(field: &str) -> String {
+        let mut ret = String::new();
+        let mut chars = field.chars();
+        while let Some(c) = chars.next() {
+            ret.push(match (c, c == '\\') {
+                (_, false) => c,
+                (_, true) => match chars.next() {
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some('x') => {
+                        let hex: String = chars.by_ref().take(2).collect();
+                        char::from(u8::from_str_radix(&hex, 16).unwrap())
+                    }
+                    Some(c) => c,
+                    None => '\\',
+                },
+            });
+        }
+        ret
+    }
+    fn span(group: &str) -> Option<(usize, usize)> {
+        let (start, end) = group.split_once(',')?;
+        Some((start.parse().ok()?, end.parse().ok()?))
+    }
+
+    let mut ret: Vec<FowlerTest> = Vec::new();
+    for (idx, line) in data.lines().enumerate() {
+        let fields: Vec<&str> = line.split('\t').filter(|field| !field.is_empty()).collect();
+        let [flags, pattern, string, expected, ..] = fields[..] else {
+            continue;
+        };
+        if flags.starts_with('#') || !flags.contains('E') {
+            continue;
+        }
+        let (mut pattern, mut string) = (pattern.to_string(), string.replace("NULL", ""));
+        if flags.contains('$') {
+            pattern = unescape(&pattern);
+            string = unescape(&string);
+        }
+        if pattern == "SAME" {
+            pattern = ret.last().unwrap().pattern.clone();
+        }
+        let expected = match expected {
+            "NOMATCH" => Ok(None),
+            groups if groups.starts_with('(') => Ok(Some(
+                groups[1..groups.len() - 1].split(")(").map(span).collect(),
+            )),
+            code => Err(code.to_string()),
+        };
+        ret.push(FowlerTest {
+            line: idx + 1,
+            pattern,
+            string,
+            case_insensitive: flags.contains('i'),
+            groups: flags
+                .chars()
+                .find_map(|c| c.to_digit(10))
+                .map(|d| d as usize),
+            expected,
+        });
+    }
+    ret
+}
+
+/// Runs the tests of a corpus file with the match kind passed in.
+///
+/// * `data`: the content of the file.
+/// * `kind`:
+/// * Return: the patterns that didn't compile, and those whose whole matches are right but whose
+///   capture groups aren't.
+#[cfg(test)]
+fn run_fowler_tests(data: &str, kind: MatchKind) -> (Vec<String>, Vec<String>) {
+    let mut unsupported = Vec::new();
+    let mut wrong_groups = Vec::new();
+    for test in fowler_tests(data) {
+        let regex = RareBuilder::new(&test.pattern)
+            .case_insensitive(test.case_insensitive)
+            .match_kind(kind)
+            .build();
+        let (regex, expected) = match (regex, test.expected) {
+            (Ok(regex), Ok(expected)) => (regex, expected),
+            (Err(_), Err(_)) => continue,
+            (Ok(_), Err(code)) => panic!("line {}: {:?} compiled, {code}", test.line, test.pattern),
+            (Err(_), Ok(_)) => {
+                if !unsupported.contains(&test.pattern) {
+                    unsupported.push(test.pattern);
+                }
+                continue;
+            }
+        };
+
+        let found = regex.captures(&test.string).map(|caps| {
+            (0..=regex.captures_len())
+                .map(|idx| caps.get(idx))
+                .collect::<Vec<_>>()
+        });
+        let whole = |groups: &Option<Groups>| groups.as_ref().map(|groups| groups[0]);
+        let at = format!(
+            "line {}: {:?} over {:?}",
+            test.line, test.pattern, test.string
+        );
+        assert_eq!(whole(&found), whole(&expected), "{at}");
+        let first = regex
+            .match_all(&test.string)
+            .and_then(|matches| matches.front().copied());
+        assert_eq!(first, whole(&expected).flatten(), "{at}");
+
+        // the groups past the ones listed are unset.
+        if let (Some(mut expected), Some(mut found)) = (expected, found) {
+            found.truncate(test.groups.unwrap_or(found.len()));
+            expected.resize(found.len(), None);
+            if expected != found && !wrong_groups.contains(&test.pattern) {
+                wrong_groups.push(test.pattern);
+            }
+        }
+    }
+    (unsupported, wrong_groups)
+}
+
+/// The AT&T corpus, with the POSIX matches it lists. Every whole match and every capture group is
+/// the POSIX one.
+#[test]
+fn fowler_leftmost_longest_test() {
+    let basic = include_str!("../../testdata/fowler/basic.dat");
+    let (unsupported, wrong_groups) = run_fowler_tests(basic, MatchKind::LeftmostLongest);
+    assert!(unsupported.is_empty(), "{unsupported:?}");
+    assert!(wrong_groups.is_empty(), "{wrong_groups:?}");
+
+    let null_subexpressions = include_str!("../../testdata/fowler/nullsubexpr.dat");
+    let (unsupported, wrong_groups) =
+        run_fowler_tests(null_subexpressions, MatchKind::LeftmostLongest);
+    assert!(unsupported.is_empty(), "{unsupported:?}");
+    assert!(wrong_groups.is_empty(), "{wrong_groups:?}");
+}
+
+/// The same corpus, with the matches Perl finds, as generated by scripts/fowler_leftmost_first.pl.
+/// Every whole match is the Perl one. The capture groups too, but for one rule: once a repeated
+/// group has matched all it could, Perl takes one more iteration matching the empty string, so
+/// "(a*)*" over "aa" gives (2,2) for the group, where it gives (0,2) here, like RE2 does.
+#[test]
+fn fowler_leftmost_first_test() {
+    let basic = include_str!("../../testdata/fowler/basic.leftmost_first.dat");
+    let (unsupported, wrong_groups) = run_fowler_tests(basic, MatchKind::LeftmostFirst);
+    assert!(unsupported.is_empty(), "{unsupported:?}");
+    assert!(wrong_groups.is_empty(), "{wrong_groups:?}");
+
+    let null_subexpressions = include_str!("../../testdata/fowler/nullsubexpr.leftmost_first.dat");
+    let (unsupported, wrong_groups) =
+        run_fowler_tests(null_subexpressions, MatchKind::LeftmostFirst);
+    assert!(unsupported.is_empty(), "{unsupported:?}");
+    assert_eq!(
+        wrong_groups,
+        [
+            "(a*)*",
+            "(a*)+",
+            "([a]*)*",
+            "([a]*)+",
+            "([^b]*)*",
+            "([ab]*)*",
+            "([^a]*)*",
+            "([^ab]*)*",
+            "(a*)*(x)",
+            "(a*)+(x)"
+        ]
+    );
+}